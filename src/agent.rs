@@ -1,19 +1,93 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, atomic::AtomicBool},
+    time::Duration,
+};
+
 use crossterm::event::{Event, KeyCode};
 
 use crate::{
-    game::{Game, GameConfig, Player},
+    error::Connect4Error,
+    game::{Game, GameConfig, Player, column_from_label, column_label},
+    mcts_agent::MctsAgent,
     minimax_agent::MinimaxAgent,
+    negamax_agent::NegamaxAgent,
+    network::NetworkAgent,
+    policy_agent::PolicyAgent,
     rl_agent::RLAgent,
+    rules_agent::RulesAgent,
 };
 
 /// Agent trait for making moves in a game.
-pub trait Agent {
+pub trait Agent: Send {
     /// Get an action based on the current game state and optional event (for input).
     fn get_action(&mut self, board: &Game, event: Option<Event>) -> Option<usize>;
 
     /// Gets the type of the agent.
     fn get_type(&self) -> String;
 
+    /// Score of the last chosen move from the mover's perspective (search agents only).
+    fn last_evaluation(&self) -> Option<i32> {
+        None
+    }
+
+    /// Line of play the last search expects, starting with the move it chose (search agents
+    /// that track a principal variation only).
+    fn expected_line(&self) -> Option<Vec<usize>> {
+        None
+    }
+
+    /// Current search depth, for agents with an adjustable strength.
+    fn search_depth(&self) -> Option<usize> {
+        None
+    }
+
+    /// Change the search depth in place (no-op for agents without one).
+    fn set_search_depth(&mut self, _depth: usize) {}
+
+    /// Limit how many moves are searched below the root (no-op for agents without a search).
+    fn set_branch_cap(&mut self, _cap: Option<usize>) {}
+
+    /// Flag the search polls to give up early and return no move, set by the app on reset or
+    /// quit (no-op for agents without a search loop).
+    fn set_cancel_token(&mut self, _token: Arc<AtomicBool>) {}
+
+    /// Number of obstacles on the boards the agent plays, for agents keeping a table per
+    /// variant of the rules (no-op for the others).
+    fn set_obstacles(&mut self, _count: usize) {}
+
+    /// Whether the position is one the agent has values for (table-based agents only).
+    fn knows_state(&self, _board: &Game) -> Option<bool> {
+        None
+    }
+
+    /// Score the agent gives each valid column in the position, higher is better for the side
+    /// to move (agents that rank every column only).
+    fn evaluate_columns(&self, _board: &Game) -> Vec<(usize, i32)> {
+        Vec::new()
+    }
+
+    /// Lines describing how the agent weighs its moves in the position, for `--explain`.
+    fn explain(&self, _board: &Game) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Told the final position once a game ends, whoever made the last move.
+    fn game_over(&mut self, _board: &Game) {}
+
+    /// Whether the agent's move is still on its way from outside the app, such as from a
+    /// remote player, so having none yet is no forfeit (false by default).
+    fn awaiting_move(&self) -> bool {
+        false
+    }
+
+    /// Problem the agent ran into besides playing, such as a table that failed to load or
+    /// save. Taken so each one is shown once (None by default).
+    fn take_warning(&mut self) -> Option<String> {
+        None
+    }
+
     /// Check if the agent is human or not.
     fn is_human(&self) -> bool;
 
@@ -21,6 +95,14 @@ pub trait Agent {
     fn learn(&mut self, board: &Game, player: Player);
 }
 
+/// Opening rule shared by the heuristic agents: on an empty board always play the center column,
+/// rather than relying on a flat early evaluation to find it. None when an obstacle has closed
+/// the center column, leaving the choice to the search.
+pub fn center_opening(board: &Game) -> Option<usize> {
+    let center = board.config().cols / 2;
+    (board.plies() == 0 && board.valid_moves().contains(&center)).then_some(center)
+}
+
 /// Different agent types
 #[derive(Debug, Clone, PartialEq)]
 pub enum Agents {
@@ -28,28 +110,36 @@ pub enum Agents {
     Random,
     Greedy,
     Minimax(usize),
+    // Time budget in milliseconds
+    MinimaxTimed(u64),
     RL(f64, bool),
+    // Softmax temperature and whether it learns
+    RLSoftmax(f64, bool),
+    // Plays the policy exported from the Q-table
+    Policy,
+    Rules,
+    // Monte Carlo Tree Search with this many iterations per move
+    Mcts(usize),
+    // Negamax search to this depth, tracking the principal variation
+    Negamax(usize),
+    // Player on another machine, only set up by hosting with --serve
+    Network,
 }
 
 impl Agents {
+    // Depth limits for time budgeted minimax
+    const TIMED_MAX_DEPTH: usize = 12;
+    const TIMED_MIN_DEPTH: usize = 3;
+
+    /// Build an agent from its name in `agent_names()`
     pub fn create_agent(
         agent_type: &str,
         agent_color: Player,
         game_config: GameConfig,
-    ) -> Box<dyn Agent> {
-        match agent_type {
-            "Human" => Box::new(HumanAgent),
-            "Random" => Box::new(RandomAgent),
-            "Greedy" => Box::new(GreedyAgent),
-            "Minimax (1)" => Box::new(MinimaxAgent { max_depth: 1 }),
-            "Minimax (3)" => Box::new(MinimaxAgent { max_depth: 3 }),
-            "Minimax (5)" => Box::new(MinimaxAgent { max_depth: 5 }),
-            "Minimax (7)" => Box::new(MinimaxAgent { max_depth: 7 }),
-            "Minimax (9)" => Box::new(MinimaxAgent { max_depth: 9 }),
-            "RL (0.2)" => Box::new(RLAgent::new(0.2, false, agent_color, game_config)),
-            "RL (Learning)" => Box::new(RLAgent::new(0.4, true, agent_color, game_config)),
-            _ => panic!("Invalid agent type"),
-        }
+    ) -> Result<Box<dyn Agent>, Connect4Error> {
+        Ok(agent_type
+            .parse::<Self>()?
+            .into_agent(agent_color, game_config))
     }
 
     pub fn agent_types() -> Vec<Self> {
@@ -62,8 +152,16 @@ impl Agents {
             Self::Minimax(5),
             Self::Minimax(7),
             Self::Minimax(9),
+            Self::MinimaxTimed(500),
             Self::RL(0.2, false),
             Self::RL(0.4, true),
+            Self::RLSoftmax(0.5, true),
+            Self::Policy,
+            Self::Rules,
+            Self::Mcts(1000),
+            Self::Mcts(10000),
+            Self::Negamax(5),
+            Self::Negamax(7),
         ]
     }
 
@@ -72,29 +170,87 @@ impl Agents {
             Self::Human => Box::new(HumanAgent),
             Self::Random => Box::new(RandomAgent),
             Self::Greedy => Box::new(GreedyAgent),
-            Self::Minimax(depth) => Box::new(MinimaxAgent { max_depth: depth }),
+            Self::Minimax(depth) => Box::new(MinimaxAgent::new(depth)),
+            Self::MinimaxTimed(millis) => Box::new(MinimaxAgent::with_time_budget(
+                Self::TIMED_MAX_DEPTH,
+                Self::TIMED_MIN_DEPTH,
+                Duration::from_millis(millis),
+            )),
             Self::RL(learning_rate, is_learning) => Box::new(RLAgent::new(
                 learning_rate,
                 is_learning,
+                RLAgent::DEFAULT_GAMMA,
+                RLAgent::full_history(&game_config),
+                agent_color,
+                game_config,
+            )),
+            Self::RLSoftmax(temperature, is_learning) => Box::new(RLAgent::with_softmax(
+                temperature,
+                is_learning,
                 agent_color,
                 game_config,
             )),
+            Self::Policy => Box::new(PolicyAgent::new(agent_color, game_config)),
+            Self::Rules => Box::new(RulesAgent::new()),
+            Self::Mcts(iterations) => Box::new(MctsAgent::new(iterations)),
+            Self::Negamax(depth) => Box::new(NegamaxAgent::new(depth)),
+            // The connection is handed over by the app, without it the agent never moves
+            Self::Network => Box::new(NetworkAgent::new(None)),
         }
     }
 
+    /// Display names in the same order as `agent_types()`
     pub fn agent_names() -> Vec<String> {
-        vec![
-            "Human".to_string(),
-            "Random".to_string(),
-            "Greedy".to_string(),
-            "Minimax (1)".to_string(),
-            "Minimax (3)".to_string(),
-            "Minimax (5)".to_string(),
-            "Minimax (7)".to_string(),
-            "Minimax (9)".to_string(),
-            "Q-table RL (Trained) (0.2)".to_string(),
-            "Q-table RL (Learning) (0.4)".to_string(),
-        ]
+        Self::agent_types()
+            .iter()
+            .map(|agent| agent.to_string())
+            .collect()
+    }
+}
+
+impl fmt::Display for Agents {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Agents::Human => write!(f, "Human"),
+            Agents::Random => write!(f, "Random"),
+            Agents::Greedy => write!(f, "Greedy"),
+            Agents::Minimax(depth) => write!(f, "Minimax ({})", depth),
+            Agents::MinimaxTimed(millis) => write!(
+                f,
+                "Minimax ({}ms, at least depth {})",
+                millis,
+                Self::TIMED_MIN_DEPTH
+            ),
+            Agents::RL(learning_rate, false) => {
+                write!(f, "Q-table RL (Trained) ({})", learning_rate)
+            }
+            Agents::RL(learning_rate, true) => {
+                write!(f, "Q-table RL (Learning) ({})", learning_rate)
+            }
+            Agents::RLSoftmax(temperature, false) => {
+                write!(f, "Q-table RL (Softmax, T={})", temperature)
+            }
+            Agents::RLSoftmax(temperature, true) => {
+                write!(f, "Q-table RL (Softmax, T={}, learning)", temperature)
+            }
+            Agents::Policy => write!(f, "Q-table RL (Policy only)"),
+            Agents::Rules => write!(f, "Rules-based (Standard board)"),
+            Agents::Mcts(iterations) => write!(f, "MCTS ({} iterations)", iterations),
+            Agents::Negamax(depth) => write!(f, "Negamax ({})", depth),
+            Agents::Network => write!(f, "Network player"),
+        }
+    }
+}
+
+// The agent of a name in `agent_names()`, the inverse of Display
+impl FromStr for Agents {
+    type Err = Connect4Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::agent_types()
+            .into_iter()
+            .find(|agent| agent.to_string() == name)
+            .ok_or_else(|| Connect4Error::UnknownAgent(name.to_string()))
     }
 }
 
@@ -102,21 +258,18 @@ impl Agents {
 pub struct HumanAgent;
 
 impl Agent for HumanAgent {
-    fn get_action(&mut self, _board: &Game, event: Option<Event>) -> Option<usize> {
+    fn get_action(&mut self, board: &Game, event: Option<Event>) -> Option<usize> {
         // We will try to get valid column
-        match event {
+        let label = match event {
             Some(Event::Key(key)) => match key.code {
-                KeyCode::Char('1') => return Some(0),
-                KeyCode::Char('2') => return Some(1),
-                KeyCode::Char('3') => return Some(2),
-                KeyCode::Char('4') => return Some(3),
-                KeyCode::Char('5') => return Some(4),
-                KeyCode::Char('6') => return Some(5),
-                KeyCode::Char('7') => return Some(6),
-                _ => None,
+                // Keys match the 1-indexed board header, 0 is the tenth column
+                KeyCode::Char('0') => 10,
+                KeyCode::Char(key @ '1'..='9') => key.to_digit(10)? as usize,
+                _ => return None,
             },
-            _ => None,
-        }
+            _ => return None,
+        };
+        column_from_label(label).filter(|&col| col < board.config().cols)
     }
 
     fn get_type(&self) -> String {
@@ -142,9 +295,14 @@ impl Agent for RandomAgent {
         use rand::Rng;
         let mut rng = rand::rng();
 
+        let valid_moves = board.valid_moves();
+        if valid_moves.is_empty() {
+            return None;
+        }
+
         // Select a random valid move
-        let random_index = rng.random_range(0..board.valid_moves().len());
-        Some(board.valid_moves()[random_index])
+        let random_index = rng.random_range(0..valid_moves.len());
+        Some(valid_moves[random_index])
     }
 
     fn get_type(&self) -> String {
@@ -164,13 +322,13 @@ impl Agent for RandomAgent {
 pub struct GreedyAgent;
 
 impl GreedyAgent {
+    const TRAP_PENALTY: i32 = 100;
+
     /// Count adjacent tiles of the same color after placing in a column
     fn evaluate_move(&self, board: &Game, col: usize) -> i32 {
-        // Clone board and make move
-        let mut board_copy = board.clone();
-        if board_copy.place(col).is_none() {
+        let Some(board_copy) = board.with_move(col) else {
             return -1; // Invalid move
-        }
+        };
 
         let player = board.current_player();
         let mut score = 0;
@@ -178,51 +336,37 @@ impl GreedyAgent {
         // Check entire board for clusters
         for row in 0..board.config().rows {
             for col in 0..board.config().cols {
-                if let Some(piece) = board_copy.get_cell(row, col) {
-                    if piece == player {
-                        // Add points for each neighbor of same color
-                        // Check 8 directions: horizontal, vertical, and two diagonals
-                        let directions = [
-                            (-1, -1),
-                            (-1, 0),
-                            (-1, 1),
-                            (0, -1),
-                            (0, 1),
-                            (1, -1),
-                            (1, 0),
-                            (1, 1),
-                        ];
-
-                        for (dr, dc) in directions.iter() {
-                            let new_row = row as i32 + dr;
-                            let new_col = col as i32 + dc;
-
-                            // Check bounds
-                            if new_row >= 0
-                                && new_row < board.config().rows as i32
-                                && new_col >= 0
-                                && new_col < board.config().cols as i32
-                            {
-                                if let Some(neighbor) =
-                                    board_copy.get_cell(new_row as usize, new_col as usize)
-                                {
-                                    if neighbor == player {
-                                        score += 1;
-                                    }
-                                }
-                            }
-                        }
-                    }
+                if board_copy.get_cell(row, col) != Some(player) {
+                    continue;
                 }
+                // Add points for each neighbor of same color, both ways along each line
+                // orientation: horizontal, vertical, and two diagonals
+                let neighbors = Game::LINE_DIRECTIONS
+                    .into_iter()
+                    .flat_map(|(row_dir, col_dir)| [(row_dir, col_dir), (-row_dir, -col_dir)])
+                    .filter(|&direction| {
+                        board_copy.line((row, col), direction, 2).nth(1) == Some(Some(player))
+                    })
+                    .count();
+                score += neighbors as i32;
             }
         }
 
+        // Don't play under a threat
+        if board.enables_opponent_win(col) {
+            score -= Self::TRAP_PENALTY;
+        }
+
         score
     }
 }
 
 impl Agent for GreedyAgent {
     fn get_action(&mut self, board: &Game, _event: Option<Event>) -> Option<usize> {
+        if let Some(col) = center_opening(board) {
+            return Some(col);
+        }
+
         // Get valid moves
         let valid_moves: Vec<usize> = board.valid_moves();
 
@@ -231,7 +375,7 @@ impl Agent for GreedyAgent {
         }
 
         // Find move with highest score
-        let mut best_score = -1;
+        let mut best_score = i32::MIN;
         let mut best_moves = Vec::new();
 
         for &col in &valid_moves {
@@ -260,6 +404,21 @@ impl Agent for GreedyAgent {
         "Greedy".to_string()
     }
 
+    fn evaluate_columns(&self, board: &Game) -> Vec<(usize, i32)> {
+        board
+            .valid_moves()
+            .into_iter()
+            .map(|col| (col, self.evaluate_move(board, col)))
+            .collect()
+    }
+
+    fn explain(&self, board: &Game) -> Vec<String> {
+        self.evaluate_columns(board)
+            .into_iter()
+            .map(|(col, score)| format!("Column {}: cluster score {}", column_label(col), score))
+            .collect()
+    }
+
     fn is_human(&self) -> bool {
         false
     }
@@ -268,3 +427,82 @@ impl Agent for GreedyAgent {
         // No learning for greedy agent
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_agent_name_parses_back_to_its_agent() {
+        let config = GameConfig::default();
+        // Two agents with one name would parse to the first of them
+        for agent in Agents::agent_types() {
+            let name = agent.to_string();
+            assert_eq!(name.parse::<Agents>(), Ok(agent.clone()));
+            assert!(Agents::create_agent(&name, Player::Yellow, config).is_ok());
+        }
+        assert_eq!(
+            "Minimax (4)".parse::<Agents>(),
+            Err(Connect4Error::UnknownAgent("Minimax (4)".to_string()))
+        );
+    }
+
+    #[test]
+    fn searching_agents_open_in_the_center_on_odd_and_even_widths() {
+        // Random and the table agents play whatever they roll or learned
+        let openers: Vec<Agents> = Agents::agent_types()
+            .into_iter()
+            .filter(|agent| {
+                matches!(
+                    agent,
+                    Agents::Greedy
+                        | Agents::Minimax(_)
+                        | Agents::MinimaxTimed(_)
+                        | Agents::Rules
+                        | Agents::Mcts(_)
+                        | Agents::Negamax(_)
+                )
+            })
+            .collect();
+        let configs = [
+            GameConfig::default(),
+            GameConfig::custom(6, 6, 4).unwrap(),
+            GameConfig::custom(4, 5, 3).unwrap(),
+            GameConfig::custom(4, 4, 3).unwrap(),
+        ];
+        for config in configs {
+            let cols = config.cols;
+            let center = [(cols - 1) / 2, cols / 2];
+            for agent in &openers {
+                let col = agent
+                    .clone()
+                    .into_agent(Player::Yellow, config)
+                    .get_action(&Game::with_config(config), None);
+                assert!(
+                    col.is_some_and(|col| center.contains(&col)),
+                    "{} opened {:?} on {} columns",
+                    agent,
+                    col,
+                    cols
+                );
+            }
+        }
+
+        // An obstacle in the top row closes the center column, the opening goes elsewhere
+        let config = GameConfig::default();
+        let board = Game::with_obstacles(config, &[(0, config.cols / 2)]);
+        assert_eq!(center_opening(&board), None);
+        for agent in &openers {
+            let col = agent
+                .clone()
+                .into_agent(Player::Yellow, config)
+                .get_action(&board, None);
+            assert!(
+                col.is_some_and(|col| board.valid_moves().contains(&col)),
+                "{} opened {:?} with the center closed",
+                agent,
+                col
+            );
+        }
+    }
+}
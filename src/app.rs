@@ -1,20 +1,40 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    net::TcpStream,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
 use color_eyre::eyre;
 use crossterm::event::Event;
+use rand::{SeedableRng, rngs::StdRng};
 use ratatui::{
     Frame,
     buffer::Buffer,
-    layout::{Direction, Flex, Layout, Rect},
+    layout::{Alignment, Constraint, Direction, Flex, Layout, Rect},
     style::{Color, Style, Stylize},
     text::Line,
     widgets::{
-        Block, BorderType, Borders, List, ListState, Padding, Paragraph, StatefulWidget, Wrap,
+        Block, BorderType, Borders, Clear, LineGauge, List, ListState, Padding, Paragraph,
+        StatefulWidget, Widget, Wrap,
     },
 };
 
 use crate::{
     RunSpeed,
     agent::{Agent, Agents},
-    game::{Game, GameConfigPreset, GameState, GridWidget, Player},
+    game::{
+        Game, GameConfig, GameConfigPreset, GameState, GridWidget, Handicap, Obstacles, Player,
+        WinCheck, column_label,
+    },
+    network::NetworkAgent,
+    puzzle::Puzzles,
+    records::Records,
 };
 
 pub struct App {
@@ -24,26 +44,180 @@ pub struct App {
     pub yellow_agent_type: Agents,
     pub red_agent_type: Agents,
 
+    pub win_check: WinCheck,
+    /// Columns (0-indexed) forced at the start of every game before the agents take over
+    pub opening: Vec<usize>,
+    /// Moves searched below the root by minimax agents, None for all of them
+    pub branch_cap: Option<usize>,
+    /// Different line length for one player on every preset
+    pub handicap: Option<Handicap>,
+    /// Blocked cells placed at random on every new board
+    pub obstacles: Option<Obstacles>,
+    /// Pause after a reset before AI moves are played automatically
+    pub startup_delay: Duration,
+    reset_at: Instant,
+    /// Start a new game automatically a moment after an AI vs AI game ends
+    pub auto_reset: bool,
+    /// Swap who moves first on every reset
+    pub alternate_starter: bool,
+    starter: Player,
+    ended_at: Option<Instant>,
+    /// Agents to restore once attract mode ends, Some while the demo is running
+    attract: Option<(Agents, Agents)>,
+    /// Pop Out: the human's next column pops their bottom piece instead of dropping one
+    pub pop_armed: bool,
+    /// Puzzle positions and score, Some while puzzle mode is on
+    pub puzzles: Option<Puzzles>,
+    /// Agents to restore once puzzle mode ends
+    before_puzzles: Option<(Agents, Agents)>,
+    last_input: Instant,
+    /// Wall clock time each player has spent on their turns this game
+    yellow_time: Duration,
+    red_time: Duration,
+    turn_started: Instant,
+    /// Moves each player has made this game, each one earns the clock increment
+    yellow_moves: u32,
+    red_moves: u32,
+    /// Lose on running out of time, with the clock set by the time control
+    pub timed: bool,
+    pub time_control: TimeControl,
+
+    /// Connection to the player hosted with `--serve`, handed to every network agent built
+    remote: Option<TcpStream>,
+
+    /// Notice about an unusual event, shown in the info panel until reset
+    pub message: Option<String>,
+    pub session: SessionStats,
+    pub summary_open: bool,
+    /// All-time human records, saved on exit
+    pub records: Records,
+    pub records_open: bool,
+
+    /// Normalized evaluation after each searched move, from Yellow's perspective in [-1, 1]
+    pub eval_log: Vec<f64>,
+    pub show_eval_graph: bool,
+    /// Bar above the board filled by Yellow's share of the latest evaluation
+    pub show_eval_bar: bool,
+    /// Per move of a Q-table agent, whether the position it moved from was already in its table
+    pub coverage: Vec<(Player, bool)>,
+    pub show_coverage: bool,
+    /// Row above the column numbers with the score the agent to move gives each column
+    pub show_heatmap: bool,
+    /// Column scores for the heatmap of every position evaluated, so deep searches only run
+    /// once per position and going back and forth through a game doesn't run them again
+    column_scores: HashMap<ColumnScoresKey, Vec<(usize, i32)>>,
+
+    /// Cancel token handed to the current agents' searches, swapped for a fresh one whenever
+    /// the position they were searching is abandoned
+    search_cancel: Arc<AtomicBool>,
+    /// AI move being searched for on a worker thread, so the loop keeps reading keys meanwhile
+    search: Option<Search>,
+    /// Searched moves to play as soon as they are found rather than only show, the batch step
+    /// queues several
+    queued_moves: usize,
+
+    /// Move the AI to play has already chosen, keyed by the ply count it was chosen at.
+    /// Shown on the board in Manual mode and played as is on the next step
+    preview: Option<(usize, Option<usize>)>,
+
+    /// Piece on its way down to the cell it was dropped into, placed once it lands
+    falling: Option<FallingPiece>,
+    /// Column settling after a pop, its pieces fall the row over one fall interval
+    popped_column: Option<PoppedColumn>,
+    /// Time per row of a falling piece, None drops pieces straight into place
+    pub fall_interval: Option<Duration>,
+
+    /// Screen area the grid was last drawn into, for mapping mouse clicks to columns
+    grid_area: Rect,
+    /// First column shown of a board too wide for the screen
+    pub board_scroll: usize,
+
+    /// Move list being typed into the load position prompt, Some while it is open
+    pub position_prompt: Option<String>,
+    /// Rows, columns and connect length being typed for a custom board, Some while it is open
+    pub size_prompt: Option<String>,
+
+    /// Board placement options for the grid widget
+    pub board_alignment: Alignment,
+    pub board_padding: u16,
+    pub board_title: bool,
+
+    pub fog: bool,
+    pub header_mode: HeaderMode,
+    pub score_mode: ScoreMode,
     pub menu_open: bool,
     pub agent_list: AgentList,
     pub config_list: GameConfigList,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new() -> eyre::Result<Self> {
         let game = Game::new();
         let yellow_agent_type = Agents::Human;
         let red_agent_type = Agents::Minimax(1);
         let yellow_agent =
-            Agents::create_agent(&Agents::agent_names()[0], Player::Yellow, *game.config());
+            Agents::create_agent(&Agents::agent_names()[0], Player::Yellow, *game.config())?;
         let red_agent =
-            Agents::create_agent(&Agents::agent_names()[3], Player::Red, *game.config());
-        App {
+            Agents::create_agent(&Agents::agent_names()[3], Player::Red, *game.config())?;
+        Ok(App {
             game,
             yellow_agent,
             red_agent,
             yellow_agent_type,
             red_agent_type,
+            win_check: WinCheck::default(),
+            opening: Vec::new(),
+            branch_cap: None,
+            handicap: None,
+            obstacles: None,
+            startup_delay: DEFAULT_STARTUP_DELAY,
+            reset_at: Instant::now(),
+            auto_reset: false,
+            alternate_starter: false,
+            starter: Player::Yellow,
+            ended_at: None,
+            attract: None,
+            pop_armed: false,
+            puzzles: None,
+            before_puzzles: None,
+            last_input: Instant::now(),
+            yellow_time: Duration::ZERO,
+            red_time: Duration::ZERO,
+            turn_started: Instant::now(),
+            yellow_moves: 0,
+            red_moves: 0,
+            timed: false,
+            time_control: TimeControl::default(),
+            remote: None,
+            message: None,
+            session: SessionStats::default(),
+            summary_open: false,
+            records: Records::load(&Records::path()),
+            records_open: false,
+            eval_log: Vec::new(),
+            show_eval_graph: false,
+            show_eval_bar: false,
+            coverage: Vec::new(),
+            show_coverage: false,
+            show_heatmap: false,
+            column_scores: HashMap::new(),
+            fog: false,
+            header_mode: HeaderMode::default(),
+            score_mode: ScoreMode::default(),
+            search_cancel: Arc::new(AtomicBool::new(false)),
+            search: None,
+            queued_moves: 0,
+            preview: None,
+            falling: None,
+            popped_column: None,
+            fall_interval: None,
+            grid_area: Rect::default(),
+            board_scroll: 0,
+            position_prompt: None,
+            size_prompt: None,
+            board_alignment: Alignment::Center,
+            board_padding: 0,
+            board_title: false,
             menu_open: false,
             agent_list: AgentList {
                 selected_player: Player::Yellow,
@@ -51,80 +225,1331 @@ impl App {
             },
             config_list: GameConfigList {
                 selected_game: GameConfigPreset::default(),
+                custom: None,
                 state: ListState::default().with_selected(Some(0)),
             },
-        }
+        })
     }
 
     pub fn reset(&mut self) {
-        self.game = Game::with_config(self.config_list.selected_game.into_config());
+        self.cancel_search();
+        self.game = self.new_board();
+        self.game.set_win_check(self.win_check);
+        if self.alternate_starter && self.ended_at.is_some() {
+            self.starter = self.starter.opponent();
+        }
+        self.game.set_starting_player(self.starter);
+        self.eval_log.clear();
+        self.coverage.clear();
+        self.pop_armed = false;
+        self.message = None;
+        self.reset_at = Instant::now();
+        self.ended_at = None;
+        self.yellow_time = Duration::ZERO;
+        self.red_time = Duration::ZERO;
+        self.yellow_moves = 0;
+        self.red_moves = 0;
+        self.preview = None;
+        self.falling = None;
+        self.popped_column = None;
         // Reset agents (may have different config)
-        self.yellow_agent = self
-            .yellow_agent_type
-            .clone()
-            .into_agent(Player::Yellow, self.config_list.selected_game.into_config());
-        self.red_agent = self
-            .red_agent_type
-            .clone()
-            .into_agent(Player::Red, self.config_list.selected_game.into_config());
+        self.yellow_agent = self.build_agent(self.yellow_agent_type.clone(), Player::Yellow);
+        self.red_agent = self.build_agent(self.red_agent_type.clone(), Player::Red);
+        self.show_agent_warnings();
+        match &self.puzzles {
+            Some(puzzles) => {
+                self.game = puzzles.board();
+                self.game.set_win_check(self.win_check);
+            }
+            None => self.play_opening(),
+        }
+        self.turn_started = Instant::now();
+    }
+
+    /// Start play from the position typed into the prompt, with the current agents and config.
+    /// An invalid move list leaves the game as it is and keeps the prompt open with the error
+    pub fn load_position(&mut self) {
+        let notation = self.position_prompt.as_deref().unwrap_or_default();
+        match self.new_board().play_notation(notation) {
+            Ok(game) => {
+                self.position_prompt = None;
+                self.reset();
+                self.game = game;
+                self.game.set_win_check(self.win_check);
+            }
+            Err(err) => self.message = Some(format!("Could not load position: {}", err)),
+        }
+    }
+
+    /// Write the game in progress to the save file, to be resumed with `load_saved_game`
+    pub fn save_game(&mut self) {
+        let path = Game::save_path();
+        self.message = Some(match self.game.save_to_file(&path) {
+            Ok(()) => format!("Game saved to {}", path.display()),
+            Err(err) => format!("Could not save game: {}", err),
+        });
+    }
+
+    /// Resume the game in the save file with the current agents, switching to its config.
+    /// The game in progress is kept if the file can't be read
+    pub fn load_saved_game(&mut self) {
+        let path = Game::save_path();
+        let game = match Game::load_from_file(&path) {
+            Ok(game) => game,
+            Err(err) => {
+                self.message = Some(format!("Could not load saved game: {}", err));
+                return;
+            }
+        };
+        self.menu_open = false;
+        self.select_config(*game.config());
+        self.reset();
+        self.game = game;
+        self.game.set_win_check(self.win_check);
+        self.message = Some(format!("Resumed game from {}", path.display()));
+    }
+
+    // Switch to the given board, as its preset when one matches and as a custom config
+    // otherwise. Takes effect on the next reset
+    fn select_config(&mut self, config: GameConfig) {
+        let board = GameConfig {
+            handicap: None,
+            ..config
+        };
+        let preset = (0..GameConfigPreset::amount_of_presets())
+            .find(|&index| GameConfigPreset::from_index(index).into_config() == board);
+        match preset {
+            Some(index) => {
+                self.config_list.selected_game = GameConfigPreset::from_index(index);
+                self.config_list.custom = None;
+                self.config_list.state.select(Some(index));
+            }
+            None => {
+                self.config_list.custom = Some(board);
+                self.config_list
+                    .state
+                    .select(Some(GameConfigPreset::amount_of_presets()));
+            }
+        }
+        self.handicap = config.handicap;
+    }
+
+    /// Go to the next entry of the config list and start a new game with it, wrapping around.
+    /// The Custom… entry after the presets opens the board size prompt instead
+    pub fn cycle_config(&mut self) {
+        let custom_index = GameConfigPreset::amount_of_presets();
+        let next = match self.config_list.state.selected() {
+            Some(index) if index < custom_index => index + 1,
+            _ => 0,
+        };
+        self.config_list.state.select(Some(next));
+        if next == custom_index {
+            self.size_prompt = Some(String::new());
+            return;
+        }
+        self.config_list.selected_game = GameConfigPreset::from_index(next);
+        self.config_list.custom = None;
+        self.reset();
+    }
+
+    /// Start a new game on the board size typed into the prompt. An invalid size keeps the
+    /// prompt open with the reason
+    pub fn apply_custom_size(&mut self) {
+        let text = self.size_prompt.as_deref().unwrap_or_default();
+        let numbers: Result<Vec<usize>, _> = text
+            .split(|c: char| !c.is_ascii_digit())
+            .filter(|number| !number.is_empty())
+            .map(str::parse)
+            .collect();
+        let config = match numbers.as_deref() {
+            Ok(&[rows, cols, connect_length]) => {
+                GameConfig::custom(rows, cols, connect_length).map_err(|err| err.to_string())
+            }
+            _ => Err("type rows, columns and connect length, e.g. 6 7 4".to_string()),
+        };
+        match config {
+            Ok(config) => {
+                self.size_prompt = None;
+                self.select_config(GameConfig {
+                    handicap: self.handicap,
+                    ..config
+                });
+                self.reset();
+            }
+            Err(err) => self.message = Some(format!("Invalid board size: {}", err)),
+        }
+    }
+
+    /// Close the board size prompt, keeping the config in use
+    pub fn cancel_size_prompt(&mut self) {
+        self.size_prompt = None;
+        let config = self.game_config();
+        self.select_config(config);
+    }
+
+    /// Go back to the agents, config and display toggles of a fresh start, unlike `reset` which
+    /// keeps them. Command line options, the session scoreboard and the records are kept
+    pub fn reset_to_defaults(&mut self) -> eyre::Result<()> {
+        self.cancel_search();
+        let mut defaults = App::new()?;
+        defaults.win_check = self.win_check;
+        defaults.opening = std::mem::take(&mut self.opening);
+        defaults.startup_delay = self.startup_delay;
+        defaults.branch_cap = self.branch_cap;
+        defaults.handicap = self.handicap;
+        defaults.timed = self.timed;
+        defaults.time_control = self.time_control;
+        defaults.obstacles = self.obstacles;
+        defaults.alternate_starter = self.alternate_starter;
+        defaults.board_alignment = self.board_alignment;
+        defaults.board_padding = self.board_padding;
+        defaults.board_title = self.board_title;
+        defaults.session = std::mem::take(&mut self.session);
+        defaults.records = std::mem::take(&mut self.records);
+        if let Some(stream) = self.remote.take() {
+            defaults.set_remote_player(stream);
+        }
+        *self = defaults;
+        self.reset();
+        Ok(())
+    }
+
+    /// Whether the startup pause after the last reset has passed, so AI autoplay may begin
+    pub fn autoplay_ready(&self) -> bool {
+        self.reset_at.elapsed() >= self.startup_delay
+    }
+
+    pub fn attract_active(&self) -> bool {
+        self.attract.is_some()
+    }
+
+    /// Time left before an idle, freshly reset game switches to attract mode,
+    /// None when there is nothing to wait for
+    pub fn time_until_attract(&self) -> Option<Duration> {
+        // The demo would take the game away from the remote player
+        if self.attract.is_some()
+            || self.remote.is_some()
+            || self.summary_open
+            || self.game.plies() > self.opening.len()
+        {
+            return None;
+        }
+        Some(ATTRACT_IDLE.saturating_sub(self.last_input.elapsed()))
+    }
+
+    /// Start the demo, a fixed AI vs AI matchup that replays until a key is pressed
+    pub fn start_attract(&mut self) {
+        self.attract = Some((self.yellow_agent_type.clone(), self.red_agent_type.clone()));
+        self.menu_open = false;
+        self.yellow_agent_type = Agents::Minimax(5);
+        self.red_agent_type = Agents::Rules;
+        self.reset();
+    }
+
+    /// Switch puzzle mode on or off. Both sides are played by the human while it is on, the
+    /// previous matchup comes back afterwards
+    pub fn toggle_puzzles(&mut self) {
+        match self.before_puzzles.take() {
+            Some((yellow_agent_type, red_agent_type)) => {
+                self.puzzles = None;
+                self.yellow_agent_type = yellow_agent_type;
+                self.red_agent_type = red_agent_type;
+            }
+            None => {
+                self.before_puzzles =
+                    Some((self.yellow_agent_type.clone(), self.red_agent_type.clone()));
+                self.puzzles = Some(Puzzles::new());
+                self.yellow_agent_type = Agents::Human;
+                self.red_agent_type = Agents::Human;
+            }
+        }
+        self.menu_open = false;
+        self.reset();
+    }
+
+    /// Go to the next puzzle, or the previous one when going back
+    pub fn cycle_puzzle(&mut self, forward: bool) {
+        let Some(puzzles) = &mut self.puzzles else {
+            return;
+        };
+        if forward {
+            puzzles.next();
+        } else {
+            puzzles.previous();
+        }
+        self.reset();
+    }
+
+    // Score the first move made in a puzzle, before it is placed so the answer is checked
+    // against the puzzle position
+    fn check_puzzle_move(&mut self, col: usize) {
+        let Some(puzzles) = &mut self.puzzles else {
+            return;
+        };
+        if puzzles.outcome.is_some() {
+            return;
+        }
+        let verdict = if puzzles.submit(&self.game, col) {
+            "Correct!"
+        } else {
+            "Not quite"
+        };
+        self.message = Some(format!("{} ({})", verdict, puzzles.solution()));
+    }
+
+    /// Note a key press, which ends attract mode and restores the previous matchup.
+    /// Returns whether the demo was running, in which case the key is used up
+    pub fn register_input(&mut self) -> bool {
+        self.last_input = Instant::now();
+        let Some((yellow_agent_type, red_agent_type)) = self.attract.take() else {
+            return false;
+        };
+        self.yellow_agent_type = yellow_agent_type;
+        self.red_agent_type = red_agent_type;
+        self.reset();
+        true
+    }
+
+    /// Whether a finished game had a human in it, these wait for a key press instead of
+    /// auto-resetting so the result doesn't flash by
+    pub fn awaiting_human_continue(&self) -> bool {
+        *self.game.state() != GameState::InProgress
+            && (self.yellow_agent.is_human() || self.red_agent.is_human())
+    }
+
+    /// Result from the human's point of view, shown over the board until a key is pressed
+    fn game_over_banner(&self) -> Option<Line<'static>> {
+        if !self.awaiting_human_continue() {
+            return None;
+        }
+        let both_human = self.yellow_agent.is_human() && self.red_agent.is_human();
+        Some(match *self.game.state() {
+            GameState::Won(player) if both_human => {
+                Line::from(format!("{} wins!", player).green().bold())
+            }
+            GameState::Won(player) if self.agent(player).is_human() => {
+                Line::from("You win!".green().bold())
+            }
+            GameState::Won(_) => Line::from("You lose".red().bold()),
+            _ => Line::from("Draw".yellow().bold()),
+        })
+    }
+
+    /// Whether an AI vs AI game has been over long enough to start the next one
+    pub fn auto_reset_due(&self) -> bool {
+        (self.auto_reset || self.attract.is_some())
+            && !self.yellow_agent.is_human()
+            && !self.red_agent.is_human()
+            && self
+                .ended_at
+                .is_some_and(|ended_at| ended_at.elapsed() >= AUTO_RESET_DELAY)
+    }
+
+    /// Play the forced opening moves, stopping at the first illegal one
+    fn play_opening(&mut self) {
+        for (index, &col) in self.opening.iter().enumerate() {
+            if *self.game.state() != GameState::InProgress
+                || !self.game.valid_moves().contains(&col)
+            {
+                self.message = Some(format!(
+                    "Opening move {} (column {}) is illegal, stopped there",
+                    index + 1,
+                    column_label(col)
+                ));
+                return;
+            }
+            self.game.place(col);
+        }
     }
 
     pub fn set_agent(&mut self, player: Player, agent: Agents) {
+        self.cancel_search();
+        self.preview = None;
         match player {
             Player::Yellow => {
+                self.yellow_agent = self.build_agent(agent.clone(), Player::Yellow);
                 self.yellow_agent_type = agent;
-                self.yellow_agent = self
-                    .yellow_agent_type
-                    .clone()
-                    .into_agent(Player::Yellow, self.config_list.selected_game.into_config());
             }
             Player::Red => {
+                self.red_agent = self.build_agent(agent.clone(), Player::Red);
                 self.red_agent_type = agent;
-                self.red_agent = self
-                    .red_agent_type
-                    .clone()
-                    .into_agent(Player::Red, self.config_list.selected_game.into_config());
             }
         }
+        self.show_agent_warnings();
+    }
+
+    /// Empty board for the current config, with the session's obstacles placed on it
+    fn new_board(&self) -> Game {
+        let config = self.game_config();
+        let Some(obstacles) = self.obstacles else {
+            return Game::with_config(config);
+        };
+        let cells = match obstacles.seed {
+            Some(seed) => {
+                Game::random_obstacles(config, obstacles.count, &mut StdRng::seed_from_u64(seed))
+            }
+            None => Game::random_obstacles(config, obstacles.count, &mut rand::rng()),
+        };
+        Game::with_obstacles(config, &cells)
+    }
+
+    /// Config of the selected preset or custom board with the session's handicap applied
+    fn game_config(&self) -> GameConfig {
+        GameConfig {
+            handicap: self.handicap,
+            ..self
+                .config_list
+                .custom
+                .unwrap_or_else(|| self.config_list.selected_game.into_config())
+        }
+    }
+
+    /// Agent for the selected config with the session's search settings applied
+    fn build_agent(&self, agent_type: Agents, player: Player) -> Box<dyn Agent> {
+        let mut agent = match (&agent_type, &self.remote) {
+            (Agents::Network, Some(stream)) => Box::new(NetworkAgent::new(stream.try_clone().ok())),
+            _ => agent_type.into_agent(player, self.game_config()),
+        };
+        agent.set_branch_cap(self.branch_cap);
+        agent.set_cancel_token(Arc::clone(&self.search_cancel));
+        agent.set_obstacles(self.obstacles.map_or(0, |obstacles| obstacles.count));
+        agent
+    }
+
+    /// Hand Red to the player on the other end of the connection, from the next reset on
+    pub fn set_remote_player(&mut self, stream: TcpStream) {
+        self.remote = Some(stream);
+        self.red_agent_type = Agents::Network;
+    }
+
+    /// Whether the move is up to the remote player, played as soon as it arrives at any speed
+    pub fn remote_to_move(&self) -> bool {
+        *self.game.state() == GameState::InProgress
+            && self.falling.is_none()
+            && match self.game.current_player() {
+                Player::Yellow => self.yellow_agent_type == Agents::Network,
+                Player::Red => self.red_agent_type == Agents::Network,
+            }
+    }
+
+    /// Time until the connection is checked again for the remote player's move, for waking
+    /// the loop
+    pub fn time_until_remote_poll(&self) -> Option<Duration> {
+        self.remote_to_move().then_some(REMOTE_POLL)
+    }
+
+    /// Human (Yellow) against the trained, non-learning RL agent (Red) on the standard board,
+    /// so the Q-table can be tried out without going through the menus. Returns the speed to
+    /// play at, Manual so the agent's moves are stepped through
+    pub fn practice_vs_rl(&mut self) -> RunSpeed {
+        self.menu_open = false;
+        self.config_list.selected_game = GameConfigPreset::Standard;
+        self.config_list.custom = None;
+        self.config_list.state.select_first();
+        self.yellow_agent_type = Agents::Human;
+        self.red_agent_type = Agents::RL(0.2, false);
+        self.reset();
+        RunSpeed::Manual
+    }
+
+    /// Cycle a player's agent through `Agents::agent_types()`, wrapping around at either end
+    pub fn cycle_agent(&mut self, player: Player, forward: bool) {
+        let agent_types = Agents::agent_types();
+        let current = match player {
+            Player::Yellow => &self.yellow_agent_type,
+            Player::Red => &self.red_agent_type,
+        };
+        let index = agent_types
+            .iter()
+            .position(|agent| agent == current)
+            .unwrap_or(0);
+        let next = if forward {
+            (index + 1) % agent_types.len()
+        } else {
+            (index + agent_types.len() - 1) % agent_types.len()
+        };
+        self.set_agent(player, agent_types[next].clone());
+    }
+
+    /// In fog mode the AI's pieces are hidden, only when playing human vs AI
+    fn fog_hidden_player(&self) -> Option<Player> {
+        if !self.fog {
+            return None;
+        }
+        match (self.yellow_agent.is_human(), self.red_agent.is_human()) {
+            (true, false) => Some(Player::Red),
+            (false, true) => Some(Player::Yellow),
+            _ => None,
+        }
+    }
+
+    /// Player whose agent the depth slider adjusts: the current player if it has a
+    /// search depth, otherwise the opponent
+    fn depth_slider_player(&self) -> Option<Player> {
+        let current = self.game.current_player();
+        [current, current.opponent()]
+            .into_iter()
+            .find(|&player| self.agent(player).search_depth().is_some())
+    }
+
+    /// Adjust the search depth of the slider's agent in place, keeping the
+    /// agent type in sync so a reset keeps the new depth
+    pub fn adjust_depth(&mut self, delta: isize) {
+        let Some(player) = self.depth_slider_player() else {
+            return;
+        };
+        self.cancel_search();
+        let (agent, agent_type) = match player {
+            Player::Yellow => (&mut self.yellow_agent, &mut self.yellow_agent_type),
+            Player::Red => (&mut self.red_agent, &mut self.red_agent_type),
+        };
+        let Some(depth) = agent.search_depth() else {
+            return;
+        };
+        let depth = depth
+            .saturating_add_signed(delta)
+            .clamp(1, MAX_SLIDER_DEPTH);
+        agent.set_search_depth(depth);
+        self.preview = None;
+        match agent_type {
+            Agents::Minimax(_) => *agent_type = Agents::Minimax(depth),
+            Agents::Negamax(_) => *agent_type = Agents::Negamax(depth),
+            _ => {}
+        }
+    }
+
+    /// Preset name, board size and connect length of the current game
+    pub fn config_description(&self) -> String {
+        match self.config_list.custom {
+            Some(_) => format!("Custom {}", self.game.config()),
+            None => format!("{} {}", self.config_list.selected_game, self.game.config()),
+        }
+    }
+
+    pub fn current_player_is_human(&self) -> bool {
+        self.agent(self.game.current_player()).is_human()
     }
 
-    fn current_player_is_human(&self) -> bool {
-        match self.game.current_player() {
-            crate::game::Player::Yellow => self.yellow_agent.is_human(),
-            crate::game::Player::Red => self.red_agent.is_human(),
+    /// Speed to run at for the player to move. A human only moves on input, timed speeds would
+    /// keep stepping through their turn, so they drop to Manual
+    pub fn speed_for_turn(&self, speed: RunSpeed) -> RunSpeed {
+        if *self.game.state() == GameState::InProgress && self.current_player_is_human() {
+            RunSpeed::Manual
+        } else {
+            speed
         }
     }
 
     pub fn step(&mut self, event: Option<Event>) -> eyre::Result<()> {
-        let event = if self.current_player_is_human() {
-            event
+        // The next move waits for the last one to land
+        if *self.game.state() != GameState::InProgress || self.falling.is_some() {
+            return Ok(());
+        }
+
+        let player = self.game.current_player();
+        let is_human = self.current_player_is_human();
+
+        let preview = self.preview.take();
+        // AI moves are searched for on the worker thread and played once found, unless the
+        // preview already has one
+        if self.ai_to_move() {
+            match preview {
+                Some((plies, action)) if plies == self.game.plies() => {
+                    self.play_action(player, false, action)
+                }
+                _ => {
+                    self.queued_moves += 1;
+                    self.start_search();
+                }
+            }
+            return Ok(());
+        }
+
+        // Humans and the remote player answer right away
+        let event = if is_human { event } else { None };
+        let agent = match player {
+            Player::Yellow => &mut self.yellow_agent,
+            Player::Red => &mut self.red_agent,
+        };
+        let action = agent.get_action(&self.game, event);
+        self.play_action(player, is_human, action);
+        Ok(())
+    }
+
+    /// Whether the player to move is an AI whose move has to be searched for, rather than a
+    /// human or the remote player
+    fn ai_to_move(&self) -> bool {
+        *self.game.state() == GameState::InProgress
+            && self.falling.is_none()
+            && !self.current_player_is_human()
+            && !self.remote_to_move()
+    }
+
+    /// Whether an AI move is being searched for on the worker thread
+    pub fn searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    // Hand the AI to move to a worker thread searching the current position, unless a search
+    // is already running. A stand-in answers for the agent until it comes back
+    fn start_search(&mut self) {
+        if self.search.is_some() || !self.ai_to_move() {
+            return;
+        }
+        let player = self.game.current_player();
+        let slot = match player {
+            Player::Yellow => &mut self.yellow_agent,
+            Player::Red => &mut self.red_agent,
+        };
+        let stand_in = Box::new(Searching {
+            agent_type: slot.get_type(),
+            search_depth: slot.search_depth(),
+        });
+        let mut agent = std::mem::replace(slot, stand_in);
+        let board = self.game.clone();
+        let handle = std::thread::spawn(move || {
+            let action = agent.get_action(&board, None);
+            (agent, action)
+        });
+        self.search = Some(Search {
+            player,
+            plies: self.game.plies(),
+            handle,
+        });
+    }
+
+    // Wait for the running search and put its agent back, returning the ply count of the
+    // position it searched and the move it found
+    fn join_search(&mut self) -> Option<(usize, Option<usize>)> {
+        let search = self.search.take()?;
+        let (agent, action) = search
+            .handle
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        match search.player {
+            Player::Yellow => self.yellow_agent = agent,
+            Player::Red => self.red_agent = agent,
+        }
+        Some((search.plies, action))
+    }
+
+    // Play the move of a finished search if one was queued, otherwise keep it as the preview
+    fn finish_search(&mut self) {
+        let Some((plies, action)) = self.join_search() else {
+            return;
+        };
+        if plies != self.game.plies() {
+            return;
+        }
+        if self.queued_moves > 0 {
+            self.queued_moves -= 1;
+            self.play_action(self.game.current_player(), false, action);
         } else {
-            None
+            self.preview = Some((plies, action));
+        }
+    }
+
+    // Search for the next queued move once the last one has landed, dropping the queue when
+    // the game ends or it is no longer an AI's turn
+    fn continue_queue(&mut self) {
+        if self.queued_moves == 0 || self.search.is_some() || self.falling.is_some() {
+            return;
+        }
+        if self.ai_to_move() {
+            self.start_search();
+        } else {
+            self.queued_moves = 0;
+        }
+    }
+
+    /// Take in the result of a search that has finished and go on with the queued moves,
+    /// called on every pass of the loop
+    pub fn poll_search(&mut self) {
+        if self
+            .search
+            .as_ref()
+            .is_some_and(|search| search.handle.is_finished())
+        {
+            self.finish_search();
+        }
+        self.continue_queue();
+    }
+
+    /// Block until the running search and the queued moves after it are done
+    #[cfg(test)]
+    pub fn wait_for_search(&mut self) {
+        while self.search.is_some() {
+            self.finish_search();
+            self.continue_queue();
+        }
+    }
+
+    /// Time until a running search is checked for its move, for waking the loop
+    pub fn time_until_search_poll(&self) -> Option<Duration> {
+        self.search.is_some().then_some(SEARCH_POLL)
+    }
+
+    /// Pop Out: switch whether the human's next column pops instead of drops, only on their
+    /// turn with one of their pieces at the bottom of the board
+    pub fn toggle_pop(&mut self) {
+        let player = self.game.current_player();
+        let can_pop = (0..self.game.config().cols).any(|col| self.game.can_pop(col, player));
+        if self.pop_armed || (can_pop && self.current_player_is_human()) {
+            self.pop_armed = !self.pop_armed;
+        }
+    }
+
+    /// Take back moves until it is a human's turn again, so against an AI the human gets their
+    /// own last move back rather than just the reply. Only while a human is playing
+    pub fn undo_move(&mut self) {
+        if !self.yellow_agent.is_human() && !self.red_agent.is_human() {
+            return;
+        }
+        while self.game.undo().is_some() && !self.current_player_is_human() {}
+        self.after_history_change();
+    }
+
+    /// Replay undone moves up to the next human turn
+    pub fn redo_move(&mut self) {
+        if !self.yellow_agent.is_human() && !self.red_agent.is_human() {
+            return;
+        }
+        while self.game.redo().is_some()
+            && *self.game.state() == GameState::InProgress
+            && !self.current_player_is_human()
+        {}
+        self.after_history_change();
+    }
+
+    // The board changed outside of play, anything tied to the previous position is stale
+    fn after_history_change(&mut self) {
+        self.cancel_search();
+        self.preview = None;
+        self.falling = None;
+        self.popped_column = None;
+        self.ended_at = None;
+        self.turn_started = Instant::now();
+    }
+
+    /// Drop a human's piece in the column under a mouse click, anywhere in the column counts.
+    /// Clicks outside the board, on full columns or during an AI turn are ignored
+    pub fn click(&mut self, x: u16, y: u16) {
+        if *self.game.state() != GameState::InProgress
+            || !self.current_player_is_human()
+            || self.falling.is_some()
+        {
+            return;
+        }
+        let Some(col) = self.grid_widget().column_at(self.grid_area, x, y) else {
+            return;
         };
+        let player = self.game.current_player();
+        let playable = if self.pop_armed {
+            self.game.can_pop(col, player)
+        } else {
+            self.game.valid_moves().contains(&col)
+        };
+        if playable {
+            self.play_action(player, true, Some(col));
+        }
+    }
 
-        match self.game.current_player() {
-            crate::game::Player::Yellow => {
-                let action = self.yellow_agent.get_action(&self.game, event);
-                if let Some(action) = action {
-                    let state = self.game.place(action);
-                    if state.is_some_and(|s| s != GameState::InProgress) {
-                        // Handle learning
-                        self.yellow_agent.learn(&self.game, Player::Yellow);
-                    }
+    /// Debug builds only: end the running game with the given result, counted in the session
+    /// like a real one
+    #[cfg(debug_assertions)]
+    pub fn force_result(&mut self, state: GameState) {
+        if *self.game.state() != GameState::InProgress {
+            return;
+        }
+        self.cancel_search();
+        self.preview = None;
+        self.game.force_state(state);
+        self.record_game_end();
+    }
+
+    // Play the move, after letting it fall to its cell when falling pieces are animated.
+    // Pops and drops into the top row take effect at once
+    fn play_action(&mut self, player: Player, is_human: bool, action: Option<usize>) {
+        if let Some(interval) = self.fall_interval
+            && let Some(col) = action
+            && !(is_human && self.pop_armed)
+            && let Some(target_row) = self.game.landing_row(col)
+            && target_row > 0
+        {
+            self.falling = Some(FallingPiece {
+                col,
+                row: 0,
+                target_row,
+                player,
+                is_human,
+                interval,
+                started: Instant::now(),
+            });
+            return;
+        }
+        self.resolve_action(player, is_human, action);
+    }
+
+    /// Move a falling piece down a row per interval, playing its move once it lands. A popped
+    /// column settles once its interval has passed
+    pub fn advance_fall(&mut self) {
+        if let Some(popped) = &self.popped_column
+            && popped.started.elapsed() >= popped.interval
+        {
+            self.popped_column = None;
+        }
+        let Some(falling) = &mut self.falling else {
+            return;
+        };
+        let rows = falling.started.elapsed().as_nanos() / falling.interval.as_nanos().max(1);
+        falling.row = (rows as usize).min(falling.target_row);
+        if falling.row == falling.target_row {
+            let FallingPiece {
+                col,
+                player,
+                is_human,
+                ..
+            } = *falling;
+            self.falling = None;
+            self.resolve_action(player, is_human, Some(col));
+        }
+    }
+
+    /// Time until a falling piece moves down the next row or a popped column settles, for
+    /// waking the loop
+    pub fn time_until_fall_frame(&self) -> Option<Duration> {
+        if let Some(popped) = &self.popped_column {
+            return Some(popped.interval.saturating_sub(popped.started.elapsed()));
+        }
+        let falling = self.falling.as_ref()?;
+        let next_row = falling.interval * (falling.row + 1) as u32;
+        Some(next_row.saturating_sub(falling.started.elapsed()))
+    }
+
+    fn resolve_action(&mut self, player: Player, is_human: bool, action: Option<usize>) {
+        if let Some(col) = action
+            && !self.pop_armed
+            && self.game.valid_moves().contains(&col)
+        {
+            self.check_puzzle_move(col);
+        }
+        let known = self.agent(player).knows_state(&self.game);
+        // A column that can't be popped keeps the pop armed for another try
+        let pop = is_human && self.pop_armed;
+        let result = action.and_then(|action| {
+            if pop {
+                self.game.pop(action)
+            } else {
+                self.game.place(action)
+            }
+        });
+        match result {
+            Some(state) => {
+                self.pop_armed = false;
+                self.popped_column = match (pop, self.fall_interval, action) {
+                    (true, Some(interval), Some(col)) => Some(PoppedColumn {
+                        col,
+                        interval,
+                        started: Instant::now(),
+                    }),
+                    _ => None,
+                };
+                if let Some(known) = known {
+                    self.coverage.push((player, known));
+                }
+                self.charge_turn_time(player);
+                self.record_evaluation(player);
+                // Puzzle games don't count towards the session
+                if state != GameState::InProgress && self.puzzles.is_none() {
+                    // Handle learning
+                    self.learn_from_game(player);
+                    self.record_game_end();
                 }
             }
-            crate::game::Player::Red => {
-                let action = self.red_agent.get_action(&self.game, event);
-                if let Some(action) = action {
-                    let state = self.game.place(action);
-                    if state.is_some_and(|s| s != GameState::InProgress) {
-                        // Handle learning
-                        self.red_agent.learn(&self.game, Player::Red);
-                    }
+            // An AI without a legal move would stall the game, end it instead
+            None if !is_human && !self.agent(player).awaiting_move() => {
+                let agent_type = self.agent(player).get_type();
+                let state = self.game.forfeit(player);
+                self.message = Some(match state {
+                    GameState::Draw => format!("{} had no move, the board is full", agent_type),
+                    _ => format!("{} ({}) had no legal move and forfeits", agent_type, player),
+                });
+                self.learn_from_game(player);
+                self.record_game_end();
+            }
+            // Waiting for human input or the remote player's answer
+            None => {}
+        }
+    }
+
+    fn grid_widget(&self) -> GridWidget<'_> {
+        GridWidget {
+            game: &self.game,
+            hidden_player: self.fog_hidden_player(),
+            show_header: match self.header_mode {
+                HeaderMode::Always => true,
+                HeaderMode::HumanTurn => {
+                    *self.game.state() == GameState::InProgress && self.current_player_is_human()
                 }
+            },
+            preview_column: self.preview_column(),
+            column_scores: self.show_heatmap.then(|| {
+                self.column_scores
+                    .get(&self.column_scores_key())
+                    .map_or(&[][..], Vec::as_slice)
+            }),
+            alignment: self.board_alignment,
+            padding: self.board_padding,
+            title: self.board_title.then(|| self.config_description()),
+            scroll: self.board_scroll,
+            falling: self
+                .falling
+                .as_ref()
+                .map(|falling| (falling.row, falling.col, falling.player)),
+            popped_column: self.popped_column.map(|popped| popped.col),
+        }
+    }
+
+    /// Scroll a board too wide for the screen by the given number of columns, stopping at
+    /// either edge
+    pub fn pan_board(&mut self, columns: isize) {
+        let max_scroll = self.grid_widget().max_scroll(self.grid_area);
+        self.board_scroll = self
+            .board_scroll
+            .min(max_scroll)
+            .saturating_add_signed(columns)
+            .min(max_scroll);
+    }
+
+    /// Let the AI to move pick its move ahead of time so it can be shown before it is played.
+    /// Humans and the remote player have nothing to preview
+    pub fn prepare_preview(&mut self) {
+        if self
+            .preview
+            .is_some_and(|(plies, _)| plies == self.game.plies())
+        {
+            return;
+        }
+        self.start_search();
+    }
+
+    /// Stop any search of the current position and make sure its result is never played.
+    /// The searching agent comes back right away without a move, and from then on the agents
+    /// search with a fresh token
+    pub fn cancel_search(&mut self) {
+        self.search_cancel.store(true, Ordering::Relaxed);
+        self.join_search();
+        self.queued_moves = 0;
+        self.search_cancel = Arc::new(AtomicBool::new(false));
+        self.yellow_agent
+            .set_cancel_token(Arc::clone(&self.search_cancel));
+        self.red_agent
+            .set_cancel_token(Arc::clone(&self.search_cancel));
+    }
+
+    /// Evaluate the columns for the heatmap with the agent to move, unless already done for
+    /// the position. Humans and agents without per-column scores leave the row blank
+    pub fn prepare_column_scores(&mut self) {
+        // The agent is away searching, only its stand-in is here
+        if self.search.is_some() {
+            return;
+        }
+        let key = self.column_scores_key();
+        if self.column_scores.contains_key(&key) {
+            return;
+        }
+        let scores = match self.game.state() {
+            GameState::InProgress => self.agent(key.player).evaluate_columns(&self.game),
+            _ => Vec::new(),
+        };
+        if self.column_scores.len() >= HEATMAP_POSITIONS {
+            self.column_scores.clear();
+        }
+        self.column_scores.insert(key, scores);
+    }
+
+    fn column_scores_key(&self) -> ColumnScoresKey {
+        let player = self.game.current_player();
+        ColumnScoresKey {
+            position: self.game.to_key(),
+            player,
+            agent: self.agent(player).get_type(),
+        }
+    }
+
+    /// Column of the prepared AI move for the current position
+    fn preview_column(&self) -> Option<usize> {
+        match self.preview {
+            Some((plies, action)) if plies == self.game.plies() => action,
+            _ => None,
+        }
+    }
+
+    /// Play up to `moves` AI moves in a row, each searched for once the last has landed.
+    /// Stops early at the end of the game or when a human is to move
+    pub fn step_moves(&mut self, moves: usize) -> eyre::Result<()> {
+        if moves == 0 || !self.ai_to_move() {
+            return Ok(());
+        }
+        self.queued_moves += moves - 1;
+        self.step(None)
+    }
+
+    /// Add the time since the last move to the player who just moved
+    fn charge_turn_time(&mut self, player: Player) {
+        let elapsed = self.turn_started.elapsed();
+        self.turn_started = Instant::now();
+        match player {
+            Player::Yellow => {
+                self.yellow_time += elapsed;
+                self.yellow_moves += 1;
+            }
+            Player::Red => {
+                self.red_time += elapsed;
+                self.red_moves += 1;
             }
         }
-        Ok(())
+    }
+
+    /// Time left on the player's clock in a timed game: the base time plus an increment per
+    /// move made, less the time used. None when the game isn't timed
+    pub fn remaining_time(&self, player: Player) -> Option<Duration> {
+        if !self.timed {
+            return None;
+        }
+        let moves = match player {
+            Player::Yellow => self.yellow_moves,
+            Player::Red => self.red_moves,
+        };
+        // Clocks too long to add up never run out
+        let allowed = self
+            .time_control
+            .increment
+            .checked_mul(moves)
+            .and_then(|increments| self.time_control.base.checked_add(increments))
+            .unwrap_or(Duration::MAX);
+        Some(allowed.saturating_sub(self.thinking_time(player)))
+    }
+
+    /// Time until the running clock shows the next second or runs out, for waking the loop.
+    /// None when no clock is running
+    pub fn time_until_clock_tick(&self) -> Option<Duration> {
+        if *self.game.state() != GameState::InProgress {
+            return None;
+        }
+        let remaining = self.remaining_time(self.game.current_player())?;
+        Some(Duration::from_nanos(remaining.subsec_nanos().into()).max(Duration::from_millis(1)))
+    }
+
+    /// End the game as a loss for the player to move once their time has run out
+    pub fn check_clock(&mut self) {
+        // A falling piece means the player to move already has
+        if *self.game.state() != GameState::InProgress || self.falling.is_some() {
+            return;
+        }
+        let player = self.game.current_player();
+        if self.remaining_time(player) != Some(Duration::ZERO) {
+            return;
+        }
+        self.cancel_search();
+        self.charge_turn_time(player);
+        self.preview = None;
+        // Same result as having no move to make
+        self.game.forfeit(player);
+        self.message = Some(format!(
+            "{} ({}) ran out of time",
+            self.agent(player).get_type(),
+            player
+        ));
+        self.learn_from_game(player);
+        self.record_game_end();
+    }
+
+    /// Time a player has used so far, including the running turn while the game is on
+    pub fn thinking_time(&self, player: Player) -> Duration {
+        let used = match player {
+            Player::Yellow => self.yellow_time,
+            Player::Red => self.red_time,
+        };
+        if *self.game.state() == GameState::InProgress && self.game.current_player() == player {
+            used + self.turn_started.elapsed()
+        } else {
+            used
+        }
+    }
+
+    fn agent(&self, player: Player) -> &dyn Agent {
+        match player {
+            Player::Yellow => self.yellow_agent.as_ref(),
+            Player::Red => self.red_agent.as_ref(),
+        }
+    }
+
+    /// Let the player's agent learn from the finished game
+    fn learn_from_game(&mut self, player: Player) {
+        match player {
+            Player::Yellow => self.yellow_agent.learn(&self.game, player),
+            Player::Red => self.red_agent.learn(&self.game, player),
+        }
+        self.show_agent_warnings();
+    }
+
+    // Put a load or save problem of either agent on the status line, where the terminal ui
+    // can show it
+    fn show_agent_warnings(&mut self) {
+        let warnings = [
+            self.yellow_agent.take_warning(),
+            self.red_agent.take_warning(),
+        ];
+        if let Some(warning) = warnings.into_iter().flatten().last() {
+            self.message = Some(warning);
+        }
+    }
+
+    /// Log the mover's evaluation of its move, flipped to Yellow's perspective
+    fn record_evaluation(&mut self, player: Player) {
+        let score = match player {
+            Player::Yellow => self.yellow_agent.last_evaluation(),
+            Player::Red => self.red_agent.last_evaluation().map(|score| -score),
+        };
+        if let Some(score) = score {
+            self.eval_log.push((score as f64 / EVAL_SCALE).tanh());
+        }
+    }
+
+    /// Latest evaluation from Yellow's perspective in [-1, 1], None until a search agent moved
+    pub fn current_evaluation(&self) -> Option<f64> {
+        self.eval_log.last().copied()
+    }
+
+    /// Text chart of the logged evaluations, higher bars mean Yellow is ahead
+    fn eval_graph(&self) -> String {
+        const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+        let start = self.eval_log.len().saturating_sub(EVAL_GRAPH_LENGTH);
+        self.eval_log[start..]
+            .iter()
+            .map(|eval| {
+                let level = ((eval + 1.0) / 2.0 * (BARS.len() - 1) as f64).round() as usize;
+                BARS[level.min(BARS.len() - 1)]
+            })
+            .collect()
+    }
+
+    /// Strip with one mark per Q-table agent move, filled for positions already in its table
+    /// and hollow for novel ones
+    fn coverage_strip(&self) -> Line<'static> {
+        let known = self.coverage.iter().filter(|(_, known)| *known).count();
+        let mut spans = vec!["Q coverage: ".into()];
+        spans.extend(self.coverage.iter().map(|&(player, known)| {
+            let mark = if known { "●" } else { "○" };
+            match player {
+                Player::Yellow => mark.yellow(),
+                Player::Red => mark.red(),
+            }
+        }));
+        spans.push(format!(" {}/{} known", known, self.coverage.len()).into());
+        Line::from(spans)
+    }
+
+    /// Add the finished game to the session totals
+    fn record_game_end(&mut self) {
+        self.yellow_agent.game_over(&self.game);
+        self.red_agent.game_over(&self.game);
+        let yellow_type = self.yellow_agent.get_type();
+        let red_type = self.red_agent.get_type();
+        self.ended_at = Some(Instant::now());
+        // Demo games don't count towards the session
+        if self.attract.is_some() {
+            return;
+        }
+        self.record_human_result();
+        let session = &mut self.session;
+        session.games += 1;
+
+        match *self.game.state() {
+            GameState::Won(winner) => {
+                let (winner_type, loser_type) = match winner {
+                    Player::Yellow => {
+                        session.yellow_wins += 1;
+                        (yellow_type, red_type)
+                    }
+                    Player::Red => {
+                        session.red_wins += 1;
+                        (red_type, yellow_type)
+                    }
+                };
+                session.agent_records.entry(winner_type).or_default().wins += 1;
+                session.agent_records.entry(loser_type).or_default().losses += 1;
+            }
+            GameState::Draw => {
+                session.draws += 1;
+                session.agent_records.entry(yellow_type).or_default().draws += 1;
+                session.agent_records.entry(red_type).or_default().draws += 1;
+            }
+            GameState::InProgress => {}
+        }
+    }
+
+    // Update the all-time records after a game between a human and an AI
+    fn record_human_result(&mut self) {
+        let human = match (self.yellow_agent.is_human(), self.red_agent.is_human()) {
+            (true, false) => Player::Yellow,
+            (false, true) => Player::Red,
+            _ => return,
+        };
+        let opponent = self.agent(human.opponent()).get_type();
+        let human_won = *self.game.state() == GameState::Won(human);
+        if self
+            .records
+            .record_game(&opponent, human_won, self.game.plies())
+        {
+            self.message = Some("New record! Press <h> to see your records".to_string());
+        }
+    }
+
+    /// Start the session totals and the record streaks over, the all-time records are kept
+    pub fn clear_scoreboard(&mut self) {
+        self.session = SessionStats::default();
+        self.records.new_session();
+        self.message = Some("Scoreboard cleared".to_string());
+    }
+
+    /// Session score line for the info panel, None before the first finished game
+    fn score_line(&self) -> Option<Line<'static>> {
+        let session = &self.session;
+        if session.games == 0 {
+            return None;
+        }
+        Some(match self.score_mode {
+            ScoreMode::Color => Line::from(format!(
+                "Score: Yellow {} - Red {} ({} drawn)",
+                session.yellow_wins, session.red_wins, session.draws
+            )),
+            ScoreMode::Identity => {
+                let record = |agent_type: String| {
+                    let record = session
+                        .agent_records
+                        .get(&agent_type)
+                        .copied()
+                        .unwrap_or_default();
+                    format!(
+                        "{} {}/{}/{}",
+                        agent_type, record.wins, record.losses, record.draws
+                    )
+                };
+                let yellow = record(self.yellow_agent.get_type());
+                let red = record(self.red_agent.get_type());
+                Line::from(vec![
+                    "Score (W/L/D): ".into(),
+                    yellow.yellow(),
+                    ", ".into(),
+                    red.red(),
+                ])
+            }
+        })
+    }
+
+    /// Most relevant keys for the current mode, shown at the bottom of the screen
+    fn footer_hints(&self) -> Line<'static> {
+        let hints: Vec<(&str, &str)> = if self.menu_open {
+            vec![
+                ("Move ", "<j/k>"),
+                (" Select ", "<Enter>"),
+                (" Config ", "<c>"),
+                (" Back to game ", "<r>"),
+                (" Defaults ", "<R>"),
+            ]
+        } else if *self.game.state() != GameState::InProgress {
+            vec![
+                ("New game ", "<r>"),
+                (" Menu ", "<p>"),
+                (" Records ", "<h>"),
+            ]
+        } else if self.current_player_is_human() {
+            vec![
+                ("Drop ", "<column number>"),
+                (" Undo/redo ", "<u/U>"),
+                (" Menu ", "<p>"),
+                (" Reset ", "<r>"),
+            ]
+        } else {
+            vec![
+                ("Step ", "<Space>"),
+                (" Step 5 ", "<>>"),
+                (" Speed ", "<s/f/i/m>"),
+                (" Menu ", "<p>"),
+                (" Reset ", "<r>"),
+            ]
+        };
+
+        let mut spans = vec![" ".into()];
+        for (label, key) in hints {
+            spans.push(label.into());
+            spans.push(key.blue());
+        }
+        spans.push(" Quit ".into());
+        spans.push("<q> ".red());
+        Line::from(spans)
+    }
+
+    fn render_summary(&self, area: Rect, buf: &mut Buffer) {
+        let session = &self.session;
+        let mut lines = vec![
+            Line::from(format!("Games played: {}", session.games)),
+            Line::from(format!("Yellow wins: {}", session.yellow_wins).yellow()),
+            Line::from(format!("Red wins: {}", session.red_wins).red()),
+            Line::from(format!("Draws: {}", session.draws)),
+            Line::from(" "),
+            Line::from("Agent records (W/L/D)".bold()),
+        ];
+        for (agent_type, record) in &session.agent_records {
+            lines.push(Line::from(format!(
+                "{}: {}/{}/{}",
+                agent_type, record.wins, record.losses, record.draws
+            )));
+        }
+
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .title_top(Line::from(" Session Summary ".bold()).green())
+                    .title_bottom(Line::from(vec!["Press any key to quit ".into()]))
+                    .padding(Padding::horizontal(1)),
+            )
+            .render(area, buf);
+    }
+
+    fn render_records(&self, area: Rect, buf: &mut Buffer) {
+        let records = &self.records;
+        let fastest = match records.fastest_win {
+            Some(moves) => format!("{} moves", moves),
+            None => "none yet".to_string(),
+        };
+        let mut lines = vec![
+            Line::from(format!(
+                "Most wins in a session: {}",
+                records.most_session_wins
+            )),
+            Line::from(format!("Fastest win: {}", fastest)),
+            Line::from(" "),
+            Line::from("Longest win streak against".bold()),
+        ];
+        for (agent_type, streak) in &records.longest_streaks {
+            lines.push(Line::from(format!("{}: {}", agent_type, streak)));
+        }
+
+        Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_type(BorderType::Rounded)
+                    .title_top(Line::from(" All-time Records ".bold()).green())
+                    .title_bottom(Line::from(vec!["Press any key to go back ".into()]))
+                    .padding(Padding::horizontal(1)),
+            )
+            .render(area, buf);
     }
 
     fn render_agent_list(&mut self, area: Rect, buf: &mut Buffer) {
@@ -134,6 +1559,7 @@ impl App {
             "Select to change Red".to_string(),
         ];
         options.append(&mut Agents::agent_names());
+        options.push("Load saved game".to_string());
 
         // Render selectable options
         let list = List::new(options)
@@ -150,11 +1576,7 @@ impl App {
                         "<Enter>".blue(),
                     ]))
                     .title_top(Line::from(
-                        format!(
-                            " Select Agent for {} ",
-                            self.agent_list.selected_player.to_string()
-                        )
-                        .blue(),
+                        format!(" Select Agent for {} ", self.agent_list.selected_player).blue(),
                     )),
             )
             .highlight_style(Style::default().fg(Color::Blue))
@@ -164,12 +1586,18 @@ impl App {
     }
 
     fn render_config_list(&mut self, area: Rect, buf: &mut Buffer) {
-        let list = List::new(vec![
-            "Standard".to_string(),
-            "Small".to_string(),
-            "Large".to_string(),
-            "Huge".to_string(),
-        ])
+        let custom = match self.config_list.custom {
+            Some(config) => format!("Custom… ({})", config),
+            None => "Custom… (type rows, columns and connect length)".to_string(),
+        };
+        let list = List::new(
+            (0..GameConfigPreset::amount_of_presets())
+                .map(|index| {
+                    let preset = GameConfigPreset::from_index(index);
+                    format!("{} ({})", preset, preset.into_config())
+                })
+                .chain([custom]),
+        )
         .block(
             Block::default()
                 .borders(Borders::ALL)
@@ -184,26 +1612,273 @@ impl App {
     }
 }
 
-pub struct AgentList {
-    pub selected_player: Player,
-    pub state: ListState,
+// A search still running would go on for a board nobody plays anymore
+impl Drop for App {
+    fn drop(&mut self) {
+        self.cancel_search();
+    }
+}
+
+/// Piece dropped into a column, shown falling a row at a time before its move is played
+#[derive(Debug, Clone, Copy)]
+struct FallingPiece {
+    col: usize,
+    // Row it is drawn at, counted from the top
+    row: usize,
+    // Row it lands on
+    target_row: usize,
+    player: Player,
+    is_human: bool,
+    interval: Duration,
+    started: Instant,
+}
+
+/// Column a piece was just popped from, drawn with its pieces a row higher until they land
+#[derive(Debug, Clone, Copy)]
+struct PoppedColumn {
+    col: usize,
+    interval: Duration,
+    started: Instant,
+}
+
+/// AI move being searched for on a worker thread, which holds the agent until it answers
+struct Search {
+    player: Player,
+    // Ply count of the position searched, the move is for that position only
+    plies: usize,
+    handle: JoinHandle<(Box<dyn Agent>, Option<usize>)>,
+}
+
+/// Stands in for an agent while it searches on the worker thread, answering for it by name
+struct Searching {
+    agent_type: String,
+    search_depth: Option<usize>,
+}
+
+impl Agent for Searching {
+    fn get_action(&mut self, _board: &Game, _event: Option<Event>) -> Option<usize> {
+        None
+    }
+
+    fn get_type(&self) -> String {
+        self.agent_type.clone()
+    }
+
+    fn search_depth(&self) -> Option<usize> {
+        self.search_depth
+    }
+
+    fn awaiting_move(&self) -> bool {
+        true
+    }
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    fn learn(&mut self, _board: &Game, _player: Player) {}
+}
+
+/// Position, player to move and agent the heatmap scores of a position were evaluated for
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ColumnScoresKey {
+    position: String,
+    player: Player,
+    agent: String,
+}
+
+/// Chess-style clock for timed games: base time per player plus an increment per move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        TimeControl {
+            base: Duration::from_secs(5 * 60),
+            increment: Duration::from_secs(3),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} + {}s",
+            format_clock(self.base),
+            self.increment.as_secs()
+        )
+    }
+}
+
+/// When to show the column numbers above the board
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderMode {
+    #[default]
+    Always,
+    // Only when a human is to move, AI doesn't need them
+    HumanTurn,
+}
+
+impl HeaderMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            HeaderMode::Always => HeaderMode::HumanTurn,
+            HeaderMode::HumanTurn => HeaderMode::Always,
+        }
+    }
+}
+
+impl fmt::Display for HeaderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeaderMode::Always => write!(f, "Always"),
+            HeaderMode::HumanTurn => write!(f, "Human turn"),
+        }
+    }
+}
+
+/// How the running score in the info panel is tallied
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ScoreMode {
+    #[default]
+    Color,
+    // By agent type, so a player keeps their tally when the colors are swapped
+    Identity,
+}
+
+impl ScoreMode {
+    pub fn toggled(self) -> Self {
+        match self {
+            ScoreMode::Color => ScoreMode::Identity,
+            ScoreMode::Identity => ScoreMode::Color,
+        }
+    }
+}
+
+impl fmt::Display for ScoreMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScoreMode::Color => write!(f, "By color"),
+            ScoreMode::Identity => write!(f, "By agent"),
+        }
+    }
+}
+
+/// Win/loss/draw record of a single agent type
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
+pub struct Record {
+    pub wins: usize,
+    pub losses: usize,
+    pub draws: usize,
+}
+
+/// Totals for the games finished during this session
+#[derive(Default, Debug)]
+pub struct SessionStats {
+    pub games: usize,
+    pub yellow_wins: usize,
+    pub red_wins: usize,
+    pub draws: usize,
+    /// Keyed by agent type so records stay sorted by name
+    pub agent_records: BTreeMap<String, Record>,
+}
+
+pub struct AgentList {
+    pub selected_player: Player,
+    pub state: ListState,
+}
+
+pub struct GameConfigList {
+    pub selected_game: GameConfigPreset,
+    /// Board typed into the Custom… entry, played instead of the preset while set
+    pub custom: Option<GameConfig>,
+    pub state: ListState,
+}
+
+/// Default pause before AI autoplay starts after a reset
+pub const DEFAULT_STARTUP_DELAY: Duration = Duration::from_millis(500);
+
+/// Idle time on a fresh game before the attract mode demo starts
+const ATTRACT_IDLE: Duration = Duration::from_secs(30);
+
+/// How often the connection is read for the remote player's move while it is theirs
+const REMOTE_POLL: Duration = Duration::from_millis(50);
+
+/// How often a running search is checked for its move
+const SEARCH_POLL: Duration = Duration::from_millis(10);
+
+/// Moves played at once by the batch step key
+pub const BATCH_STEP_MOVES: usize = 5;
+
+/// Pause on the final position before an automatic reset
+const AUTO_RESET_DELAY: Duration = Duration::from_secs(1);
+
+/// Deepest search the depth slider allows
+const MAX_SLIDER_DEPTH: usize = 12;
+
+/// Score treated as a clear advantage when normalizing evaluations
+const EVAL_SCALE: f64 = 200.0;
+/// Number of most recent evaluations shown in the graph
+const EVAL_GRAPH_LENGTH: usize = 30;
+
+/// Positions the heatmap keeps scores for before starting over
+const HEATMAP_POSITIONS: usize = 4096;
+
+/// Minimum terminal height for showing the key hint footer
+const FOOTER_MIN_HEIGHT: u16 = 20;
+
+/// Minutes and seconds, e.g. "1:05"
+fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Share of the evaluation bar filled for Yellow: 0 is a lost position, 0.5 even, 1 won
+pub fn eval_bar_fill(evaluation: f64) -> f64 {
+    (evaluation.clamp(-1.0, 1.0) + 1.0) / 2.0
 }
 
-pub struct GameConfigList {
-    pub selected_game: GameConfigPreset,
-    pub state: ListState,
+// Yellow fills the bar from the left, the rest is Red's
+fn eval_bar(evaluation: Option<f64>) -> LineGauge<'static> {
+    let (label, ratio) = match evaluation {
+        Some(evaluation) => {
+            let fill = eval_bar_fill(evaluation);
+            (format!("Yellow {:>3.0}% ", fill * 100.0), fill)
+        }
+        None => ("No evaluation ".to_string(), 0.5),
+    };
+    LineGauge::default()
+        .label(label)
+        .ratio(ratio)
+        .filled_style(Style::default().fg(Color::Yellow))
+        .unfilled_style(Style::default().fg(Color::Red))
 }
 
 pub fn render(frame: &mut Frame, app: &mut App, current_speed: &RunSpeed) {
-    let grid = GridWidget { game: &app.game };
+    if app.summary_open {
+        app.render_summary(frame.area(), frame.buffer_mut());
+        return;
+    }
+    if app.records_open {
+        app.render_records(frame.area(), frame.buffer_mut());
+        return;
+    }
 
     let area = frame.area();
 
-    let global_block = Block::default()
+    let mut global_block = Block::default()
         .borders(Borders::ALL)
         .border_type(BorderType::Rounded)
         .title_top(Line::from(" Connect 4 ".bold()).red())
         .padding(Padding::horizontal(1));
+    // Hide the key hints on short terminals to leave room for the board
+    if area.height >= FOOTER_MIN_HEIGHT {
+        global_block = global_block.title_bottom(app.footer_hints());
+    }
 
     let horizontal_layout = Layout::default()
         .direction(Direction::Horizontal)
@@ -233,6 +1908,7 @@ pub fn render(frame: &mut Frame, app: &mut App, current_speed: &RunSpeed) {
         .direction(Direction::Vertical)
         .constraints([
             ratatui::layout::Constraint::Percentage(30),
+            ratatui::layout::Constraint::Length(1),
             ratatui::layout::Constraint::Percentage(60),
         ])
         .split(right_block.inner(horizontal_layout[1]));
@@ -242,39 +1918,175 @@ pub fn render(frame: &mut Frame, app: &mut App, current_speed: &RunSpeed) {
             // Match color of player
             match app.game.current_player() {
                 Player::Red => {
-                    Line::from(format!("Current player: {:?}", app.game.current_player()).red())
+                    Line::from(format!("Current player: {}", app.game.current_player()).red())
                 }
                 Player::Yellow => {
-                    Line::from(format!("Current player: {:?}", app.game.current_player()).yellow())
+                    Line::from(format!("Current player: {}", app.game.current_player()).yellow())
                 }
             }
         }
-        GameState::Won(player) => Line::from(format!("Player {:?} wins!", player).green()),
+        GameState::Won(player) => match app.game.win_direction() {
+            Some(direction) => {
+                Line::from(format!("Player {} wins with {}!", player, direction).green())
+            }
+            None => Line::from(format!("Player {} wins!", player).green()),
+        },
         GameState::Draw => Line::from("Game ended in a draw".yellow()),
     };
 
-    let player_info = Paragraph::new(vec![
-        status,
-        Line::from(" "),
-        Line::from(format!("Player 1 [{}]", app.yellow_agent.get_type()).yellow()),
-        Line::from(format!("Player 2 [{}]", app.red_agent.get_type()).red()),
-    ]);
+    let mut player_info_lines = vec![status, Line::from(" ")];
+    // Player 1 is whoever moves first
+    let first_player = app.game.first_player();
+    for (number, player) in [(1, first_player), (2, first_player.opponent())] {
+        let label = format!("Player {} [{}]", number, app.agent(player).get_type());
+        player_info_lines.push(Line::from(match player {
+            Player::Yellow => label.yellow(),
+            Player::Red => label.red(),
+        }));
+        if let Some(line) = app.agent(player).expected_line()
+            && !line.is_empty()
+        {
+            let line: Vec<String> = line
+                .into_iter()
+                .map(|col| column_label(col).to_string())
+                .collect();
+            player_info_lines.push(Line::from(format!("  Expected line: {}", line.join(" "))));
+        }
+    }
+    player_info_lines.push(Line::from(vec![
+        format!("{} moves, clock ", app.game.plies()).into(),
+        format_clock(app.thinking_time(Player::Yellow)).yellow(),
+        " / ".into(),
+        format_clock(app.thinking_time(Player::Red)).red(),
+    ]));
+    if let (Some(yellow), Some(red)) = (
+        app.remaining_time(Player::Yellow),
+        app.remaining_time(Player::Red),
+    ) {
+        player_info_lines.push(Line::from(vec![
+            format!("Time left ({}) ", app.time_control).into(),
+            format_clock(yellow).yellow(),
+            " / ".into(),
+            format_clock(red).red(),
+        ]));
+    }
+    if let Some(notation) = &app.position_prompt {
+        player_info_lines.push(Line::from(vec![
+            "Load moves: ".blue(),
+            format!("{}_", notation).into(),
+            " <Enter> load <Esc> cancel".blue(),
+        ]));
+    }
+    if let Some(size) = &app.size_prompt {
+        player_info_lines.push(Line::from(vec![
+            "Rows, columns and connect length: ".blue(),
+            format!("{}_", size).into(),
+            " <Enter> play <Esc> cancel".blue(),
+        ]));
+    }
+    if let Some(score) = app.score_line() {
+        player_info_lines.push(score);
+    }
+    if app.attract_active() {
+        player_info_lines.push(Line::from("Demo game, press any key to take over".blue()));
+    }
+    if let Some(message) = &app.message {
+        player_info_lines.push(Line::from(message.clone().red()));
+    }
+    if app.show_eval_graph {
+        player_info_lines.push(Line::from(vec![
+            "Yellow outlook: ".into(),
+            app.eval_graph().yellow(),
+        ]));
+    }
+    if app.show_coverage {
+        player_info_lines.push(app.coverage_strip());
+    }
+    if app.game.config().pop_out {
+        player_info_lines.push(Line::from(format!(
+            "Popped: Yellow {}, Red {}",
+            app.game.popped(Player::Yellow),
+            app.game.popped(Player::Red)
+        )));
+    }
+    if app.game.config().pop_out && *app.game.state() == GameState::InProgress {
+        player_info_lines.push(if app.pop_armed {
+            Line::from("Pop armed: choose a column to pop your bottom piece".blue())
+        } else {
+            Line::from(vec!["Pop a piece ".into(), "<x>".blue()])
+        });
+    }
+    if let Some(puzzles) = &app.puzzles {
+        player_info_lines.push(Line::from(vec![
+            format!(
+                "{}, solved {}/{} ",
+                puzzles.title(),
+                puzzles.solved,
+                puzzles.attempted
+            )
+            .blue(),
+            "<, .>".blue(),
+            " cycle".into(),
+        ]));
+    }
+    let player_info = Paragraph::new(player_info_lines).wrap(Wrap { trim: true });
 
     let mut instructions = vec![
         Line::from(" "),
-        Line::from(format!(
-            "Game Config: {}x{}",
-            app.game.config().cols,
-            app.game.config().rows
-        )),
+        Line::from(format!("Game Config: {}", app.config_description())),
         Line::from(vec![
             "Quit ".into(),
             "<q>".red(),
             " Reset Play ".into(),
             "<r>".blue(),
+            " Defaults ".into(),
+            "<R>".blue(),
             " Menu ".into(),
             "<p>".blue(),
+            " Fog ".into(),
+            "<o>".blue(),
+            " Eval graph ".into(),
+            "<e>".blue(),
+        ]),
+        Line::from(vec![
+            format!("Column numbers: {} ", app.header_mode).into(),
+            "<n>".blue(),
+            format!(
+                " Auto-reset: {} ",
+                if app.auto_reset { "On" } else { "Off" }
+            )
+            .into(),
+            "<a>".blue(),
         ]),
+        Line::from(vec![
+            format!("Score: {} ", app.score_mode).into(),
+            "<t>".blue(),
+            " Clear score ".into(),
+            "<z>".blue(),
+            " Eval bar ".into(),
+            "<b>".blue(),
+            " Load position ".into(),
+            "<L>".blue(),
+            " Save game ".into(),
+            "<w>".blue(),
+            " Q coverage ".into(),
+            "<K>".blue(),
+            " Puzzles ".into(),
+            "<P>".blue(),
+            format!(" Timed: {} ", if app.timed { "On" } else { "Off" }).into(),
+            "<T>".blue(),
+            " Heatmap ".into(),
+            "<H>".blue(),
+        ]),
+        Line::from(vec![
+            "Cycle Yellow ".into(),
+            "<[ ]>".yellow(),
+            " Cycle Red ".into(),
+            "<{ }>".red(),
+            " Pan board ".into(),
+            "<( )>".blue(),
+        ]),
+        Line::from(vec!["Practice vs trained RL ".into(), "<v>".blue()]),
         Line::from(" "),
         Line::from(vec![
             "Current speed: ".into(),
@@ -294,23 +2106,1279 @@ pub fn render(frame: &mut Frame, app: &mut App, current_speed: &RunSpeed) {
 
     // Add extra instruction if any human player
     if app.yellow_agent.is_human() || app.red_agent.is_human() {
-        instructions.push(Line::from(
-            "Drop a piece by entering number of column.".green(),
-        ));
+        let hint = if app.game.config().cols >= 10 {
+            "Drop a piece by entering number of column, 0 for column 10."
+        } else {
+            "Drop a piece by entering number of column."
+        };
+        instructions.push(Line::from(hint.green()));
     }
 
     frame.render_widget(global_block, area);
     frame.render_widget(right_block, horizontal_layout[1]);
     frame.render_widget(
         Paragraph::new(instructions).wrap(Wrap { trim: true }),
-        vertical_layout[1],
+        vertical_layout[2],
     );
     frame.render_widget(player_info, vertical_layout[0]);
+    if let Some(player) = app.depth_slider_player()
+        && let Some(depth) = app.agent(player).search_depth()
+    {
+        let label_color = match player {
+            Player::Yellow => Color::Yellow,
+            Player::Red => Color::Red,
+        };
+        let gauge = LineGauge::default()
+            .label(Line::from(format!("{} depth {:>2} <+/->", player, depth)).fg(label_color))
+            .ratio(depth as f64 / MAX_SLIDER_DEPTH as f64)
+            .filled_style(Style::default().fg(Color::Blue));
+        frame.render_widget(gauge, vertical_layout[1]);
+    }
 
     if app.menu_open {
         app.render_agent_list(left_menu, frame.buffer_mut());
         app.render_config_list(right_menu, frame.buffer_mut());
     } else {
-        frame.render_widget(grid, horizontal_layout[0]);
+        let board_area = if app.show_eval_bar {
+            let [bar, board] = Layout::vertical([Constraint::Length(1), Constraint::Min(0)])
+                .areas(horizontal_layout[0]);
+            frame.render_widget(eval_bar(app.current_evaluation()), bar);
+            board
+        } else {
+            horizontal_layout[0]
+        };
+        app.grid_area = board_area;
+        frame.render_widget(app.grid_widget(), board_area);
+        if let Some(banner) = app.game_over_banner() {
+            // Below the board where possible, so the winning line stays visible
+            let [popup] = Layout::vertical([Constraint::Length(4)])
+                .flex(Flex::End)
+                .areas(board_area);
+            let [popup] = Layout::horizontal([Constraint::Length(34)])
+                .flex(Flex::Center)
+                .areas(popup);
+            let lines = vec![banner, Line::from("Press any key for a new game")];
+            frame.render_widget(Clear, popup);
+            frame.render_widget(
+                Paragraph::new(lines).centered().block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Rounded),
+                ),
+                popup,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    #[test]
+    fn a_reset_or_quit_cancels_the_search_and_its_move_is_never_played() {
+        let mut app = App::new().unwrap();
+        // Far too deep to finish, only a cancel can end the search
+        app.set_agent(Player::Yellow, Agents::Minimax(30));
+        app.reset();
+        app.game = Game::from_notation(*app.game.config(), "4453").unwrap();
+        let board = app.game.to_key();
+
+        // The search runs on its own thread, the loop gets the board back right away
+        app.step(None).unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        app.poll_search();
+        assert!(app.searching());
+        assert_eq!(app.game.to_key(), board);
+
+        let start = Instant::now();
+        app.reset();
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            start.elapsed()
+        );
+        assert!(!app.searching());
+        app.poll_search();
+        assert_eq!(app.game.plies(), 0);
+        assert_eq!(*app.game.state(), GameState::InProgress);
+        assert!(app.preview.is_none());
+
+        // A preview search is cancelled on quit, handing the agent back
+        app.game = Game::from_notation(*app.game.config(), "4453").unwrap();
+        app.prepare_preview();
+        assert!(app.searching());
+        app.cancel_search();
+        assert!(!app.searching());
+        assert!(!app.yellow_agent.awaiting_move());
+        assert!(app.preview.is_none());
+        assert_eq!(app.game.to_key(), board);
+
+        // Searches after a cancel run with a fresh token
+        app.reset();
+        app.set_agent(Player::Yellow, Agents::Minimax(3));
+        app.step(None).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.move_count(), 1);
+    }
+
+    // AI agent which never finds a move
+    struct NoMove;
+
+    impl Agent for NoMove {
+        fn get_action(&mut self, _board: &Game, _event: Option<Event>) -> Option<usize> {
+            None
+        }
+
+        fn get_type(&self) -> String {
+            "No move".to_string()
+        }
+
+        fn is_human(&self) -> bool {
+            false
+        }
+
+        fn learn(&mut self, _board: &Game, _player: Player) {}
+    }
+
+    #[test]
+    fn an_agent_without_a_move_forfeits_the_game() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        app.reset();
+        app.step(None).unwrap();
+        app.wait_for_search();
+        app.red_agent = Box::new(NoMove);
+        app.message = None;
+
+        app.step(None).unwrap();
+        app.wait_for_search();
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+        assert_eq!(app.game.move_count(), 1);
+        let message = app.message.clone().unwrap_or_default();
+        assert!(message.contains("forfeits"), "{}", message);
+        assert_eq!((app.session.games, app.session.yellow_wins), (1, 1));
+
+        // Nothing more happens once the game is over
+        app.step(None).unwrap();
+        assert_eq!(app.session.games, 1);
+    }
+
+    #[test]
+    fn running_out_of_time_loses_the_game() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Human);
+        app.set_agent(Player::Red, Agents::Human);
+        app.timed = true;
+        app.time_control = TimeControl {
+            base: Duration::from_millis(200),
+            increment: Duration::ZERO,
+        };
+        app.reset();
+        app.resolve_action(Player::Yellow, true, Some(3));
+
+        // Red still has time, then the clock runs out on Red's turn
+        app.check_clock();
+        assert_eq!(*app.game.state(), GameState::InProgress);
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(app.remaining_time(Player::Red), Some(Duration::ZERO));
+        app.check_clock();
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+        let message = app.message.clone().unwrap_or_default();
+        assert!(message.contains("ran out of time"), "{}", message);
+        assert_eq!(app.session.yellow_wins, 1);
+
+        // Clocks too long to add up never run out, rather than overflowing
+        app.time_control = TimeControl {
+            base: Duration::MAX,
+            increment: Duration::MAX,
+        };
+        app.reset();
+        app.resolve_action(Player::Yellow, true, Some(3));
+        assert_eq!(
+            app.remaining_time(Player::Yellow)
+                .map(|time| time > Duration::from_secs(1)),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn cycling_agents_advances_and_wraps_around() {
+        let mut app = App::new().unwrap();
+        let agent_types = Agents::agent_types();
+        let last = agent_types.last().unwrap().clone();
+        app.set_agent(Player::Yellow, agent_types[0].clone());
+
+        app.cycle_agent(Player::Yellow, true);
+        assert_eq!(app.yellow_agent_type, agent_types[1]);
+        app.cycle_agent(Player::Yellow, false);
+        app.cycle_agent(Player::Yellow, false);
+        assert_eq!(app.yellow_agent_type, last);
+        app.cycle_agent(Player::Yellow, true);
+        assert_eq!(app.yellow_agent_type, agent_types[0]);
+
+        // The other player's agent is left alone
+        assert_eq!(app.red_agent_type, Agents::Minimax(1));
+        app.cycle_agent(Player::Red, true);
+        assert_eq!(app.red_agent_type, Agents::Minimax(3));
+        assert_eq!(app.yellow_agent_type, agent_types[0]);
+    }
+
+    #[test]
+    fn cycling_through_every_config_and_back_keeps_agents_and_boards_in_step() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::RL(0.2, false));
+        app.set_agent(Player::Red, Agents::RL(0.4, true));
+        app.cycle_config();
+        app.cycle_config();
+        let start = app.config_list.state.selected();
+
+        for _ in 0..=GameConfigPreset::amount_of_presets() {
+            app.cycle_config();
+            if app.size_prompt.take().is_some() {
+                continue;
+            }
+            let preset = app.config_list.selected_game.into_config();
+            assert_eq!(*app.game.config(), preset);
+            assert_eq!(app.yellow_agent_type, Agents::RL(0.2, false));
+            assert_eq!(app.red_agent_type, Agents::RL(0.4, true));
+
+            // The agents were rebuilt for the board, so their moves fit it
+            app.step(None).unwrap();
+            app.wait_for_search();
+            assert_eq!(app.game.move_count(), 1);
+            app.step(None).unwrap();
+            app.wait_for_search();
+            assert_eq!(app.game.move_count(), 2);
+        }
+        assert_eq!(app.config_list.state.selected(), start);
+    }
+
+    #[test]
+    fn pops_are_counted_and_the_popped_column_settles() {
+        let mut app = App::new().unwrap();
+        app.select_config(GameConfigPreset::PopOut.into_config());
+        app.set_agent(Player::Yellow, Agents::Human);
+        app.set_agent(Player::Red, Agents::Human);
+        app.reset();
+        let interval = Duration::from_millis(50);
+        app.fall_interval = Some(interval);
+        let bottom = app.game.config().rows - 1;
+
+        // Yellow and Red stack in the first column, then Yellow pops its piece from under Red's
+        app.resolve_action(Player::Yellow, true, Some(0));
+        app.resolve_action(Player::Red, true, Some(0));
+        app.toggle_pop();
+        app.resolve_action(Player::Yellow, true, Some(0));
+        assert_eq!(app.game.popped(Player::Yellow), 1);
+        assert_eq!(app.game.popped(Player::Red), 0);
+        assert_eq!(app.game.get_cell(bottom, 0), Some(Player::Red));
+
+        // Red's piece is still drawn where it was until the interval has passed
+        let grid = app.grid_widget();
+        assert_eq!(grid.shown_cell(bottom - 1, 0), Some(Player::Red));
+        assert_eq!(grid.shown_cell(bottom, 0), None);
+        app.advance_fall();
+        assert!(app.popped_column.is_some());
+
+        std::thread::sleep(interval);
+        app.advance_fall();
+        assert!(app.popped_column.is_none());
+        let grid = app.grid_widget();
+        for row in 0..=bottom {
+            for col in 0..app.game.config().cols {
+                assert_eq!(grid.shown_cell(row, col), app.game.get_cell(row, col));
+            }
+        }
+
+        // Taking the pop back takes it off the count
+        app.game.undo();
+        assert_eq!(app.game.popped(Player::Yellow), 0);
+    }
+
+    #[test]
+    fn agent_load_problems_reach_the_status_line() {
+        let mut app = App::new().unwrap();
+        app.select_config(GameConfig::custom(7, 3, 3).unwrap());
+        app.reset();
+        app.message = None;
+
+        // No policy was ever exported for this board
+        app.set_agent(Player::Yellow, Agents::Policy);
+        let message = app.message.clone().unwrap_or_default();
+        assert!(message.starts_with("Failed to load policy"), "{}", message);
+
+        // Shown once, the same agent doesn't repeat it
+        app.message = None;
+        app.show_agent_warnings();
+        assert_eq!(app.message, None);
+    }
+
+    #[test]
+    fn two_digit_column_numbers_stay_over_their_cells() {
+        let mut app = App::new().unwrap();
+        app.select_config(GameConfigPreset::Huge.into_config());
+        app.reset();
+        let buffer = crate::render_to_buffer(&mut app, 160, 50).unwrap();
+        let lines: Vec<Vec<char>> = crate::buffer_lines(&buffer)
+            .iter()
+            .map(|line| line.chars().collect())
+            .collect();
+
+        // Cells sit between the joints of the bottom border, one character per cell
+        let bottom = lines
+            .iter()
+            .find(|line| line.contains(&'└') && line.contains(&'┘'))
+            .unwrap();
+        let dividers: Vec<usize> = (0..bottom.len())
+            .filter(|&x| matches!(bottom[x], '└' | '┴' | '┘'))
+            .collect();
+        assert_eq!(dividers.len(), 11);
+
+        let header = lines
+            .iter()
+            .find(|line| line.iter().collect::<String>().contains("9  10"))
+            .unwrap();
+        for (col, cell) in dividers.windows(2).enumerate() {
+            assert_eq!(cell[1] - cell[0], 4);
+            let label: String = header[cell[0] + 1..cell[1]].iter().collect();
+            assert_eq!(
+                label.trim(),
+                column_label(col).to_string(),
+                "column {}",
+                col
+            );
+            assert_eq!(header.get(cell[1]).copied().unwrap_or(' '), ' ');
+        }
+    }
+
+    #[test]
+    fn panning_moves_the_view_of_a_wide_board_and_stops_at_the_edges() {
+        let mut app = App::new().unwrap();
+        app.select_config(GameConfigPreset::Huge.into_config());
+        app.reset();
+        // First and last column in view, from the indicator under the board
+        let shown = |app: &mut App| {
+            let lines = crate::buffer_lines(&crate::render_to_buffer(app, 40, 60).unwrap());
+            let indicator = lines
+                .iter()
+                .find_map(|line| Some(line.split_once("columns ")?.1.split_once(" of 10")?.0))
+                .expect("the whole board is in view")
+                .to_string();
+            let (first, last) = indicator.split_once('-').unwrap();
+            (
+                first.parse::<usize>().unwrap(),
+                last.parse::<usize>().unwrap(),
+            )
+        };
+
+        let (first, last) = shown(&mut app);
+        assert_eq!(first, 1);
+        assert!(last < 10);
+        let width = last - first;
+        app.pan_board(1);
+        assert_eq!(shown(&mut app), (2, last + 1));
+
+        app.pan_board(100);
+        assert_eq!(shown(&mut app), (10 - width, 10));
+        app.pan_board(1);
+        assert_eq!(shown(&mut app), (10 - width, 10));
+
+        app.pan_board(-100);
+        assert_eq!(shown(&mut app), (1, last));
+    }
+
+    #[test]
+    fn the_eval_bar_fills_with_yellows_share() {
+        let mut app = App::new().unwrap();
+        let rendered = |app: &mut App| {
+            let buffer = crate::render_to_buffer(app, 120, 40).unwrap();
+            let lines = crate::buffer_lines(&buffer);
+            (buffer, lines)
+        };
+        app.show_eval_bar = true;
+        let (_, lines) = rendered(&mut app);
+        assert!(lines.iter().any(|line| line.contains("No evaluation")));
+
+        app.eval_log.push(0.5);
+        let (buffer, lines) = rendered(&mut app);
+        let y = lines
+            .iter()
+            .position(|line| line.contains("Yellow  75%"))
+            .unwrap() as u16;
+        let colors: Vec<Color> = (buffer.area.left()..buffer.area.right())
+            .filter_map(|x| buffer.cell((x, y)))
+            .filter(|cell| cell.symbol() == ratatui::symbols::line::HORIZONTAL)
+            .map(|cell| cell.fg)
+            .collect();
+        let yellow = colors
+            .iter()
+            .filter(|&&color| color == Color::Yellow)
+            .count();
+        let red = colors.iter().filter(|&&color| color == Color::Red).count();
+        let fill = yellow as f64 / (yellow + red) as f64;
+        assert!((fill - eval_bar_fill(0.5)).abs() < 0.05, "{}", fill);
+
+        app.show_eval_bar = false;
+        let (_, lines) = rendered(&mut app);
+        assert!(!lines.iter().any(|line| line.contains("Yellow  75%")));
+    }
+
+    #[test]
+    fn the_coverage_strip_marks_known_and_novel_moves() {
+        let mut app = App::new().unwrap();
+        app.coverage = vec![
+            (Player::Yellow, true),
+            (Player::Red, false),
+            (Player::Yellow, false),
+        ];
+        let strip = |app: &mut App| {
+            let buffer = crate::render_to_buffer(app, 120, 40).unwrap();
+            let lines = crate::buffer_lines(&buffer);
+            let y = lines
+                .iter()
+                .position(|line| line.contains("Q coverage: "))?;
+            let line = &lines[y];
+            let x = line.find("Q coverage: ").unwrap() + "Q coverage: ".len();
+            let x = line[..x].chars().count() as u16;
+            let marks: Vec<(String, Color)> = (x..x + 3)
+                .map(|x| {
+                    let cell = &buffer[(x, y as u16)];
+                    (cell.symbol().to_string(), cell.fg)
+                })
+                .collect();
+            Some((line.clone(), marks))
+        };
+        assert_eq!(strip(&mut app), None);
+
+        app.show_coverage = true;
+        let (line, marks) = strip(&mut app).unwrap();
+        assert!(line.contains("●○○ 1/3 known"), "{}", line);
+        assert_eq!(
+            marks,
+            [
+                ("●".to_string(), Color::Yellow),
+                ("○".to_string(), Color::Red),
+                ("○".to_string(), Color::Yellow),
+            ]
+        );
+    }
+
+    // Scores every column by its index, counting how often it is asked to
+    struct CountingEvaluator(Arc<AtomicUsize>);
+
+    impl Agent for CountingEvaluator {
+        fn get_action(&mut self, _board: &Game, _event: Option<Event>) -> Option<usize> {
+            None
+        }
+
+        fn get_type(&self) -> String {
+            "Counting".to_string()
+        }
+
+        fn evaluate_columns(&self, board: &Game) -> Vec<(usize, i32)> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            board
+                .valid_moves()
+                .into_iter()
+                .map(|col| (col, col as i32 * 10))
+                .collect()
+        }
+
+        fn is_human(&self) -> bool {
+            false
+        }
+
+        fn learn(&mut self, _board: &Game, _player: Player) {}
+    }
+
+    #[test]
+    fn the_heatmap_shows_each_columns_score_and_evaluates_a_position_once() {
+        let mut app = App::new().unwrap();
+        let evaluations = Arc::new(AtomicUsize::new(0));
+        app.yellow_agent = Box::new(CountingEvaluator(Arc::clone(&evaluations)));
+        app.red_agent = Box::new(CountingEvaluator(Arc::clone(&evaluations)));
+        let score_row = |app: &mut App| {
+            let lines = crate::buffer_lines(&crate::render_to_buffer(app, 120, 40).unwrap());
+            lines
+                .iter()
+                .find(|line| line.contains(" 0 ") && line.contains(" 60"))
+                .cloned()
+        };
+        assert_eq!(score_row(&mut app), None);
+
+        app.show_heatmap = true;
+        app.prepare_column_scores();
+        let row = score_row(&mut app).unwrap();
+        let shown: Vec<i32> = row
+            .split_whitespace()
+            .filter_map(|word| word.parse().ok())
+            .collect();
+        assert_eq!(shown, [0, 10, 20, 30, 40, 50, 60]);
+        assert_eq!(evaluations.load(Ordering::Relaxed), 1);
+
+        // Going back to a position already scored, or drawing it again, reuses its scores
+        app.game.place(3);
+        app.prepare_column_scores();
+        app.game.undo();
+        app.prepare_column_scores();
+        app.game.redo();
+        app.prepare_column_scores();
+        score_row(&mut app).unwrap();
+        assert_eq!(evaluations.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn fog_draws_the_ais_pieces_as_neutral_discs_until_the_game_ends() {
+        let mut app = App::new().unwrap();
+        // Colors of the pieces on the board, row by row from the top
+        let pieces = |app: &mut App| {
+            let buffer = crate::render_to_buffer(app, 120, 40).unwrap();
+            let grid = app.grid_area;
+            let mut pieces = Vec::new();
+            for y in grid.top()..grid.bottom() {
+                for x in grid.left()..grid.right() {
+                    let cell = &buffer[(x, y)];
+                    if cell.symbol() == "●" {
+                        pieces.push(cell.fg);
+                    }
+                }
+            }
+            pieces
+        };
+        app.resolve_action(Player::Yellow, true, Some(0));
+        app.resolve_action(Player::Red, false, Some(6));
+        assert_eq!(pieces(&mut app), [Color::Yellow, Color::Red]);
+
+        app.fog = true;
+        assert_eq!(pieces(&mut app), [Color::Yellow, Color::Gray]);
+
+        // Neither side is hidden without an AI opponent
+        app.set_agent(Player::Red, Agents::Human);
+        assert_eq!(pieces(&mut app), [Color::Yellow, Color::Red]);
+
+        // Revealed once the game is over
+        app.set_agent(Player::Red, Agents::Minimax(1));
+        for col in [0, 6, 0, 6, 0] {
+            app.game.place(col);
+        }
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+        let shown = pieces(&mut app);
+        assert_eq!(shown.len(), 7);
+        assert!(!shown.contains(&Color::Gray), "{:?}", shown);
+    }
+
+    #[test]
+    fn the_summary_totals_the_games_played_this_session() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        app.set_agent(Player::Red, Agents::Rules);
+        app.reset();
+        let greedy = app.yellow_agent.get_type();
+        let rules = app.red_agent.get_type();
+        let play = |app: &mut App, cols: &[usize]| {
+            app.reset();
+            for &col in cols {
+                app.resolve_action(app.game.current_player(), false, Some(col));
+            }
+        };
+        assert_eq!(app.session.games, 0);
+
+        // Yellow wins up column 1, then Red up column 2
+        play(&mut app, &[0, 1, 0, 1, 0, 1, 0]);
+        play(&mut app, &[0, 1, 0, 1, 0, 1, 2, 1]);
+        // Swapped sides, Greedy wins as Red
+        app.set_agent(Player::Yellow, Agents::Rules);
+        app.set_agent(Player::Red, Agents::Greedy);
+        play(&mut app, &[0, 1, 0, 1, 0, 1, 2, 1]);
+
+        let session = &app.session;
+        assert_eq!(
+            (
+                session.games,
+                session.yellow_wins,
+                session.red_wins,
+                session.draws
+            ),
+            (3, 1, 2, 0)
+        );
+        assert_eq!(
+            session.agent_records.get(&greedy),
+            Some(&Record {
+                wins: 2,
+                losses: 1,
+                draws: 0
+            })
+        );
+        assert_eq!(
+            session.agent_records.get(&rules),
+            Some(&Record {
+                wins: 1,
+                losses: 2,
+                draws: 0
+            })
+        );
+
+        app.summary_open = true;
+        let lines = crate::buffer_lines(&crate::render_to_buffer(&mut app, 120, 40).unwrap());
+        for expected in [
+            "Games played: 3".to_string(),
+            "Red wins: 2".to_string(),
+            format!("{}: 2/1/0", greedy),
+            format!("{}: 1/2/0", rules),
+        ] {
+            assert!(
+                lines.iter().any(|line| line.contains(&expected)),
+                "{}",
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn the_footer_hints_follow_the_menu_and_hide_on_short_terminals() {
+        let mut app = App::new().unwrap();
+        let footer = |app: &mut App, height: u16| {
+            let lines = crate::buffer_lines(&crate::render_to_buffer(app, 120, height).unwrap());
+            lines.last().cloned().unwrap_or_default()
+        };
+        let playing = footer(&mut app, 40);
+        assert!(playing.contains("Drop <column number>"), "{}", playing);
+        assert!(!playing.contains("Select <Enter>"), "{}", playing);
+
+        app.menu_open = true;
+        let menu = footer(&mut app, 40);
+        assert!(menu.contains("Select <Enter>"), "{}", menu);
+        assert!(!menu.contains("Drop <column number>"), "{}", menu);
+        assert_ne!(playing, menu);
+
+        let short = footer(&mut app, FOOTER_MIN_HEIGHT - 1);
+        assert!(!short.contains("Quit <q>"), "{}", short);
+    }
+
+    #[test]
+    fn logged_evaluations_trend_toward_the_winner() {
+        for (winner, loser) in [(Player::Yellow, Player::Red), (Player::Red, Player::Yellow)] {
+            let mut app = App::new().unwrap();
+            app.set_agent(winner, Agents::Minimax(6));
+            app.set_agent(loser, Agents::Minimax(2));
+            app.reset();
+            while *app.game.state() == GameState::InProgress {
+                app.step(None).unwrap();
+                app.wait_for_search();
+            }
+            assert_eq!(*app.game.state(), GameState::Won(winner));
+
+            // From Yellow's perspective throughout, so the winner's side is the same sign
+            // whichever agent logged it
+            let toward_winner: Vec<f64> = app
+                .eval_log
+                .iter()
+                .map(|eval| match winner {
+                    Player::Yellow => *eval,
+                    Player::Red => -eval,
+                })
+                .collect();
+            let mean = |evals: &[f64]| evals.iter().sum::<f64>() / evals.len() as f64;
+            let (opening, ending) = toward_winner.split_at(toward_winner.len() / 2);
+            assert!(mean(ending) > mean(opening), "{:?}", app.eval_log);
+            assert!(*toward_winner.last().unwrap() > 0.9, "{:?}", app.eval_log);
+        }
+    }
+
+    #[test]
+    fn the_column_numbers_follow_the_header_setting_and_keep_the_board_in_place() {
+        let mut app = App::new().unwrap();
+        // Whether the column numbers are shown, and the line the board's top row is drawn on
+        let header = |app: &mut App| {
+            let lines = crate::buffer_lines(&crate::render_to_buffer(app, 120, 40).unwrap());
+            let shown = lines.iter().any(|line| line.contains("1   2   3   4"));
+            let top_row = lines
+                .iter()
+                .position(|line| line.contains("· │ ·"))
+                .unwrap();
+            (shown, top_row)
+        };
+        let (shown, top_row) = header(&mut app);
+        assert!(shown);
+        // Always shown, on the AI's turn too
+        app.resolve_action(Player::Yellow, true, Some(3));
+        assert_eq!(header(&mut app), (true, top_row));
+
+        app.header_mode = HeaderMode::HumanTurn;
+        assert_eq!(header(&mut app), (false, top_row));
+        app.resolve_action(Player::Red, false, Some(3));
+        assert_eq!(header(&mut app), (true, top_row));
+        // Nobody is to move once the game is over
+        for col in [0, 6, 0, 6, 0, 6, 0] {
+            app.game.place(col);
+        }
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+        assert_eq!(header(&mut app), (false, top_row));
+    }
+
+    #[test]
+    fn the_config_line_names_the_preset_and_its_connect_length() {
+        let mut app = App::new().unwrap();
+        for (index, expected) in [
+            "Standard 7x6, connect 4",
+            "Small 4x4, connect 3",
+            "Large 8x8, connect 5",
+            "Huge 10x10, connect 6",
+            "Pop Out 7x6, connect 4, pop out",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            assert_eq!(
+                GameConfigPreset::from_index(index),
+                app.config_list.selected_game
+            );
+            assert_eq!(app.config_description(), expected);
+            // Wide enough for the info panel to keep the line unwrapped
+            let lines = crate::buffer_lines(&crate::render_to_buffer(&mut app, 200, 40).unwrap());
+            let shown = format!("Game Config: {}", expected);
+            assert!(lines.iter().any(|line| line.contains(&shown)), "{}", shown);
+            app.cycle_config();
+        }
+    }
+
+    #[test]
+    fn the_forced_opening_is_played_before_the_agents_take_over() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Minimax(2));
+        app.set_agent(Player::Red, Agents::Minimax(2));
+        app.opening = vec![3, 2, 3];
+        app.reset();
+        let config = *app.game.config();
+        assert_eq!(
+            app.game.to_key(),
+            Game::from_notation(config, "434").unwrap().to_key()
+        );
+        assert_eq!(app.message, None);
+
+        // Red's agent moves from the resulting position
+        app.step(None).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.plies(), 4);
+        app.step(None).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.plies(), 5);
+
+        // Every game starts the same way
+        app.reset();
+        assert_eq!(
+            app.game.to_key(),
+            Game::from_notation(config, "434").unwrap().to_key()
+        );
+
+        // The moves before an illegal one are kept
+        app.opening = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        app.reset();
+        assert_eq!(
+            app.game.to_key(),
+            Game::from_notation(config, "111111").unwrap().to_key()
+        );
+        let message = app.message.clone().unwrap_or_default();
+        assert!(message.contains("Opening move 7 (column 1)"), "{}", message);
+    }
+
+    #[test]
+    fn ai_autoplay_waits_for_the_startup_delay_after_each_reset() {
+        let mut app = App::new().unwrap();
+        app.startup_delay = Duration::from_millis(150);
+        app.reset();
+        assert!(!app.autoplay_ready());
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(app.autoplay_ready());
+
+        // Every reset starts the pause over
+        app.reset();
+        assert!(!app.autoplay_ready());
+
+        app.startup_delay = Duration::ZERO;
+        app.reset();
+        assert!(app.autoplay_ready());
+    }
+
+    #[test]
+    fn the_practice_shortcut_sets_up_human_against_the_trained_rl_agent() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        app.menu_open = true;
+        app.cycle_config();
+        assert_eq!(app.config_list.selected_game, GameConfigPreset::Small);
+
+        assert_eq!(app.practice_vs_rl(), RunSpeed::Manual);
+        assert_eq!(app.yellow_agent_type, Agents::Human);
+        assert_eq!(app.red_agent_type, Agents::RL(0.2, false));
+        assert!(app.yellow_agent.is_human());
+        // The agents are built for the standard board, so Red loads its Q-table
+        assert_eq!(app.config_list.selected_game, GameConfigPreset::Standard);
+        assert_eq!(*app.game.config(), GameConfig::default());
+        assert_eq!(app.game.plies(), 0);
+        assert!(!app.menu_open);
+    }
+
+    // Play out the game with the current agents
+    fn play_to_the_end(app: &mut App) {
+        while *app.game.state() == GameState::InProgress {
+            app.step(None).unwrap();
+            app.wait_for_search();
+        }
+    }
+
+    #[test]
+    fn ai_games_auto_reset_and_keep_counting_without_input() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Minimax(2));
+        app.set_agent(Player::Red, Agents::Greedy);
+        app.auto_reset = true;
+        app.alternate_starter = true;
+        app.reset();
+
+        let mut starters = Vec::new();
+        for games in 1..=3 {
+            starters.push(app.game.current_player());
+            play_to_the_end(&mut app);
+            assert_eq!(app.session.games, games);
+            // The result stays up for a moment first
+            assert!(!app.auto_reset_due());
+            app.ended_at = Some(Instant::now() - AUTO_RESET_DELAY);
+            assert!(app.auto_reset_due());
+            app.reset();
+            assert_eq!(*app.game.state(), GameState::InProgress);
+            assert!(!app.auto_reset_due());
+        }
+        assert_eq!(starters, [Player::Yellow, Player::Red, Player::Yellow]);
+
+        // Off, a finished game waits for a reset
+        app.auto_reset = false;
+        play_to_the_end(&mut app);
+        app.ended_at = Some(Instant::now() - AUTO_RESET_DELAY);
+        assert!(!app.auto_reset_due());
+        assert_eq!(app.session.games, 4);
+    }
+
+    #[test]
+    fn an_idle_fresh_game_turns_into_the_demo_until_a_key_is_pressed() {
+        let mut app = App::new().unwrap();
+        let idle = app.time_until_attract().unwrap();
+        assert!(idle > Duration::ZERO && idle <= ATTRACT_IDLE);
+
+        // Nothing to wait for once the game has started
+        app.resolve_action(Player::Yellow, true, Some(3));
+        assert_eq!(app.time_until_attract(), None);
+
+        app.reset();
+        app.last_input = Instant::now() - ATTRACT_IDLE;
+        assert_eq!(app.time_until_attract(), Some(Duration::ZERO));
+        app.start_attract();
+        assert!(app.attract_active());
+        assert_eq!(app.time_until_attract(), None);
+        assert!(!app.yellow_agent.is_human() && !app.red_agent.is_human());
+
+        // The same matchup plays the same game every time
+        play_to_the_end(&mut app);
+        let demo = app.game.to_key();
+        app.reset();
+        play_to_the_end(&mut app);
+        assert_eq!(app.game.to_key(), demo);
+        assert_eq!(app.session.games, 0);
+
+        // A key press ends it and brings the previous agents back, the key is used up
+        assert!(app.register_input());
+        assert!(!app.attract_active());
+        assert_eq!(app.yellow_agent_type, Agents::Human);
+        assert_eq!(app.red_agent_type, Agents::Minimax(1));
+        assert!(!app.register_input());
+        assert!(app.time_until_attract().unwrap() > Duration::from_secs(1));
+    }
+
+    #[test]
+    fn the_config_picker_shows_each_presets_connect_length() {
+        let mut app = App::new().unwrap();
+        app.menu_open = true;
+        let lines = crate::buffer_lines(&crate::render_to_buffer(&mut app, 200, 50).unwrap());
+        for (preset, connect_length) in [
+            (GameConfigPreset::Standard, 4),
+            (GameConfigPreset::Small, 3),
+            (GameConfigPreset::Large, 5),
+            (GameConfigPreset::Huge, 6),
+            (GameConfigPreset::PopOut, 4),
+        ] {
+            let config = preset.into_config();
+            let entry = format!(
+                "{} ({}x{}, connect {}",
+                preset, config.cols, config.rows, connect_length
+            );
+            assert!(lines.iter().any(|line| line.contains(&entry)), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn stepping_several_moves_plays_exactly_that_many_or_stops_at_the_end() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Minimax(2));
+        app.set_agent(Player::Red, Agents::Minimax(2));
+        app.reset();
+        app.step_moves(5).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.plies(), 5);
+        app.step_moves(3).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.plies(), 8);
+
+        // Yellow wins with the first of the moves asked for, the rest are dropped
+        app.game = Game::from_notation(*app.game.config(), "121212").unwrap();
+        app.step_moves(5).unwrap();
+        app.wait_for_search();
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+        assert_eq!(app.game.plies(), 7);
+        assert_eq!(app.queued_moves, 0);
+
+        // And so are the moves left when a human is to move
+        app.reset();
+        app.set_agent(Player::Red, Agents::Human);
+        app.step_moves(5).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.plies(), 1);
+        assert_eq!(app.queued_moves, 0);
+    }
+
+    #[test]
+    fn scoring_by_agent_keeps_a_players_wins_together_across_a_color_swap() {
+        let mut app = App::new().unwrap();
+        let play = |app: &mut App, yellow: Agents, red: Agents, cols: &[usize]| {
+            app.set_agent(Player::Yellow, yellow);
+            app.set_agent(Player::Red, red);
+            app.reset();
+            for &col in cols {
+                app.resolve_action(app.game.current_player(), false, Some(col));
+            }
+        };
+        // Greedy wins as Yellow, then again as Red after swapping sides
+        play(
+            &mut app,
+            Agents::Greedy,
+            Agents::Rules,
+            &[0, 1, 0, 1, 0, 1, 0],
+        );
+        play(
+            &mut app,
+            Agents::Rules,
+            Agents::Greedy,
+            &[0, 1, 0, 1, 0, 1, 2, 1],
+        );
+        let score = |app: &App| app.score_line().unwrap().to_string();
+
+        assert_eq!(score(&app), "Score: Yellow 1 - Red 1 (0 drawn)");
+        app.score_mode = ScoreMode::Identity;
+        let greedy = app.red_agent.get_type();
+        let rules = app.yellow_agent.get_type();
+        assert_eq!(
+            score(&app),
+            format!("Score (W/L/D): {} 0/2/0, {} 2/0/0", rules, greedy)
+        );
+    }
+
+    #[test]
+    fn thinking_time_goes_to_the_player_whose_turn_it_was() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Red, Agents::Human);
+        app.reset();
+        const SLACK: Duration = Duration::from_millis(500);
+        let about = |time: Duration, seconds: u64| {
+            time >= Duration::from_secs(seconds) && time < Duration::from_secs(seconds) + SLACK
+        };
+        // Turns of 3s and 2s for Yellow and 7s for Red, with the turn start moved back in time
+        for (seconds, col) in [(3, 0), (7, 1), (2, 0)] {
+            app.turn_started -= Duration::from_secs(seconds);
+            app.resolve_action(app.game.current_player(), true, Some(col));
+        }
+        assert!(about(app.thinking_time(Player::Yellow), 5));
+        // Red's running turn counts toward Red only
+        app.turn_started -= Duration::from_secs(4);
+        assert!(about(app.thinking_time(Player::Red), 11));
+        assert!(about(app.thinking_time(Player::Yellow), 5));
+        assert_eq!((app.yellow_moves, app.red_moves), (2, 1));
+
+        // The clocks stop once the game is over
+        for col in [1, 0, 1, 0] {
+            app.resolve_action(app.game.current_player(), true, Some(col));
+        }
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+        let red = app.thinking_time(Player::Red);
+        app.turn_started -= Duration::from_secs(10);
+        assert_eq!(app.thinking_time(Player::Red), red);
+        assert!(about(red, 11));
+
+        app.reset();
+        assert!(app.thinking_time(Player::Yellow) < SLACK);
+        assert_eq!(app.thinking_time(Player::Red), Duration::ZERO);
+        assert_eq!((app.yellow_moves, app.red_moves), (0, 0));
+    }
+
+    #[test]
+    fn loading_a_position_plays_from_it_or_keeps_the_prompt_open_with_the_error() {
+        let mut app = App::new().unwrap();
+        app.resolve_action(Player::Yellow, true, Some(0));
+        let before = app.game.to_key();
+
+        // Column 8 doesn't exist on the standard board
+        app.position_prompt = Some("448".to_string());
+        app.load_position();
+        assert_eq!(app.game.to_key(), before);
+        assert_eq!(app.position_prompt.as_deref(), Some("448"));
+        let message = app.message.clone().unwrap_or_default();
+        assert!(
+            message.starts_with("Could not load position"),
+            "{}",
+            message
+        );
+
+        app.position_prompt = Some("4453".to_string());
+        app.load_position();
+        assert_eq!(app.position_prompt, None);
+        assert_eq!(app.message, None);
+        let config = *app.game.config();
+        assert_eq!(
+            app.game.to_key(),
+            Game::from_notation(config, "4453").unwrap().to_key()
+        );
+        // The current agents play on from it
+        assert!(app.current_player_is_human());
+        app.resolve_action(Player::Yellow, true, Some(2));
+        app.step(None).unwrap();
+        app.wait_for_search();
+        assert_eq!(app.game.plies(), 6);
+    }
+
+    #[test]
+    fn a_game_with_a_human_waits_for_a_key_while_an_ai_game_auto_resets() {
+        let mut app = App::new().unwrap();
+        app.auto_reset = true;
+        let finish = |app: &mut App| {
+            app.reset();
+            for col in [0, 1, 0, 1, 0, 1, 0] {
+                let is_human = app.current_player_is_human();
+                app.resolve_action(app.game.current_player(), is_human, Some(col));
+            }
+            assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+            app.ended_at = Some(Instant::now() - AUTO_RESET_DELAY);
+        };
+
+        // Yellow is the human, who won
+        finish(&mut app);
+        assert!(app.awaiting_human_continue());
+        assert!(!app.auto_reset_due());
+        assert_eq!(app.game_over_banner().unwrap().to_string(), "You win!");
+
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        finish(&mut app);
+        assert!(!app.awaiting_human_continue());
+        assert!(app.auto_reset_due());
+        assert_eq!(app.game_over_banner(), None);
+    }
+
+    #[test]
+    fn left_aligned_and_centered_boards_sit_at_different_places() {
+        let mut app = App::new().unwrap();
+        // Column of the board's bottom left corner
+        let left_edge = |app: &mut App, width: u16| {
+            let lines = crate::buffer_lines(&crate::render_to_buffer(app, width, 40).unwrap());
+            lines
+                .iter()
+                .find_map(|line| line.chars().position(|c| c == '└'))
+                .unwrap()
+        };
+        let centered = left_edge(&mut app, 120);
+        app.board_alignment = Alignment::Left;
+        let left = left_edge(&mut app, 120);
+        assert!(left < centered, "{} {}", left, centered);
+
+        app.board_padding = 4;
+        assert_eq!(left_edge(&mut app, 120), left + 4);
+
+        // The padding gives way on a terminal too narrow for it, keeping every column in view
+        let lines = crate::buffer_lines(&crate::render_to_buffer(&mut app, 50, 40).unwrap());
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("1   2   3   4   5   6   7"))
+        );
+    }
+
+    #[test]
+    fn a_full_reset_restores_the_defaults_a_game_reset_keeps() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        app.set_agent(Player::Red, Agents::Rules);
+        app.cycle_config();
+        app.fog = true;
+        // Three in a row wins on the small board
+        for col in [0, 1, 0, 1, 0] {
+            app.resolve_action(app.game.current_player(), false, Some(col));
+        }
+        assert_eq!(app.session.games, 1);
+
+        // A new game keeps the agents, board and toggles
+        app.reset();
+        assert_eq!(app.yellow_agent_type, Agents::Greedy);
+        assert_eq!(app.red_agent_type, Agents::Rules);
+        assert_eq!(app.config_list.selected_game, GameConfigPreset::Small);
+        assert_eq!(*app.game.config(), GameConfigPreset::Small.into_config());
+        assert!(app.fog);
+
+        // Back to a fresh start, the scoreboard stays
+        app.reset_to_defaults().unwrap();
+        let defaults = App::new().unwrap();
+        assert_eq!(app.yellow_agent_type, defaults.yellow_agent_type);
+        assert_eq!(app.red_agent_type, defaults.red_agent_type);
+        assert!(app.yellow_agent.is_human());
+        assert_eq!(app.config_list.selected_game, GameConfigPreset::Standard);
+        assert_eq!(*app.game.config(), GameConfig::default());
+        assert!(!app.fog);
+        assert_eq!(app.session.games, 1);
+    }
+
+    #[test]
+    fn the_highlighted_column_is_the_move_the_ai_plays_next() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Minimax(5));
+        app.set_agent(Player::Red, Agents::Human);
+        app.reset();
+        // Column whose number is drawn reversed in the header
+        let highlighted = |app: &mut App| {
+            let buffer = crate::render_to_buffer(app, 120, 40).unwrap();
+            let labels: Vec<usize> = buffer
+                .content()
+                .iter()
+                .filter(|cell| cell.modifier.contains(ratatui::style::Modifier::REVERSED))
+                .filter_map(|cell| cell.symbol().parse().ok())
+                .collect();
+            match labels[..] {
+                [label] => Some(label - 1),
+                [] => None,
+                _ => panic!("{:?}", labels),
+            }
+        };
+
+        // Red always answers in the leftmost free column, until Yellow wins
+        while *app.game.state() == GameState::InProgress {
+            assert_eq!(highlighted(&mut app), None);
+            app.prepare_preview();
+            app.wait_for_search();
+            let column = highlighted(&mut app).unwrap();
+            let height = |game: &Game| game.landing_row(column);
+            let before = height(&app.game);
+            app.step(None).unwrap();
+            assert_ne!(height(&app.game), before, "column {} wasn't played", column);
+            if *app.game.state() != GameState::InProgress {
+                break;
+            }
+
+            // Nothing to preview on the human's turn
+            app.prepare_preview();
+            assert!(!app.searching());
+            assert_eq!(highlighted(&mut app), None);
+            let reply = app.game.valid_moves()[0];
+            app.resolve_action(Player::Red, true, Some(reply));
+        }
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+    }
+
+    #[test]
+    fn player_1_is_whoever_moves_first() {
+        let mut app = App::new().unwrap();
+        // Agent type and color of each numbered player in the info panel
+        let labels = |app: &mut App| {
+            let buffer = crate::render_to_buffer(app, 120, 40).unwrap();
+            let lines = crate::buffer_lines(&buffer);
+            ["Player 1 [", "Player 2 ["].map(|label| {
+                let y = lines.iter().position(|line| line.contains(label)).unwrap();
+                let line = &lines[y];
+                let start = line.find(label).unwrap();
+                let agent = line[start + label.len()..].split(']').next().unwrap();
+                let x = line[..start].chars().count() as u16;
+                (agent.to_string(), buffer[(x, y as u16)].fg)
+            })
+        };
+        let human = Agents::Human.to_string();
+        let minimax = Agents::Minimax(1).to_string();
+        assert_eq!(
+            labels(&mut app),
+            [
+                (human.clone(), Color::Yellow),
+                (minimax.clone(), Color::Red)
+            ]
+        );
+
+        app.starter = Player::Red;
+        app.reset();
+        assert_eq!(app.game.current_player(), Player::Red);
+        assert_eq!(
+            labels(&mut app),
+            [(minimax, Color::Red), (human, Color::Yellow)]
+        );
+    }
+
+    #[test]
+    fn a_human_joining_at_a_timed_speed_switches_to_manual() {
+        let mut app = App::new().unwrap();
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        app.reset();
+        assert_eq!(app.speed_for_turn(RunSpeed::Fast), RunSpeed::Fast);
+
+        // The player to move becomes human mid-game
+        app.resolve_action(Player::Yellow, false, Some(3));
+        app.set_agent(Player::Red, Agents::Human);
+        assert_eq!(app.speed_for_turn(RunSpeed::Fast), RunSpeed::Manual);
+        assert_eq!(app.speed_for_turn(RunSpeed::Instant), RunSpeed::Manual);
+
+        // The AI's turns keep the speed, and so does a finished game
+        app.resolve_action(Player::Red, true, Some(3));
+        assert_eq!(app.speed_for_turn(RunSpeed::Fast), RunSpeed::Fast);
+        app.game = Game::from_notation(*app.game.config(), "21212131").unwrap();
+        assert_eq!(app.game.current_player(), Player::Red);
+        assert_eq!(app.speed_for_turn(RunSpeed::Slow), RunSpeed::Slow);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn a_forced_result_ends_the_game_like_a_real_one() {
+        let mut app = App::new().unwrap();
+        app.resolve_action(Player::Yellow, true, Some(3));
+        app.force_result(GameState::Draw);
+        assert_eq!(*app.game.state(), GameState::Draw);
+        assert_eq!(app.game.plies(), 1);
+        assert_eq!((app.session.games, app.session.draws), (1, 1));
+        assert_eq!(app.game_over_banner().unwrap().to_string(), "Draw");
+
+        // Only a running game can be ended
+        app.force_result(GameState::Won(Player::Red));
+        assert_eq!(*app.game.state(), GameState::Draw);
+        assert_eq!(app.session.games, 1);
+
+        app.reset();
+        app.force_result(GameState::Won(Player::Yellow));
+        assert_eq!(app.game.get_winning_combination(), None);
+        assert_eq!((app.session.games, app.session.yellow_wins), (2, 1));
+        assert_eq!(app.game_over_banner().unwrap().to_string(), "You win!");
+        assert_eq!(
+            app.score_line().unwrap().to_string(),
+            "Score: Yellow 1 - Red 0 (1 drawn)"
+        );
+
+        // Between AIs the next game starts on its own
+        app.set_agent(Player::Yellow, Agents::Greedy);
+        app.auto_reset = true;
+        app.reset();
+        app.force_result(GameState::Won(Player::Red));
+        app.ended_at = Some(Instant::now() - AUTO_RESET_DELAY);
+        assert!(app.auto_reset_due());
+        assert_eq!(app.session.red_wins, 1);
     }
 }
@@ -0,0 +1,434 @@
+use std::fmt;
+
+use rand::seq::IndexedRandom;
+
+use crate::{
+    agent::Agents,
+    game::{Game, GameConfig, GameConfigPreset, GameState, Player, column_label},
+    solver::Solver,
+};
+
+/// An agent answered a position with a column it may not play.
+pub struct IllegalMove {
+    pub agent: String,
+    pub preset: GameConfigPreset,
+    pub board: Game,
+    pub action: Option<usize>,
+}
+
+impl fmt::Display for IllegalMove {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            Some(col) => format!("column {}", column_label(col)),
+            None => "no move".to_string(),
+        };
+        writeln!(
+            f,
+            "{} on {} played {} with {} to move:",
+            self.agent,
+            self.preset,
+            action,
+            self.board.current_player()
+        )?;
+        write_board(f, &self.board)
+    }
+}
+
+/// The game state and the highlighted winning line disagree with a brute force line search.
+pub struct WinMismatch {
+    pub preset: GameConfigPreset,
+    pub board: Game,
+    pub problem: String,
+}
+
+impl fmt::Display for WinMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} on {}:", self.problem, self.preset)?;
+        write_board(f, &self.board)
+    }
+}
+
+/// What an agent has to play in one of the tactical positions
+pub enum Expectation {
+    /// Any of these columns (0-indexed), e.g. the winning or blocking column
+    PlayOneOf(&'static [usize]),
+    /// Any move that keeps the draw, checked against the solver
+    HoldDraw,
+}
+
+impl Expectation {
+    /// Whether the action meets the expectation on the given board
+    pub fn met_by(&self, board: &Game, action: Option<usize>) -> bool {
+        let Some(col) = action else {
+            return false;
+        };
+        match self {
+            Expectation::PlayOneOf(cols) => cols.contains(&col),
+            Expectation::HoldDraw => Solver::new()
+                .move_values(board)
+                .into_iter()
+                .any(|(move_col, value)| move_col == col && value >= 0),
+        }
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expectation::PlayOneOf(cols) => {
+                let cols: Vec<String> = cols
+                    .iter()
+                    .map(|&col| column_label(col).to_string())
+                    .collect();
+                write!(f, "column {}", cols.join(" or "))
+            }
+            Expectation::HoldDraw => write!(f, "a move that holds the draw"),
+        }
+    }
+}
+
+/// An agent missed the expected move in a tactical position
+pub struct TacticalMiss {
+    pub agent: String,
+    pub motif: &'static str,
+    pub board: Game,
+    pub expected: String,
+    pub action: Option<usize>,
+}
+
+impl fmt::Display for TacticalMiss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let action = match self.action {
+            Some(col) => format!("column {}", column_label(col)),
+            None => "no move".to_string(),
+        };
+        writeln!(
+            f,
+            "{} played {} in \"{}\", expected {}:",
+            self.agent, action, self.motif, self.expected
+        )?;
+        write_board(f, &self.board)
+    }
+}
+
+// Standard board move lists for the tactical motifs, in 1-indexed notation
+const TACTICAL_NOTATIONS: [(&str, &str, Expectation); 6] = [
+    (
+        "112233",
+        "immediate horizontal win",
+        Expectation::PlayOneOf(&[3]),
+    ),
+    (
+        "121212",
+        "immediate vertical win",
+        Expectation::PlayOneOf(&[0]),
+    ),
+    (
+        "17273",
+        "must block horizontal",
+        Expectation::PlayOneOf(&[3]),
+    ),
+    ("12131", "must block vertical", Expectation::PlayOneOf(&[0])),
+    (
+        "3344",
+        "double threat on the bottom row",
+        Expectation::PlayOneOf(&[1, 4]),
+    ),
+    (
+        "4357265453231611117135565222633277",
+        "forced draw",
+        Expectation::HoldDraw,
+    ),
+];
+
+/// Canonical positions for common tactical motifs on the standard board, with a short name
+/// and the move any decent agent should find
+pub fn tactical_positions() -> Vec<(Game, &'static str, Expectation)> {
+    TACTICAL_NOTATIONS
+        .into_iter()
+        .map(|(notation, motif, expectation)| {
+            let board = Game::from_notation(GameConfig::default(), notation)
+                .expect("tactical position notation is valid");
+            (board, motif, expectation)
+        })
+        .collect()
+}
+
+/// Ask the agent for a move in every tactical position, collecting each one it gets wrong.
+/// A fresh agent is built per position so learning agents don't carry anything over
+pub fn find_tactical_misses(agent_type: Agents) -> Vec<TacticalMiss> {
+    let mut misses = Vec::new();
+
+    for (board, motif, expectation) in tactical_positions() {
+        let mut agent = agent_type
+            .clone()
+            .into_agent(board.current_player(), *board.config());
+        let action = agent.get_action(&board, None);
+        if !expectation.met_by(&board, action) {
+            misses.push(TacticalMiss {
+                agent: agent.get_type(),
+                motif,
+                expected: expectation.to_string(),
+                board,
+                action,
+            });
+        }
+    }
+
+    misses
+}
+
+// One row per line, Y and R for the pieces and # for obstacles
+fn write_board(f: &mut fmt::Formatter, board: &Game) -> fmt::Result {
+    let config = board.config();
+    for row in 0..config.rows {
+        let line: String = (0..config.cols)
+            .map(|col| match board.get_cell(row, col) {
+                Some(Player::Yellow) => 'Y',
+                Some(Player::Red) => 'R',
+                None if board.is_blocked(row, col) => '#',
+                None => '.',
+            })
+            .collect();
+        writeln!(f, "  {}", line)?;
+    }
+    Ok(())
+}
+
+/// Play random openings on every preset and ask every AI agent for a move, collecting each
+/// answer that is a full or out-of-range column, or None while moves are still available.
+/// Humans are skipped since they only move on input
+pub fn find_illegal_moves(positions_per_preset: usize) -> Vec<IllegalMove> {
+    let mut rng = rand::rng();
+    let mut failures = Vec::new();
+
+    for index in 0..GameConfigPreset::amount_of_presets() {
+        let preset = GameConfigPreset::from_index(index);
+        let config = preset.into_config();
+
+        for _ in 0..positions_per_preset {
+            let board = random_position(Game::with_config(config), &mut rng);
+            let player = board.current_player();
+
+            for agent_type in Agents::agent_types() {
+                if agent_type == Agents::Human {
+                    continue;
+                }
+                let mut agent = agent_type.into_agent(player, config);
+                let action = agent.get_action(&board, None);
+                let legal = match action {
+                    Some(col) => board.valid_moves().contains(&col),
+                    None => board.valid_moves().is_empty(),
+                };
+                if !legal {
+                    failures.push(IllegalMove {
+                        agent: agent.get_type(),
+                        preset,
+                        board: board.clone(),
+                        action,
+                    });
+                }
+            }
+        }
+    }
+
+    failures
+}
+
+/// Play random games on every preset and check after each move that the game state matches
+/// a brute force search for lines of connect_length, and that a won game highlights a real
+/// line of at least that length covering the whole run. The tracked winnable line counts are recounted the same way.
+/// Long diagonals running into the corners of the Large and Huge boards are where bounds
+/// mistakes would show up
+pub fn find_win_mismatches(games_per_preset: usize) -> Vec<WinMismatch> {
+    let mut rng = rand::rng();
+    let mut mismatches = Vec::new();
+
+    for index in 0..GameConfigPreset::amount_of_presets() {
+        let preset = GameConfigPreset::from_index(index);
+
+        for _ in 0..games_per_preset {
+            let mut board = Game::with_config(preset.into_config());
+            while *board.state() == GameState::InProgress {
+                let Some(&col) = board.valid_moves().choose(&mut rng) else {
+                    break;
+                };
+                board.place(col);
+                if let Some(problem) = win_problem(&board) {
+                    mismatches.push(WinMismatch {
+                        preset,
+                        board: board.clone(),
+                        problem,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+
+    mismatches
+}
+
+// Compare the board's own verdict with line_owner and its winnable line counts with
+// open_windows, None if they agree
+fn win_problem(board: &Game) -> Option<String> {
+    let connect_length = board.config().connect_length;
+    for player in [Player::Yellow, Player::Red] {
+        let (tracked, counted) = (board.winnable_lines(player), open_windows(board, player));
+        if tracked != counted {
+            return Some(format!(
+                "{} has {} winnable lines tracked but {} open windows",
+                player, tracked, counted
+            ));
+        }
+    }
+    match (*board.state(), line_owner(board)) {
+        (GameState::Won(winner), Some(owner)) if winner == owner => {
+            let Some(cells) = board.get_winning_combination() else {
+                return Some(format!("{} won without a winning combination", winner));
+            };
+            if board.find_winning_combination().is_none() {
+                return Some(format!(
+                    "{} has a cached winning combination the board scan can't find",
+                    winner
+                ));
+            }
+            let owned = cells
+                .iter()
+                .all(|&(row, col)| board.get_cell(row, col) == Some(winner));
+            if cells.len() < connect_length || !owned || !is_straight_line(cells) {
+                return Some(format!(
+                    "winning combination {:?} is not a line of at least {} {} pieces",
+                    cells, connect_length, winner
+                ));
+            }
+            // The whole run is highlighted, it can't be extended at either end
+            if run_continues(board, cells, winner) {
+                return Some(format!(
+                    "winning combination {:?} stops short of the full run",
+                    cells
+                ));
+            }
+            None
+        }
+        (GameState::Won(winner), owner) => Some(format!(
+            "{:?} won but the line search found a line for {:?}",
+            winner, owner
+        )),
+        (state, Some(owner)) => Some(format!(
+            "{} has {} in a row but the game is {:?}",
+            owner, connect_length, state
+        )),
+        (_, None) => None,
+    }
+}
+
+// Owner of any line of connect_length, the line iterator stops at the board edge
+fn line_owner(board: &Game) -> Option<Player> {
+    let config = board.config();
+    for row in 0..config.rows {
+        for col in 0..config.cols {
+            let Some(player) = board.get_cell(row, col) else {
+                continue;
+            };
+            for direction in Game::LINE_DIRECTIONS {
+                let run = board
+                    .line((row, col), direction, config.connect_length)
+                    .take_while(|&cell| cell == Some(player))
+                    .count();
+                if run == config.connect_length {
+                    return Some(player);
+                }
+            }
+        }
+    }
+    None
+}
+
+// Windows of the player's win length without any of the opponent's pieces
+fn open_windows(board: &Game, player: Player) -> usize {
+    let config = board.config();
+    let length = board.win_length(player);
+    let mut open = 0;
+    for row in 0..config.rows {
+        for col in 0..config.cols {
+            for direction in Game::LINE_DIRECTIONS {
+                let cells: Vec<Option<Player>> =
+                    board.line((row, col), direction, length).collect();
+                if cells.len() == length && !cells.contains(&Some(player.opponent())) {
+                    open += 1;
+                }
+            }
+        }
+    }
+    open
+}
+
+// Consecutive cells along one of the four line directions
+// Whether the player's pieces carry on past either end of a straight line of two or more cells
+fn run_continues(board: &Game, cells: &[(usize, usize)], player: Player) -> bool {
+    let (first, second, last) = (cells[0], cells[1], cells[cells.len() - 1]);
+    let direction = (
+        second.0 as i32 - first.0 as i32,
+        second.1 as i32 - first.1 as i32,
+    );
+    [(first, (-direction.0, -direction.1)), (last, direction)]
+        .into_iter()
+        .any(|((row, col), (row_dir, col_dir))| {
+            let (row, col) = (row as i32 + row_dir, col as i32 + col_dir);
+            row >= 0 && col >= 0 && board.get_cell(row as usize, col as usize) == Some(player)
+        })
+}
+
+fn is_straight_line(cells: &[(usize, usize)]) -> bool {
+    let Some(&(first_row, first_col)) = cells.first() else {
+        return false;
+    };
+    let Some(&(second_row, second_col)) = cells.get(1) else {
+        return true;
+    };
+    let row_dir = second_row as isize - first_row as isize;
+    let col_dir = second_col as isize - first_col as isize;
+    if row_dir.abs() > 1 || col_dir.abs() > 1 || (row_dir, col_dir) == (0, 0) {
+        return false;
+    }
+    cells.iter().enumerate().all(|(i, &(row, col))| {
+        row as isize == first_row as isize + row_dir * i as isize
+            && col as isize == first_col as isize + col_dir * i as isize
+    })
+}
+
+// Random moves from the empty board, keeping the last position that is still in progress
+fn random_position(mut board: Game, rng: &mut impl rand::Rng) -> Game {
+    let cells = board.config().rows * board.config().cols;
+    let plies = rng.random_range(0..cells);
+    for _ in 0..plies {
+        let Some(&col) = board.valid_moves().choose(rng) else {
+            break;
+        };
+        match board.with_move(col) {
+            Some(next) if *next.state() == GameState::InProgress => board = next,
+            _ => break,
+        }
+    }
+    board
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_agent_plays_legal_moves_on_every_preset() {
+        let failures = find_illegal_moves(1);
+        let report: Vec<String> = failures.iter().map(ToString::to_string).collect();
+        assert!(failures.is_empty(), "{}", report.join("\n"));
+    }
+
+    #[test]
+    fn minimax_five_solves_every_tactical_position() {
+        let misses = find_tactical_misses(Agents::Minimax(5));
+        let report: Vec<String> = misses.iter().map(ToString::to_string).collect();
+        assert!(misses.is_empty(), "{}", report.join("\n"));
+    }
+}
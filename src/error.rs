@@ -0,0 +1,26 @@
+use std::fmt;
+
+/// Errors raised by the game and agent setup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Connect4Error {
+    /// No agent is registered under the given name
+    UnknownAgent(String),
+    /// A move in a notation string is not a legal column at that point of the game
+    InvalidNotation { index: usize, token: String },
+    /// A custom board size that can't be played, with the reason why
+    InvalidConfig(String),
+}
+
+impl fmt::Display for Connect4Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Connect4Error::UnknownAgent(name) => write!(f, "unknown agent type '{}'", name),
+            Connect4Error::InvalidNotation { index, token } => {
+                write!(f, "move {} ('{}') is not a legal column here", index, token)
+            }
+            Connect4Error::InvalidConfig(problem) => write!(f, "{}", problem),
+        }
+    }
+}
+
+impl std::error::Error for Connect4Error {}
@@ -0,0 +1,226 @@
+use crate::game::{Game, GameConfig, Player};
+
+/// Static evaluation of a non-terminal position, used at the search horizon.
+pub trait Evaluator: Send {
+    /// Score from the root player's perspective, higher is better for them.
+    /// The side to move alternates at the leaves, so the perspective has to come from the search
+    /// rather than the board
+    fn evaluate(&self, board: &Game, root_player: Player) -> i32;
+}
+
+/// The default evaluation: center control, piece placement, adjacency, vertical runs and on the
+/// standard board threat parity. Swapping the colors negates the score
+pub struct ClassicEvaluator;
+
+impl Evaluator for ClassicEvaluator {
+    fn evaluate(&self, board: &Game, root_player: Player) -> i32 {
+        self.player_score(board, root_player) - self.player_score(board, root_player.opponent())
+    }
+}
+
+impl ClassicEvaluator {
+    // Open vertical runs, per stacked piece
+    const VERTICAL_RUN: i32 = 2;
+    // Vertical run one short of a line, the opponent still gets to cap it
+    const VERTICAL_THREAT: i32 = 20;
+    // Vertical run one short of a line with its owner to move, it cannot be stopped
+    const VERTICAL_UNBLOCKABLE: i32 = 200;
+    // Threat on a row of its owner's parity, the opponent is eventually forced to allow it
+    const PARITY_THREAT: i32 = 30;
+    // Threat on a row of the opponent's parity, it rarely comes to anything on its own
+    const OFF_PARITY_THREAT: i32 = 5;
+
+    /// Positional score of one player's pieces
+    fn player_score(&self, board: &Game, color: Player) -> i32 {
+        let mut score = 0;
+        let center_col = board.config().cols / 2;
+
+        for row in 0..board.config().rows {
+            for col in 0..board.config().cols {
+                if board.get_cell(row, col) != Some(color) {
+                    continue;
+                }
+
+                // Prioritize center control
+                if col == center_col {
+                    score += 5;
+                }
+
+                // Pieces closer to the center are more valuable
+                score += 5 - (col as i32 - center_col as i32).abs();
+
+                // Check for adjacent friendly pieces
+                if self.has_adjacent_same_color(board, row, col, color) {
+                    score += 2;
+                }
+            }
+        }
+
+        score + self.vertical_score(board, color) + self.parity_score(board, color)
+    }
+
+    /// Score the player's threats by row parity. When the columns fill up on the standard board
+    /// the first player gets the odd rows counted from the bottom and the second player the even
+    /// ones, so only threats on the player's own rows tend to win. Other boards are left alone,
+    /// the theory is only known to hold on 7x6
+    fn parity_score(&self, board: &Game, color: Player) -> i32 {
+        if *board.config() != GameConfig::default() {
+            return 0;
+        }
+        let rows = board.config().rows;
+        let odd_rows = color == board.first_player();
+
+        board
+            .threats(color)
+            .into_iter()
+            // Playable threats are tactics, the search already sees those
+            .filter(|&(row, col)| board.landing_row(col) != Some(row))
+            .map(|(row, _)| {
+                if ((rows - row) % 2 == 1) == odd_rows {
+                    Self::PARITY_THREAT
+                } else {
+                    Self::OFF_PARITY_THREAT
+                }
+            })
+            .sum()
+    }
+
+    /// Score the run of the player's pieces on top of each column. Stacked pieces can only be
+    /// stopped by capping them, so a vertical run is more dangerous than other adjacency.
+    /// Runs are measured against the player's own win length, which a handicap changes
+    fn vertical_score(&self, board: &Game, color: Player) -> i32 {
+        let rows = board.config().rows;
+        let connect_length = board.win_length(color);
+        let mut score = 0;
+
+        for col in 0..board.config().cols {
+            // Topmost occupied cell, which may be an obstacle
+            let top = board.landing_row(col).map_or(0, |row| row + 1);
+            if top >= rows {
+                continue;
+            }
+            let run = board
+                .line((top, col), (1, 0), rows)
+                .take_while(|&cell| cell == Some(color))
+                .count();
+
+            // Capped by the opponent, or no room left above to finish the line
+            if run == 0 || top + run < connect_length {
+                continue;
+            }
+
+            if run + 1 >= connect_length {
+                score += if board.current_player() == color {
+                    Self::VERTICAL_UNBLOCKABLE
+                } else {
+                    Self::VERTICAL_THREAT
+                };
+            } else if run >= 2 {
+                score += Self::VERTICAL_RUN * run as i32;
+            }
+        }
+
+        score
+    }
+
+    /// Helper method to check if a position has adjacent pieces of the same color
+    fn has_adjacent_same_color(&self, board: &Game, row: usize, col: usize, color: Player) -> bool {
+        let directions = [
+            (0, -1), // left
+            (0, 1),  // right
+            (1, 0),  // down
+            (1, -1), // diagonal down-left
+            (1, 1),  // diagonal down-right
+        ];
+
+        for &(row_dir, col_dir) in &directions {
+            let new_row = row as i32 + row_dir;
+            let new_col = col as i32 + col_dir;
+
+            // Check if position is valid and has the same color
+            if new_row >= 0
+                && new_row < board.config().rows as i32
+                && new_col >= 0
+                && new_col < board.config().cols as i32
+                && let Some(player) = board.get_cell(new_row as usize, new_col as usize)
+                && player == color
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::Handicap;
+
+    #[test]
+    fn vertical_threats_use_the_players_own_win_length() {
+        // Three Yellow pieces stacked in the first column, Red to move
+        let notation = "17171";
+        let board = Game::from_notation(GameConfig::default(), notation).unwrap();
+        assert_eq!(
+            ClassicEvaluator.vertical_score(&board, Player::Yellow),
+            ClassicEvaluator::VERTICAL_THREAT
+        );
+
+        // Needing five, the same stack is only a run
+        let handicapped = GameConfig {
+            handicap: Some(Handicap {
+                player: Player::Yellow,
+                connect_length: 5,
+            }),
+            ..GameConfig::default()
+        };
+        let board = Game::from_notation(handicapped, notation).unwrap();
+        assert_eq!(
+            ClassicEvaluator.vertical_score(&board, Player::Yellow),
+            ClassicEvaluator::VERTICAL_RUN * 3
+        );
+    }
+
+    #[test]
+    fn swapping_the_colors_negates_the_score() {
+        for notation in ["4", "4453", "44536", "3344556", "17171"] {
+            let board = Game::from_notation(GameConfig::default(), notation).unwrap();
+            let swapped = board.colors_swapped();
+            for player in [Player::Yellow, Player::Red] {
+                let score = ClassicEvaluator.evaluate(&board, player);
+                assert_eq!(score, -ClassicEvaluator.evaluate(&board, player.opponent()));
+                assert_eq!(
+                    score,
+                    -ClassicEvaluator.evaluate(&swapped, player),
+                    "{}",
+                    notation
+                );
+                // The standard board's center column is its own mirror image
+                assert_eq!(score, ClassicEvaluator.evaluate(&board.mirrored(), player));
+            }
+        }
+    }
+
+    #[test]
+    fn a_threat_on_the_movers_own_rows_outweighs_the_center() {
+        // Yellow moved first and is to move. The third column makes a threat on an odd row
+        // counted from the bottom, the center one on an even row Red can claim
+        let board = Game::from_notation(GameConfig::default(), "6753435535").unwrap();
+        let after = |col| board.with_move(col).unwrap();
+        let threat_row = |col| {
+            let threats = after(col).threats(Player::Yellow);
+            assert_eq!(threats.len(), 1);
+            board.config().rows - threats[0].0
+        };
+        assert_eq!(threat_row(2) % 2, 1);
+        assert_eq!(threat_row(3) % 2, 0);
+
+        let score = |col| ClassicEvaluator.evaluate(&after(col), Player::Yellow);
+        let parity = |col| ClassicEvaluator.parity_score(&after(col), Player::Yellow);
+        assert!(score(2) > score(3));
+        // Only the parity term puts it ahead
+        assert!(score(2) - parity(2) < score(3) - parity(3));
+    }
+}
@@ -1,44 +1,129 @@
+use std::{
+    collections::VecDeque,
+    fmt, fs, io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use rand::{Rng, seq::IteratorRandom};
 use ratatui::{
     buffer::Buffer,
-    layout::Rect,
+    layout::{Alignment, Rect},
     style::Stylize,
     symbols::border,
     text::{Line, Text},
-    widgets::{Block, Paragraph, Widget},
+    widgets::{Block, Padding, Paragraph, Widget},
 };
 use serde::{Deserialize, Serialize};
 
+use crate::error::Connect4Error;
+
+// Columns are 0-indexed everywhere inside the crate. Everything a person reads or types,
+// the board header, move notation, the human keys, CLI options and messages, counts them
+// from 1, and these two helpers are the only place that converts between the two
+
+// 1-indexed label shown for a column
+pub fn column_label(col: usize) -> usize {
+    col + 1
+}
+
+// Column for a 1-indexed label, None for 0
+pub fn column_from_label(label: usize) -> Option<usize> {
+    label.checked_sub(1)
+}
+
 // Define player types
-#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize, Default)]
 pub enum Player {
     #[default]
     Red,
     Yellow,
 }
 
-impl ToString for Player {
-    fn to_string(&self) -> String {
+impl Player {
+    // Get the other player
+    pub fn opponent(self) -> Player {
         match self {
-            Player::Red => "Red".to_string(),
-            Player::Yellow => "Yellow".to_string(),
+            Player::Red => Player::Yellow,
+            Player::Yellow => Player::Red,
+        }
+    }
+}
+
+impl fmt::Display for Player {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Player::Red => write!(f, "Red"),
+            Player::Yellow => write!(f, "Yellow"),
         }
     }
 }
 
 // Define game state
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum GameState {
     InProgress,
     Won(Player),
     Draw,
 }
 
-// Configuration for the Connect 4 game
+// Direction of a winning line
 #[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WinDirection {
+    Horizontal,
+    Vertical,
+    Diagonal,
+}
+
+impl WinDirection {
+    // Classify a line from its cells, None if there are fewer than two
+    pub fn from_cells(cells: &[(usize, usize)]) -> Option<Self> {
+        let (&(row_a, col_a), &(row_b, col_b)) = (cells.first()?, cells.get(1)?);
+        Some(if row_a == row_b {
+            WinDirection::Horizontal
+        } else if col_a == col_b {
+            WinDirection::Vertical
+        } else {
+            WinDirection::Diagonal
+        })
+    }
+}
+
+impl fmt::Display for WinDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinDirection::Horizontal => write!(f, "a horizontal line"),
+            WinDirection::Vertical => write!(f, "a vertical line"),
+            WinDirection::Diagonal => write!(f, "a diagonal"),
+        }
+    }
+}
+
+// Configuration for the Connect 4 game
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
 pub struct GameConfig {
     pub rows: usize,
     pub cols: usize,
     pub connect_length: usize,
+    // One player needing a different line length, for handicap games
+    pub handicap: Option<Handicap>,
+    // Pop Out variant: instead of dropping, a player may pop their own piece from the bottom
+    // of a column
+    pub pop_out: bool,
+}
+
+// Line length for one player which overrides the usual one
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Handicap {
+    pub player: Player,
+    pub connect_length: usize,
+}
+
+// Randomly placed blocked cells at the start of every game, a seed repeats the same layout
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Obstacles {
+    pub count: usize,
+    pub seed: Option<u64>,
 }
 
 impl Default for GameConfig {
@@ -47,6 +132,96 @@ impl Default for GameConfig {
             rows: 6,
             cols: 7,
             connect_length: 4,
+            handicap: None,
+            pop_out: false,
+        }
+    }
+}
+
+// Board size and line length, e.g. "7x6, connect 4" or "7x6, connect 4, Yellow needs 5"
+impl fmt::Display for GameConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}x{}, connect {}",
+            self.cols, self.rows, self.connect_length
+        )?;
+        if let Some(handicap) = self.handicap {
+            write!(f, ", {} needs {}", handicap.player, handicap.connect_length)?;
+        }
+        if self.pop_out {
+            write!(f, ", pop out")?;
+        }
+        Ok(())
+    }
+}
+
+impl GameConfig {
+    // Shortest line that can count as a win
+    pub const MIN_CONNECT_LENGTH: usize = 2;
+
+    // Config for a board size typed in by the user. Unlike clamped, sizes that aren't playable
+    // are rejected rather than adjusted
+    pub fn custom(rows: usize, cols: usize, connect_length: usize) -> Result<Self, Connect4Error> {
+        let problem = if rows == 0 || cols == 0 {
+            Some("the board needs at least one row and column".to_string())
+        } else if rows.saturating_mul(cols) > Board::MAX_CELLS {
+            Some(format!(
+                "{}x{} is more than the {} cells a board can hold",
+                cols,
+                rows,
+                Board::MAX_CELLS
+            ))
+        } else if connect_length < Self::MIN_CONNECT_LENGTH {
+            Some(format!(
+                "lines need at least {} pieces",
+                Self::MIN_CONNECT_LENGTH
+            ))
+        } else if connect_length > rows.max(cols) {
+            Some(format!(
+                "a line of {} doesn't fit on a {}x{} board",
+                connect_length, cols, rows
+            ))
+        } else {
+            None
+        };
+        match problem {
+            Some(problem) => Err(Connect4Error::InvalidConfig(problem)),
+            None => Ok(GameConfig {
+                rows,
+                cols,
+                connect_length,
+                ..GameConfig::default()
+            }),
+        }
+    }
+
+    // Clamp to a playable config: connect_length of at least MIN_CONNECT_LENGTH, at least one
+    // cell and no more than the board storage holds, and a board long enough in one direction
+    // to fit a winning line
+    pub fn clamped(self) -> Self {
+        let connect_length = self
+            .connect_length
+            .clamp(Self::MIN_CONNECT_LENGTH, Board::MAX_CELLS);
+        let mut cols = self.cols.clamp(1, Board::MAX_CELLS);
+        let mut rows = self.rows.clamp(1, Board::MAX_CELLS / cols);
+        if rows.max(cols) < connect_length {
+            cols = connect_length;
+            rows = rows.min(Board::MAX_CELLS / cols);
+        }
+        // A handicapped line still has to fit on the board
+        let handicap = self.handicap.map(|handicap| Handicap {
+            connect_length: handicap
+                .connect_length
+                .clamp(Self::MIN_CONNECT_LENGTH, rows.max(cols)),
+            ..handicap
+        });
+        GameConfig {
+            rows,
+            cols,
+            connect_length,
+            handicap,
+            pop_out: self.pop_out,
         }
     }
 }
@@ -59,11 +234,24 @@ pub enum GameConfigPreset {
     Small,
     Large,
     Huge,
+    PopOut,
+}
+
+impl fmt::Display for GameConfigPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameConfigPreset::Standard => write!(f, "Standard"),
+            GameConfigPreset::Small => write!(f, "Small"),
+            GameConfigPreset::Large => write!(f, "Large"),
+            GameConfigPreset::Huge => write!(f, "Huge"),
+            GameConfigPreset::PopOut => write!(f, "Pop Out"),
+        }
+    }
 }
 
 impl GameConfigPreset {
     pub fn amount_of_presets() -> usize {
-        4
+        5
     }
 
     pub fn from_index(index: usize) -> Self {
@@ -72,6 +260,7 @@ impl GameConfigPreset {
             1 => GameConfigPreset::Small,
             2 => GameConfigPreset::Large,
             3 => GameConfigPreset::Huge,
+            4 => GameConfigPreset::PopOut,
             _ => GameConfigPreset::Standard,
         }
     }
@@ -83,46 +272,464 @@ impl GameConfigPreset {
                 rows: 4,
                 cols: 4,
                 connect_length: 3,
+                handicap: None,
+                pop_out: false,
             },
             GameConfigPreset::Large => GameConfig {
                 rows: 8,
                 cols: 8,
                 connect_length: 5,
+                handicap: None,
+                pop_out: false,
             },
             GameConfigPreset::Huge => GameConfig {
                 rows: 10,
                 cols: 10,
                 connect_length: 6,
+                handicap: None,
+                pop_out: false,
             },
+            GameConfigPreset::PopOut => GameConfig {
+                pop_out: true,
+                ..GameConfig::default()
+            },
+        }
+    }
+}
+
+// How wins are detected after a move
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum WinCheck {
+    // Only check lines through the last placed piece (fast)
+    #[default]
+    Incremental,
+    // Scan the whole board for any winning line (robust to other board mutations)
+    FullScan,
+}
+
+// Contents of a board cell. Pieces can't land on or fall through blocked cells, and a
+// blocked cell breaks any line running through it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Cell {
+    #[default]
+    Empty,
+    Blocked,
+    Piece(Player),
+}
+
+// Cells packed into bitmasks with one bit per cell in row-major order, so copying a board is
+// a few words rather than a vector per row. Rows aren't tracked by a height per column since
+// an obstacle can hold up the pieces above it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+struct Board {
+    cols: usize,
+    yellow: u128,
+    red: u128,
+    blocked: u128,
+}
+
+impl Board {
+    // Largest board that fits the masks
+    const MAX_CELLS: usize = u128::BITS as usize;
+
+    fn new(cols: usize) -> Self {
+        Board {
+            cols,
+            yellow: 0,
+            red: 0,
+            blocked: 0,
         }
     }
+
+    fn bit(&self, row: usize, col: usize) -> u128 {
+        1 << (row * self.cols + col)
+    }
+
+    fn get(&self, row: usize, col: usize) -> Cell {
+        let bit = self.bit(row, col);
+        if self.yellow & bit != 0 {
+            Cell::Piece(Player::Yellow)
+        } else if self.red & bit != 0 {
+            Cell::Piece(Player::Red)
+        } else if self.blocked & bit != 0 {
+            Cell::Blocked
+        } else {
+            Cell::Empty
+        }
+    }
+
+    fn set(&mut self, row: usize, col: usize, cell: Cell) {
+        let bit = self.bit(row, col);
+        self.yellow &= !bit;
+        self.red &= !bit;
+        self.blocked &= !bit;
+        match cell {
+            Cell::Empty => {}
+            Cell::Blocked => self.blocked |= bit,
+            Cell::Piece(Player::Yellow) => self.yellow |= bit,
+            Cell::Piece(Player::Red) => self.red |= bit,
+        }
+    }
+
+    fn piece_count(&self) -> usize {
+        (self.yellow | self.red).count_ones() as usize
+    }
+
+    // Whether every piece and obstacle lies on a board of the given rows, one to a cell
+    fn fits(&self, rows: usize) -> bool {
+        let outside = u128::MAX
+            .checked_shl((rows * self.cols) as u32)
+            .unwrap_or(0);
+        (self.yellow | self.red | self.blocked) & outside == 0
+            && self.yellow & self.red == 0
+            && (self.yellow | self.red) & self.blocked == 0
+    }
 }
 
-// Connect 4 game struct
-#[derive(Clone, PartialEq, Debug)]
+impl Cell {
+    // Owner of the piece in the cell, if there is one
+    pub fn piece(self) -> Option<Player> {
+        match self {
+            Cell::Piece(player) => Some(player),
+            Cell::Empty | Cell::Blocked => None,
+        }
+    }
+}
+
+// A move as kept in the undo history
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+enum Move {
+    Drop(usize),
+    // Pop Out only, the mover's piece taken from the bottom of the column
+    Pop(usize),
+}
+
+// Connect 4 game struct. Saved games hold everything but the settings and window bookkeeping,
+// which are rebuilt on load
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Game {
-    board: Vec<Vec<Option<Player>>>,
+    board: Board,
     current_player: Player,
+    // Who made (or will make) the first move
+    first_player: Player,
     state: GameState,
     config: GameConfig,
+    #[serde(skip)]
+    win_check: WinCheck,
+    // Per player, the windows of its win_length holding an opponent's piece: a mask per
+    // direction with the window's start cell bit set like the board's. Kept in masks so
+    // searches can copy the game cheaply. Windows that don't fit on the board stay unused
+    #[serde(skip)]
+    closed_windows: [[u128; 4]; 2],
+    // Windows still free of the opponent's pieces, indexed like closed_windows
+    #[serde(skip)]
+    winnable: [usize; 2],
+    // Found once when the winning move is placed, so rendering doesn't rescan the board
+    winning_combination: Option<Vec<(usize, usize)>>,
+    // Column and mover of the recent moves, oldest first. Pop Out games can go on forever, so
+    // only the last MOVE_HISTORY moves are kept and undo goes back no further
+    move_stack: VecDeque<(Move, Player)>,
+    // Moves taken back by undo, most recent last, cleared by any new move
+    redo_stack: Vec<(Move, Player)>,
+    // Pop Out: pieces each player has popped off the board, indexed like closed_windows
+    #[serde(default)]
+    popped: [usize; 2],
+    // Moves played so far, pops included, unlike the pieces on the board
+    #[serde(default)]
+    plies: usize,
 }
 
 impl Game {
+    // One direction per line orientation: horizontal, vertical and both diagonals
+    pub const LINE_DIRECTIONS: [(i32, i32); 4] = [(0, 1), (1, 0), (-1, 1), (1, 1)];
+    // Most moves kept for undo, the oldest are dropped past it
+    const MOVE_HISTORY: usize = 1024;
+
     // Create a new game
     pub fn new() -> Self {
         Self::with_config(GameConfig::default())
     }
 
+    // Create a new game, invalid configs are clamped to the minimum playable size
     pub fn with_config(config: GameConfig) -> Self {
-        let board = vec![vec![None; config.cols]; config.rows];
-        Game {
+        let config = config.clamped();
+        let board = Board::new(config.cols);
+        let mut game = Game {
             board,
             current_player: Player::Yellow, // Yellow goes first
+            first_player: Player::Yellow,
             state: GameState::InProgress,
             config,
+            win_check: WinCheck::default(),
+            closed_windows: [[0; 4]; 2],
+            winnable: [0; 2],
+            winning_combination: None,
+            move_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            popped: [0; 2],
+            plies: 0,
+        };
+        game.open_all_windows();
+        game
+    }
+
+    // Every window is open on the empty board
+    fn open_all_windows(&mut self) {
+        let config = self.config;
+        for player in [Player::Yellow, Player::Red] {
+            let length = self.win_length(player);
+            let index = Self::player_index(player);
+            self.closed_windows[index] = [0; 4];
+            self.winnable[index] = Self::LINE_DIRECTIONS
+                .iter()
+                .map(|&direction| {
+                    (0..config.rows)
+                        .flat_map(|row| (0..config.cols).map(move |col| (row, col)))
+                        .filter(|&start| self.window_fits(start, direction, length))
+                        .count()
+                })
+                .sum();
+        }
+    }
+
+    // Redo the window bookkeeping from the cells on the board, after a piece was removed
+    fn recount_windows(&mut self) {
+        self.open_all_windows();
+        for row in 0..self.config.rows {
+            for col in 0..self.config.cols {
+                match self.board.get(row, col) {
+                    Cell::Empty => {}
+                    Cell::Blocked => {
+                        self.close_windows(row, col, Player::Yellow);
+                        self.close_windows(row, col, Player::Red);
+                    }
+                    Cell::Piece(player) => self.close_windows(row, col, player.opponent()),
+                }
+            }
+        }
+    }
+
+    // Create a new game with obstacles on the given cells, cells off the board are skipped
+    pub fn with_obstacles(config: GameConfig, obstacles: &[(usize, usize)]) -> Self {
+        let mut game = Self::with_config(config);
+        for &(row, col) in obstacles {
+            if row >= game.config.rows
+                || col >= game.config.cols
+                || game.board.get(row, col) == Cell::Blocked
+            {
+                continue;
+            }
+            game.board.set(row, col, Cell::Blocked);
+            // Neither player can complete a line through an obstacle
+            game.close_windows(row, col, Player::Yellow);
+            game.close_windows(row, col, Player::Red);
+        }
+        game
+    }
+
+    // Up to count distinct random cells for obstacles, never in the top row so every column
+    // can still be played
+    pub fn random_obstacles(
+        config: GameConfig,
+        count: usize,
+        rng: &mut impl Rng,
+    ) -> Vec<(usize, usize)> {
+        let config = config.clamped();
+        (1..config.rows)
+            .flat_map(|row| (0..config.cols).map(move |col| (row, col)))
+            .choose_multiple(rng, count)
+    }
+
+    // Where the game is saved to resume it later
+    pub fn save_path() -> PathBuf {
+        ["connect4", "saves", "game.json"].iter().collect()
+    }
+
+    // Write the board, players, state, config and move history as JSON
+    pub fn save_to_file(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    // Read a game written by save_to_file, with the default win check. Files whose board
+    // doesn't match their config or move history are rejected
+    pub fn load_from_file(path: &Path) -> io::Result<Game> {
+        Self::from_json(&fs::read_to_string(path)?)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", path.display(), err)))
+    }
+
+    // Parse a game in the save file format, as also sent to network players. Nothing in the
+    // file is trusted: a full move history is replayed from the empty board and has to end
+    // on the saved position, a history cut short in a long Pop Out game has to take back
+    // cleanly from it
+    pub fn from_json(json: &str) -> io::Result<Game> {
+        let invalid = |reason| io::Error::new(io::ErrorKind::InvalidData, reason);
+        let saved: Game = serde_json::from_str(json)?;
+        if saved.config != saved.config.clamped()
+            || saved.board.cols != saved.config.cols
+            || !saved.board.fits(saved.config.rows)
+        {
+            return Err(invalid("not a valid board"));
+        }
+
+        let mut game = if saved.move_stack.len() >= saved.plies {
+            let obstacles: Vec<(usize, usize)> = (0..saved.config.rows)
+                .flat_map(|row| (0..saved.config.cols).map(move |col| (row, col)))
+                .filter(|&(row, col)| saved.board.get(row, col) == Cell::Blocked)
+                .collect();
+            let mut game = Game::with_obstacles(saved.config, &obstacles);
+            game.set_starting_player(saved.first_player);
+            for &(played, player) in &saved.move_stack {
+                if !game.replay(played, player) {
+                    return Err(invalid("the move history has an illegal move"));
+                }
+            }
+            // A forfeit or timeout ends the game without a move
+            let forfeited = game.state == GameState::InProgress && saved.state != game.state;
+            if game.board != saved.board
+                || game.current_player != saved.current_player
+                || game.popped != saved.popped
+                || (game.state != saved.state && !forfeited)
+            {
+                return Err(invalid("the board doesn't match the move history"));
+            }
+            game.state = saved.state;
+            game
+        } else {
+            let mut taken_back = saved.clone();
+            while let Some(&(played, player)) = taken_back.move_stack.back() {
+                if !taken_back.can_undo(played, player) {
+                    return Err(invalid("the move history doesn't lead to the board"));
+                }
+                taken_back.undo();
+            }
+            let mut game = saved.clone();
+            game.winning_combination = match game.state {
+                GameState::Won(_) => game.find_winning_combination(),
+                _ => None,
+            };
+            game.recount_windows();
+            game
+        };
+
+        // Moves taken back have to be playable again in turn
+        let mut redone = game.clone();
+        for &(played, player) in saved.redo_stack.iter().rev() {
+            if !redone.replay(played, player) {
+                return Err(invalid("the undone moves can't be played again"));
+            }
+        }
+        game.redo_stack = saved.redo_stack;
+        Ok(game)
+    }
+
+    // Play a move from a saved history, false unless it was the player's turn and the move
+    // is legal
+    fn replay(&mut self, played: Move, player: Player) -> bool {
+        self.state == GameState::InProgress
+            && self.current_player == player
+            && match played {
+                Move::Drop(column) => self.place(column),
+                Move::Pop(column) => self.pop(column),
+            }
+            .is_some()
+    }
+
+    // Whether undo can take the move back: a drop needs the mover's piece on top of its
+    // column, a pop a popped piece of the mover and room in the column to put it back
+    fn can_undo(&self, played: Move, player: Player) -> bool {
+        match played {
+            Move::Drop(column) => {
+                let row = self.landing_row(column).map_or(0, |row| row + 1);
+                column < self.config.cols
+                    && row < self.config.rows
+                    && self.board.get(row, column) == Cell::Piece(player)
+            }
+            Move::Pop(column) => {
+                column < self.config.cols
+                    && self.popped[Self::player_index(player)] > 0
+                    && (0..self.config.rows)
+                        .any(|row| self.board.get(row, column).piece().is_none())
+            }
         }
     }
 
+    // Replay a move list of 1-indexed columns from the empty board, e.g. "4453",
+    // or comma separated like "4,4,10,3" for boards wider than 9 columns
+    pub fn from_notation(config: GameConfig, notation: &str) -> Result<Game, Connect4Error> {
+        Self::with_config(config).play_notation(notation)
+    }
+
+    // Replay a move list in the same format on top of this position
+    pub fn play_notation(self, notation: &str) -> Result<Game, Connect4Error> {
+        let notation = notation.trim();
+        let tokens: Vec<String> = if notation.contains(',') {
+            notation
+                .split(',')
+                .map(|token| token.trim().to_string())
+                .collect()
+        } else {
+            notation
+                .chars()
+                .filter(|c| !c.is_whitespace())
+                .map(String::from)
+                .collect()
+        };
+
+        // Played in place rather than with with_move, so the moves can be undone
+        let mut game = self;
+        for (index, token) in tokens.into_iter().enumerate() {
+            let played = game.state == GameState::InProgress
+                && token
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(column_from_label)
+                    .and_then(|col| game.place(col))
+                    .is_some();
+            if !played {
+                return Err(Connect4Error::InvalidNotation {
+                    index: index + 1,
+                    token,
+                });
+            }
+        }
+        Ok(game)
+    }
+
+    // Encode every cell plus the side to move, equal keys are the same position
+    pub fn to_key(&self) -> String {
+        let mut key = String::with_capacity(self.config.rows * self.config.cols + 1);
+        for row in 0..self.config.rows {
+            for col in 0..self.config.cols {
+                key.push(match self.board.get(row, col) {
+                    Cell::Piece(Player::Yellow) => 'y',
+                    Cell::Piece(Player::Red) => 'r',
+                    Cell::Blocked => '#',
+                    Cell::Empty => '.',
+                });
+            }
+        }
+        key.push(match self.current_player {
+            Player::Yellow => 'Y',
+            Player::Red => 'R',
+        });
+        key
+    }
+
+    // Choose who moves first, only possible before any piece is placed
+    pub fn set_starting_player(&mut self, player: Player) {
+        if self.move_count() == 0 {
+            self.current_player = player;
+            self.first_player = player;
+        }
+    }
+
+    pub fn first_player(&self) -> Player {
+        self.first_player
+    }
+
     // Place a piece in the selected column
     pub fn place(&mut self, column: usize) -> Option<GameState> {
         // Check if the game is still in progress
@@ -134,38 +741,341 @@ impl Game {
             return None;
         }
 
-        // Find the first empty row in the column (from bottom to top)
-        let row = (0..self.config.rows)
-            .rev()
-            .find(|&row| self.board[row][column].is_none());
-
-        match row {
+        match self.landing_row(column) {
             Some(row) => {
                 // Place the piece
-                self.board[row][column] = Some(self.current_player);
+                self.board
+                    .set(row, column, Cell::Piece(self.current_player));
+                self.record_move(Move::Drop(column));
 
                 // Change state
                 // Check if this move results in a win
-                if self.check_win(row, column) {
-                    self.state = GameState::Won(self.current_player);
-                } else if self.is_board_full() {
+                let winner = match self.win_check {
+                    WinCheck::Incremental => {
+                        self.check_win(row, column).then_some(self.current_player)
+                    }
+                    WinCheck::FullScan => self.scan_win(),
+                };
+                self.claim_windows(row, column);
+                if let Some(winner) = winner {
+                    self.state = GameState::Won(winner);
+                    self.winning_combination = self.line_through(row, column, winner);
+                } else if self.is_board_full() && !self.can_pop_any(self.current_player.opponent())
+                    // Nobody can complete a line anymore, no need to fill the board. Pops
+                    // reopen lines, so Pop Out games go on
+                    || (!self.config.pop_out && self.winnable == [0, 0])
+                {
                     self.state = GameState::Draw;
                 }
 
                 if self.state == GameState::InProgress {
                     // Switch players
-                    self.current_player = match self.current_player {
-                        Player::Red => Player::Yellow,
-                        Player::Yellow => Player::Red,
-                    };
+                    self.current_player = self.current_player.opponent();
                 }
 
-                return Some(self.state);
+                Some(self.state)
             }
             None => None,
         }
     }
 
+    // Whether the player may pop from the column: Pop Out only, with their own piece at the
+    // bottom
+    pub fn can_pop(&self, column: usize, player: Player) -> bool {
+        self.config.pop_out
+            && column < self.config.cols
+            && self.board.get(self.config.rows - 1, column) == Cell::Piece(player)
+    }
+
+    fn can_pop_any(&self, player: Player) -> bool {
+        (0..self.config.cols).any(|col| self.can_pop(col, player))
+    }
+
+    // Pop Out only: take the current player's piece from the bottom of the column, the pieces
+    // above fall one row. A pop can complete lines for both players at once, the popping
+    // player wins if one of the lines is theirs, otherwise the opponent wins.
+    // Returns the game state (the unchanged one for a finished game), None if the pop isn't allowed
+    pub fn pop(&mut self, column: usize) -> Option<GameState> {
+        if self.state != GameState::InProgress {
+            return Some(self.state);
+        }
+        if !self.can_pop(column, self.current_player) {
+            return None;
+        }
+
+        // Pieces resting on the popped one fall, an obstacle holds up everything above it
+        let mut row = self.config.rows - 1;
+        while row > 0 && self.board.get(row - 1, column).piece().is_some() {
+            self.board.set(row, column, self.board.get(row - 1, column));
+            row -= 1;
+        }
+        self.board.set(row, column, Cell::Empty);
+        self.record_move(Move::Pop(column));
+        self.popped[Self::player_index(self.current_player)] += 1;
+        // Lines closed by the popped piece may be open again
+        self.recount_windows();
+
+        let mover = self.current_player;
+        let won = [mover, mover.opponent()]
+            .into_iter()
+            .find_map(|player| Some((player, self.player_line(player)?)));
+        if let Some((winner, line)) = won {
+            self.state = GameState::Won(winner);
+            self.winning_combination = Some(line);
+        } else {
+            self.current_player = mover.opponent();
+        }
+        Some(self.state)
+    }
+
+    // Keep the current player's move for undo, a new move ends the line of play redo follows
+    fn record_move(&mut self, played: Move) {
+        if self.move_stack.len() == Self::MOVE_HISTORY {
+            self.move_stack.pop_front();
+        }
+        self.move_stack.push_back((played, self.current_player));
+        self.redo_stack.clear();
+        self.plies += 1;
+    }
+
+    // Take back the last move, the game is back in progress with its mover to move.
+    // Returns the column, None on a board without moves
+    pub fn undo(&mut self) -> Option<usize> {
+        let (last, player) = self.move_stack.pop_back()?;
+        let column = match last {
+            Move::Drop(column) => {
+                // The last piece is the topmost one in its column
+                let row = self.landing_row(column).map_or(0, |row| row + 1);
+                self.board.set(row, column, Cell::Empty);
+                column
+            }
+            Move::Pop(column) => {
+                // Lift the fallen pieces back up and put the popped one under them
+                let bottom = self.config.rows - 1;
+                let mut top = bottom;
+                while self.board.get(top, column).piece().is_some() {
+                    top -= 1;
+                }
+                for row in top..bottom {
+                    self.board.set(row, column, self.board.get(row + 1, column));
+                }
+                self.board.set(bottom, column, Cell::Piece(player));
+                self.popped[Self::player_index(player)] -= 1;
+                column
+            }
+        };
+        self.current_player = player;
+        self.plies -= 1;
+        self.state = GameState::InProgress;
+        self.winning_combination = None;
+        self.recount_windows();
+        self.redo_stack.push((last, player));
+        Some(column)
+    }
+
+    // Play the last undone move again. Returns the column, None if there is nothing to redo
+    pub fn redo(&mut self) -> Option<usize> {
+        let (next, player) = self.redo_stack.pop()?;
+        let redo_stack = std::mem::take(&mut self.redo_stack);
+        self.current_player = player;
+        let column = match next {
+            Move::Drop(column) => {
+                self.place(column);
+                column
+            }
+            Move::Pop(column) => {
+                self.pop(column);
+                column
+            }
+        };
+        // place clears the stack for a new line of play, this is the same line
+        self.redo_stack = redo_stack;
+        Some(column)
+    }
+
+    // Pop Out: pieces the player has popped off the board this game
+    pub fn popped(&self, player: Player) -> usize {
+        self.popped[Self::player_index(player)]
+    }
+
+    // Number of windows of the player's win_length it could still complete, those without any
+    // of the opponent's pieces. Once both players are at zero the game ends in a draw
+    pub fn winnable_lines(&self, player: Player) -> usize {
+        self.winnable[Self::player_index(player)]
+    }
+
+    // Length of line the player needs to win, the config's connect length unless the player
+    // has a handicap
+    pub fn win_length(&self, player: Player) -> usize {
+        match self.config.handicap {
+            Some(handicap) if handicap.player == player => handicap.connect_length,
+            _ => self.config.connect_length,
+        }
+    }
+
+    // Index of the player in the per player arrays
+    fn player_index(player: Player) -> usize {
+        match player {
+            Player::Yellow => 0,
+            Player::Red => 1,
+        }
+    }
+
+    // Whether a window of the length from start in the direction stays on the board
+    fn window_fits(
+        &self,
+        (row, col): (usize, usize),
+        (row_dir, col_dir): (i32, i32),
+        length: usize,
+    ) -> bool {
+        let steps = length as i32 - 1;
+        let end_row = row as i32 + row_dir * steps;
+        let end_col = col as i32 + col_dir * steps;
+        end_row >= 0
+            && end_row < self.config.rows as i32
+            && end_col >= 0
+            && end_col < self.config.cols as i32
+    }
+
+    // Close the opponent's windows running through the current player's new piece
+    fn claim_windows(&mut self, row: usize, col: usize) {
+        self.close_windows(row, col, self.current_player.opponent());
+    }
+
+    // Close the player's windows running through the cell
+    fn close_windows(&mut self, row: usize, col: usize, player: Player) {
+        let length = self.win_length(player);
+        let index = Self::player_index(player);
+        for (direction_index, &(row_dir, col_dir)) in Self::LINE_DIRECTIONS.iter().enumerate() {
+            for offset in 0..length as i32 {
+                let start_row = row as i32 - row_dir * offset;
+                let start_col = col as i32 - col_dir * offset;
+                if start_row < 0
+                    || start_row >= self.config.rows as i32
+                    || start_col < 0
+                    || start_col >= self.config.cols as i32
+                {
+                    continue;
+                }
+                let start = (start_row as usize, start_col as usize);
+                if !self.window_fits(start, (row_dir, col_dir), length) {
+                    continue;
+                }
+                let window = self.board.bit(start.0, start.1);
+                let closed = &mut self.closed_windows[index][direction_index];
+                if *closed & window == 0 {
+                    *closed |= window;
+                    self.winnable[index] -= 1;
+                }
+            }
+        }
+    }
+
+    // Copy of the game with a move played in the column, None if the move is illegal
+    // or the game is already over. The original game is left untouched. Like position, the
+    // copy has no undo history
+    pub fn with_move(&self, column: usize) -> Option<Game> {
+        if self.state != GameState::InProgress {
+            return None;
+        }
+        let mut next = self.position();
+        next.place(column)?;
+        Some(next)
+    }
+
+    // Copy of the game without the undo and redo history, for searches that only play
+    // moves forward. Cloning would copy up to MOVE_HISTORY moves at every node
+    pub fn position(&self) -> Game {
+        Game {
+            winning_combination: self.winning_combination.clone(),
+            move_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            ..*self
+        }
+    }
+
+    // Tests only: the position reflected left to right, obstacles included, without the
+    // history
+    #[cfg(test)]
+    pub fn mirrored(&self) -> Game {
+        let cols = self.config.cols;
+        let mut board = Board::new(cols);
+        for row in 0..self.config.rows {
+            for col in 0..cols {
+                board.set(row, cols - 1 - col, self.board.get(row, col));
+            }
+        }
+        let mut game = Game {
+            board,
+            winning_combination: self.winning_combination.as_ref().map(|line| {
+                line.iter()
+                    .map(|&(row, col)| (row, cols - 1 - col))
+                    .collect()
+            }),
+            ..self.position()
+        };
+        game.recount_windows();
+        game
+    }
+
+    // Tests only: the position with every piece, the turn, the first move and a handicap
+    // handed to the other player, without the history
+    #[cfg(test)]
+    pub fn colors_swapped(&self) -> Game {
+        let mut game = Game {
+            board: Board {
+                yellow: self.board.red,
+                red: self.board.yellow,
+                ..self.board
+            },
+            current_player: self.current_player.opponent(),
+            first_player: self.first_player.opponent(),
+            state: match self.state {
+                GameState::Won(player) => GameState::Won(player.opponent()),
+                state => state,
+            },
+            config: GameConfig {
+                handicap: self.config.handicap.map(|handicap| Handicap {
+                    player: handicap.player.opponent(),
+                    ..handicap
+                }),
+                ..self.config
+            },
+            popped: [self.popped[1], self.popped[0]],
+            ..self.position()
+        };
+        game.recount_windows();
+        game
+    }
+
+    // Same as with_move, but also None if it is not the given player's turn
+    pub fn with_move_for(&self, column: usize, player: Player) -> Option<Game> {
+        if self.current_player != player {
+            return None;
+        }
+        self.with_move(column)
+    }
+
+    // End the game because the player has no move to make:
+    // a draw if the board is actually full, otherwise the opponent wins
+    pub fn forfeit(&mut self, player: Player) -> GameState {
+        if self.state == GameState::InProgress {
+            self.state = if self.is_board_full() {
+                GameState::Draw
+            } else {
+                GameState::Won(player.opponent())
+            };
+        }
+        self.state
+    }
+
+    // Debug builds only: set the result directly, to try out the game over flow without
+    // playing a full game. No winning line is highlighted for a forced win
+    #[cfg(debug_assertions)]
+    pub fn force_state(&mut self, state: GameState) {
+        self.state = state;
+    }
+
     // Get the current player
     pub fn current_player(&self) -> Player {
         self.current_player
@@ -180,6 +1090,11 @@ impl Game {
         &self.config
     }
 
+    // Set how wins are detected for the following moves
+    pub fn set_win_check(&mut self, win_check: WinCheck) {
+        self.win_check = win_check;
+    }
+
     pub fn valid_moves(&self) -> Vec<usize> {
         let valid_moves: Vec<usize> = (0..self.config.cols)
             .filter(|&col| !self.is_column_full(col))
@@ -187,112 +1102,197 @@ impl Game {
         valid_moves
     }
 
+    // Row a piece dropped in the column would land in, None if the column is full.
+    // The piece falls until the cell below holds a piece or an obstacle
+    pub fn landing_row(&self, col: usize) -> Option<usize> {
+        if col >= self.config.cols {
+            return None;
+        }
+        (0..self.config.rows)
+            .take_while(|&row| self.board.get(row, col) == Cell::Empty)
+            .last()
+    }
+
+    // Number of pieces on the board, pops take them off again
+    pub fn move_count(&self) -> usize {
+        self.board.piece_count()
+    }
+
+    // Number of moves played so far, drops and pops alike
+    pub fn plies(&self) -> usize {
+        self.plies
+    }
+
+    // Whether the cell holds an obstacle
+    pub fn is_blocked(&self, row: usize, col: usize) -> bool {
+        row < self.config.rows
+            && col < self.config.cols
+            && self.board.get(row, col) == Cell::Blocked
+    }
+
+    // Whether any cell of the board is blocked
+    pub fn has_obstacles(&self) -> bool {
+        self.board.blocked != 0
+    }
+
+    // Check if the empty cell would complete a winning line for the player if filled
+    pub fn is_winning_cell(&self, row: usize, col: usize, player: Player) -> bool {
+        if row >= self.config.rows
+            || col >= self.config.cols
+            || self.board.get(row, col) != Cell::Empty
+        {
+            return false;
+        }
+        Self::LINE_DIRECTIONS.into_iter().any(|(row_dir, col_dir)| {
+            1 + self.count_direction(row, col, row_dir, col_dir, player)
+                + self.count_direction(row, col, -row_dir, -col_dir, player)
+                >= self.win_length(player)
+        })
+    }
+
+    // Check if playing the column lets the opponent win by dropping directly on top of it
+    pub fn enables_opponent_win(&self, col: usize) -> bool {
+        match self.landing_row(col) {
+            Some(row) if row > 0 => {
+                self.is_winning_cell(row - 1, col, self.current_player.opponent())
+            }
+            _ => false,
+        }
+    }
+
+    // All empty cells which would complete a winning line for the player (threats)
+    pub fn threats(&self, player: Player) -> Vec<(usize, usize)> {
+        let mut threats = Vec::new();
+        for row in 0..self.config.rows {
+            for col in 0..self.config.cols {
+                if self.is_winning_cell(row, col, player) {
+                    threats.push((row, col));
+                }
+            }
+        }
+        threats
+    }
+
     // Check if the move at (row, col) results in a win
     fn check_win(&self, row: usize, col: usize) -> bool {
-        // Check horizontal
-        if self.count_consecutive(row, col, 0, 1) >= 4 {
-            return true;
-        }
-
-        // Check vertical
-        if self.count_consecutive(row, col, 1, 0) >= 4 {
-            return true;
-        }
-
-        // Check diagonal (/)
-        if self.count_consecutive(row, col, -1, 1) >= 4 {
-            return true;
-        }
-
-        // Check diagonal (\)
-        if self.count_consecutive(row, col, 1, 1) >= 4 {
-            return true;
-        }
-
-        false
-    }
-    // Get the winning combination if one exists
-    pub fn get_winning_combination(&self) -> Option<Vec<(usize, usize)>> {
-        if let GameState::Won(player) = self.state {
-            // Check all possible positions for a starting point of a winning combination
-            for row in 0..self.config.rows {
-                for col in 0..self.config.cols {
-                    if self.board[row][col] == Some(player) {
-                        // Check horizontal
-                        if col + 3 < self.config.cols {
-                            let mut valid = true;
-                            for i in 1..4 {
-                                if self.board[row][col + i] != Some(player) {
-                                    valid = false;
-                                    break;
-                                }
-                            }
-                            if valid {
-                                return Some(vec![
-                                    (row, col),
-                                    (row, col + 1),
-                                    (row, col + 2),
-                                    (row, col + 3),
-                                ]);
-                            }
-                        }
+        let Cell::Piece(player) = self.board.get(row, col) else {
+            return false;
+        };
+        let length = self.win_length(player);
 
-                        // Check vertical
-                        if row + 3 < self.config.rows {
-                            let mut valid = true;
-                            for i in 1..4 {
-                                if self.board[row + i][col] != Some(player) {
-                                    valid = false;
-                                    break;
-                                }
-                            }
-                            if valid {
-                                return Some(vec![
-                                    (row, col),
-                                    (row + 1, col),
-                                    (row + 2, col),
-                                    (row + 3, col),
-                                ]);
-                            }
-                        }
+        Self::LINE_DIRECTIONS
+            .into_iter()
+            .any(|(row_dir, col_dir)| self.count_consecutive(row, col, row_dir, col_dir) >= length)
+    }
 
-                        // Check diagonal (/)
-                        if row >= 3 && col + 3 < self.config.cols {
-                            let mut valid = true;
-                            for i in 1..4 {
-                                if self.board[row - i][col + i] != Some(player) {
-                                    valid = false;
-                                    break;
-                                }
-                            }
-                            if valid {
-                                return Some(vec![
-                                    (row, col),
-                                    (row - 1, col + 1),
-                                    (row - 2, col + 2),
-                                    (row - 3, col + 3),
-                                ]);
-                            }
+    // Scan the whole board for a winning line, returning its owner
+    pub fn scan_win(&self) -> Option<Player> {
+        // Only look forward in each direction so every line is counted from its start
+        for row in 0..self.config.rows {
+            for col in 0..self.config.cols {
+                if let Cell::Piece(player) = self.board.get(row, col) {
+                    let length = self.win_length(player);
+                    for direction in Self::LINE_DIRECTIONS {
+                        let run = self
+                            .line((row, col), direction, length)
+                            .take_while(|&cell| cell == Some(player))
+                            .count();
+                        if run >= length {
+                            return Some(player);
                         }
+                    }
+                }
+            }
+        }
+        None
+    }
 
-                        // Check diagonal (\)
-                        if row + 3 < self.config.rows && col + 3 < self.config.cols {
-                            let mut valid = true;
-                            for i in 1..4 {
-                                if self.board[row + i][col + i] != Some(player) {
-                                    valid = false;
-                                    break;
-                                }
-                            }
-                            if valid {
-                                return Some(vec![
-                                    (row, col),
-                                    (row + 1, col + 1),
-                                    (row + 2, col + 2),
-                                    (row + 3, col + 3),
-                                ]);
-                            }
-                        }
+    // The cells from start stepping by direction, at most length of them, stopping at the edge
+    pub fn line(
+        &self,
+        start: (usize, usize),
+        direction: (i32, i32),
+        length: usize,
+    ) -> impl Iterator<Item = Option<Player>> + '_ {
+        let (row, col) = (start.0 as i32, start.1 as i32);
+        let (row_dir, col_dir) = direction;
+        (0..length as i32).map_while(move |i| {
+            let r = row + row_dir * i;
+            let c = col + col_dir * i;
+            let inside =
+                r >= 0 && r < self.config.rows as i32 && c >= 0 && c < self.config.cols as i32;
+            inside.then(|| self.board.get(r as usize, c as usize).piece())
+        })
+    }
+
+    // Direction of the winning line if the game is won
+    pub fn win_direction(&self) -> Option<WinDirection> {
+        WinDirection::from_cells(self.get_winning_combination()?)
+    }
+
+    // Get the winning combination if one exists, cached when the winning move was placed.
+    // Forced or forfeited wins have none
+    pub fn get_winning_combination(&self) -> Option<&[(usize, usize)]> {
+        self.winning_combination.as_deref()
+    }
+
+    // The first winning line through a cell, the winning move is always part of the line.
+    // Includes the whole run, which can be longer than the win length
+    fn line_through(&self, row: usize, col: usize, player: Player) -> Option<Vec<(usize, usize)>> {
+        Self::LINE_DIRECTIONS
+            .into_iter()
+            .find_map(|(row_dir, col_dir)| {
+                let back = self.count_direction(row, col, -row_dir, -col_dir, player);
+                let forward = self.count_direction(row, col, row_dir, col_dir, player);
+                (back + forward + 1 >= self.win_length(player)).then(|| {
+                    let start_row = row as i32 - row_dir * back as i32;
+                    let start_col = col as i32 - col_dir * back as i32;
+                    (0..(back + forward + 1) as i32)
+                        .map(|i| {
+                            (
+                                (start_row + row_dir * i) as usize,
+                                (start_col + col_dir * i) as usize,
+                            )
+                        })
+                        .collect()
+                })
+            })
+    }
+
+    // Search the whole board for the winner's full run, without the cache
+    pub fn find_winning_combination(&self) -> Option<Vec<(usize, usize)>> {
+        let GameState::Won(player) = self.state else {
+            return None;
+        };
+        self.player_line(player)
+    }
+
+    // The first full run of the player's pieces at least their win length long
+    fn player_line(&self, player: Player) -> Option<Vec<(usize, usize)>> {
+        let length = self.win_length(player);
+        let longest = self.config.rows.max(self.config.cols);
+        for row in 0..self.config.rows {
+            for col in 0..self.config.cols {
+                for (row_dir, col_dir) in Self::LINE_DIRECTIONS {
+                    // Only count runs from their first cell
+                    if self.count_direction(row, col, -row_dir, -col_dir, player) > 0 {
+                        continue;
+                    }
+                    let run = self
+                        .line((row, col), (row_dir, col_dir), longest)
+                        .take_while(|&cell| cell == Some(player))
+                        .count();
+                    if run >= length {
+                        return Some(
+                            (0..run as i32)
+                                .map(|i| {
+                                    (
+                                        (row as i32 + row_dir * i) as usize,
+                                        (col as i32 + col_dir * i) as usize,
+                                    )
+                                })
+                                .collect(),
+                        );
                     }
                 }
             }
@@ -302,7 +1302,7 @@ impl Game {
 
     // Count consecutive pieces of the same color in a given direction
     fn count_consecutive(&self, row: usize, col: usize, row_dir: i32, col_dir: i32) -> usize {
-        let player = self.board[row][col].unwrap();
+        let player = self.board.get(row, col).piece().unwrap();
         let mut count = 1; // Start with 1 for the piece just placed
 
         // Count in the positive direction
@@ -331,7 +1331,7 @@ impl Game {
             && r < self.config.rows as i32
             && c >= 0
             && c < self.config.cols as i32
-            && self.board[r as usize][c as usize] == Some(player)
+            && self.board.get(r as usize, c as usize) == Cell::Piece(player)
         {
             count += 1;
             r += row_dir;
@@ -341,43 +1341,214 @@ impl Game {
         count
     }
 
-    // Check if the board is full (draw condition)
+    // Check if the board is full (draw condition), cells shut off below an obstacle count
+    // as full since nothing can reach them
     fn is_board_full(&self) -> bool {
-        self.board
-            .iter()
-            .all(|row| row.iter().all(|cell| cell.is_some()))
+        (0..self.config.cols).all(|col| self.is_column_full(col))
     }
 
     pub fn is_column_full(&self, col: usize) -> bool {
-        self.board.iter().all(|row| row[col].is_some())
+        self.landing_row(col).is_none()
     }
 
-    // Get a cell's content
+    // Get the piece in a cell, None for empty and blocked cells
     pub fn get_cell(&self, row: usize, col: usize) -> Option<Player> {
         if row < self.config.rows && col < self.config.cols {
-            self.board[row][col]
+            self.board.get(row, col).piece()
         } else {
             None
         }
     }
 }
 
+// Width of a single cell in the grid, excluding dividers
+const CELL_WIDTH: usize = 3;
+
 pub struct GridWidget<'a> {
     pub game: &'a Game,
+    // Player whose pieces are drawn as neutral discs (fog mode), revealed once the game ends
+    pub hidden_player: Option<Player>,
+    // Show the column numbers, the line is kept blank otherwise so the board doesn't move
+    pub show_header: bool,
+    // Column the AI is about to play, marked in the header and at its landing cell
+    pub preview_column: Option<usize>,
+    // Score of each column from the agent to move, drawn as a row above the column numbers.
+    // Some with no scores keeps the row blank
+    pub column_scores: Option<&'a [(usize, i32)]>,
+    // Horizontal placement of the board in its area
+    pub alignment: Alignment,
+    // Blank columns kept on either side, reduced on terminals too narrow for it
+    pub padding: u16,
+    // Title shown above the board, e.g. the config
+    pub title: Option<String>,
+    // First column shown when the board is too wide for its area, clamped so the view stays
+    // filled
+    pub scroll: usize,
+    // Row, column and owner of a piece still falling to its cell, drawn over the empty cell
+    pub falling: Option<(usize, usize, Player)>,
+    // Column just popped, its fallen pieces still drawn a row higher
+    pub popped_column: Option<usize>,
+}
+
+impl GridWidget<'_> {
+    // Piece drawn in a cell: the board's, or in a column settling after a pop the one that
+    // sat there before the pieces above fell, the bottom cell staying empty
+    pub fn shown_cell(&self, row: usize, col: usize) -> Option<Player> {
+        let bottom = self.game.config.rows - 1;
+        if self.popped_column != Some(col) {
+            return self.game.get_cell(row, col);
+        }
+        // The pieces that fell are the run up from the bottom, an obstacle held up the rest
+        let run_top = (0..=bottom)
+            .rev()
+            .take_while(|&r| self.game.get_cell(r, col).is_some())
+            .last();
+        match run_top {
+            Some(top) if row + 1 >= top && row < bottom => self.game.get_cell(row + 1, col),
+            Some(_) if row == bottom => None,
+            _ => self.game.get_cell(row, col),
+        }
+    }
+
+    // Every line has the same width: a border plus a cell and divider per column
+    fn grid_width(&self) -> u16 {
+        (self.game.config.cols * (CELL_WIDTH + 1) + 1) as u16
+    }
+
+    // Padding on either side, reduced so the board still fits narrow areas
+    fn fitted_padding(&self, area: Rect) -> u16 {
+        self.padding
+            .min(area.width.saturating_sub(self.grid_width()) / 2)
+    }
+
+    // Number of columns that fit in the area, at least one
+    fn visible_cols(&self, area: Rect) -> usize {
+        let inner_width = area.width.saturating_sub(self.fitted_padding(area) * 2) as usize;
+        (inner_width.saturating_sub(1) / (CELL_WIDTH + 1)).clamp(1, self.game.config.cols)
+    }
+
+    // Furthest the view can be scrolled when rendered into area, 0 if the board fits
+    pub fn max_scroll(&self, area: Rect) -> usize {
+        self.game.config.cols - self.visible_cols(area)
+    }
+
+    // Columns drawn when rendered into area
+    fn visible_range(&self, area: Rect) -> Range<usize> {
+        let first = self.scroll.min(self.max_scroll(area));
+        first..first + self.visible_cols(area)
+    }
+
+    // Where the grid lines (header row included) end up when rendered into area
+    pub fn board_rect(&self, area: Rect) -> Rect {
+        let padding = self.fitted_padding(area);
+        let title_height = u16::from(self.title.is_some());
+        let inner_x = area.x + padding;
+        let inner_width = area.width.saturating_sub(padding * 2);
+        let visible_width = (self.visible_range(area).len() * (CELL_WIDTH + 1) + 1) as u16;
+        let width = visible_width.min(inner_width);
+        let x = match self.alignment {
+            Alignment::Left => inner_x,
+            Alignment::Center => inner_x + (inner_width - width) / 2,
+            Alignment::Right => inner_x + inner_width - width,
+        };
+        let height =
+            (self.game.config.rows * 2 + 1) as u16 + u16::from(self.column_scores.is_some());
+        Rect {
+            x,
+            y: area.y + title_height,
+            width,
+            height: height.min(area.height.saturating_sub(title_height)),
+        }
+    }
+
+    // Column under a screen position inside the board, dividers count toward the cell left of them
+    pub fn column_at(&self, area: Rect, x: u16, y: u16) -> Option<usize> {
+        let board = self.board_rect(area);
+        if x <= board.x
+            || x >= board.right().saturating_sub(1)
+            || y < board.y
+            || y >= board.bottom()
+        {
+            return None;
+        }
+        let range = self.visible_range(area);
+        let col = range.start + (x - board.x - 1) as usize / (CELL_WIDTH + 1);
+        range.contains(&col).then_some(col)
+    }
 }
 
 impl<'a> Widget for GridWidget<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        let block = Block::default().border_set(border::THICK);
+        let padding = self.fitted_padding(area);
+        let range = self.visible_range(area);
+        let cols = self.game.config.cols;
+        let mut block = Block::default()
+            .border_set(border::THICK)
+            .padding(Padding::horizontal(padding));
+        if let Some(title) = &self.title {
+            block = block.title_top(Line::from(title.as_str()).gray().alignment(self.alignment));
+        }
+        // Which part of a board wider than the area is in view
+        if range.len() < cols {
+            let indicator = format!(
+                "{} columns {}-{} of {} {}",
+                if range.start > 0 { "◀" } else { " " },
+                column_label(range.start),
+                column_label(range.end - 1),
+                cols,
+                if range.end < cols { "▶" } else { " " },
+            );
+            block = block.title_bottom(Line::from(indicator).gray().alignment(self.alignment));
+        }
 
         // Build the grid display
 
         let mut grid = Text::default();
 
-        // Add column numbers
+        // Column scores, the best on green and the worst on red. Scores too wide for the
+        // cell are shown as an arrow in their direction
+        if let Some(scores) = self.column_scores {
+            let best = scores.iter().map(|&(_, score)| score).max();
+            let worst = scores.iter().map(|&(_, score)| score).min();
+            let mut row = Line::default();
+            row.spans.push(" ".into());
+            for i in range.clone() {
+                let score = scores
+                    .iter()
+                    .find(|&&(col, _)| col == i)
+                    .map(|&(_, score)| score);
+                let text = match score {
+                    Some(score) if score.to_string().len() <= CELL_WIDTH => score.to_string(),
+                    Some(score) if score > 0 => "▲".to_string(),
+                    Some(_) => "▼".to_string(),
+                    None => String::new(),
+                };
+                let cell = format!("{:^width$}", text, width = CELL_WIDTH);
+                row.spans.push(match score {
+                    _ if best == worst => cell.into(),
+                    Some(score) if Some(score) == best => cell.black().on_green(),
+                    Some(score) if Some(score) == worst => cell.black().on_red(),
+                    _ => cell.into(),
+                });
+                row.spans.push(" ".into());
+            }
+            grid.lines.push(row);
+        }
+
+        // Add column numbers, centered over each cell
+        // (leading space lines up with the left border, trailing space with the divider)
         let mut header = Line::default();
-        for i in 0..self.game.config.cols {
-            header.spans.push(format!(" {}  ", i + 1).bold().blue());
+        header.spans.push(" ".into());
+        for i in range.clone().filter(|_| self.show_header) {
+            let number = format!("{:^width$}", column_label(i), width = CELL_WIDTH)
+                .bold()
+                .blue();
+            header.spans.push(if self.preview_column == Some(i) {
+                number.reversed()
+            } else {
+                number
+            });
+            header.spans.push(" ".into());
         }
         grid.lines.push(header);
 
@@ -393,21 +1564,42 @@ impl<'a> Widget for GridWidget<'a> {
             None
         };
 
+        let preview_cell = self
+            .preview_column
+            .and_then(|col| Some((self.game.landing_row(col)?, col)));
+
         // Add the game board
         for row in 0..self.game.config.rows {
             let mut line = Line::default();
             line.spans.push("│".into()); // Left border
 
-            for col in 0..self.game.config.cols {
-                let mut cell = match self.game.get_cell(row, col) {
+            for col in range.clone() {
+                let shown = self.shown_cell(row, col);
+                let hidden = self.game.state == GameState::InProgress
+                    && self.hidden_player.is_some()
+                    && shown == self.hidden_player;
+                let falling = self
+                    .falling
+                    .filter(|&(falling_row, falling_col, _)| {
+                        (falling_row, falling_col) == (row, col)
+                    })
+                    .map(|(_, _, player)| player);
+                let hidden = hidden || (falling.is_some() && falling == self.hidden_player);
+                let mut cell = match shown.or(falling) {
+                    None if self.game.is_blocked(row, col) => "███".dark_gray(),
+                    Some(_) if hidden => " ● ".gray(),
                     Some(Player::Red) => " ● ".red(),
                     Some(Player::Yellow) => " ● ".yellow(),
+                    None if preview_cell == Some((row, col)) => match self.game.current_player {
+                        Player::Red => " ○ ".red(),
+                        Player::Yellow => " ○ ".yellow(),
+                    },
                     None => " · ".gray(),
                 };
-                if let Some(winning_cells) = &winning_cells {
-                    if winning_cells.contains(&(row, col)) {
-                        cell = cell.on_light_green();
-                    }
+                if let Some(winning_cells) = &winning_cells
+                    && winning_cells.contains(&(row, col))
+                {
+                    cell = cell.on_light_green();
                 }
                 line.spans.push(cell);
                 line.spans.push("│".into()); // Cell divider
@@ -419,9 +1611,9 @@ impl<'a> Widget for GridWidget<'a> {
             if row < self.game.config.rows - 1 {
                 let mut separator = Line::default();
                 separator.spans.push("├".into());
-                for col in 0..self.game.config.cols {
-                    separator.spans.push("───".into());
-                    if col < self.game.config.cols - 1 {
+                for col in range.clone() {
+                    separator.spans.push("─".repeat(CELL_WIDTH).into());
+                    if col < range.end - 1 {
                         separator.spans.push("┼".into());
                     } else {
                         separator.spans.push("┤".into());
@@ -434,9 +1626,9 @@ impl<'a> Widget for GridWidget<'a> {
         // Add bottom border
         let mut bottom = Line::default();
         bottom.spans.push("└".into());
-        for col in 0..self.game.config.cols {
-            bottom.spans.push("───".into());
-            if col < self.game.config.cols - 1 {
+        for col in range.clone() {
+            bottom.spans.push("─".repeat(CELL_WIDTH).into());
+            if col < range.end - 1 {
                 bottom.spans.push("┴".into());
             } else {
                 bottom.spans.push("┘".into());
@@ -445,8 +1637,828 @@ impl<'a> Widget for GridWidget<'a> {
         grid.lines.push(bottom);
 
         Paragraph::new(grid)
-            .centered()
+            .alignment(self.alignment)
             .block(block)
             .render(area, buf)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Board where Yellow, to move, completes a line of the preset's connect length from
+    // start by dropping into the missing cell. The line sits on columns of filler pieces,
+    // alternating up each column and every two columns across so they form no line of their
+    // own. Fixtures are picked so no Yellow filler extends the line
+    fn almost_line(
+        preset: GameConfigPreset,
+        start: (usize, usize),
+        direction: (i32, i32),
+        missing: usize,
+    ) -> (Game, usize, Vec<(usize, usize)>) {
+        let config = preset.into_config();
+        let mut game = Game::with_config(config);
+        let line: Vec<(usize, usize)> = (0..config.connect_length as i32)
+            .map(|i| {
+                (
+                    (start.0 as i32 + direction.0 * i) as usize,
+                    (start.1 as i32 + direction.1 * i) as usize,
+                )
+            })
+            .collect();
+
+        for &(row, col) in &line {
+            for filler_row in row + 1..config.rows {
+                if line.contains(&(filler_row, col)) {
+                    continue;
+                }
+                let height = config.rows - 1 - filler_row;
+                let filler = if (col / 2 + height).is_multiple_of(2) {
+                    Player::Red
+                } else {
+                    Player::Yellow
+                };
+                game.board.set(filler_row, col, Cell::Piece(filler));
+            }
+        }
+        for (i, &(row, col)) in line.iter().enumerate() {
+            if i != missing {
+                game.board.set(row, col, Cell::Piece(Player::Yellow));
+            }
+        }
+        game.current_player = Player::Yellow;
+        assert_eq!(game.scan_win(), None, "the fixture is already won");
+        (game, line[missing].1, line)
+    }
+
+    // Play the missing cell and check the state, the highlight and the full scan agree
+    fn assert_completes(
+        preset: GameConfigPreset,
+        start: (usize, usize),
+        direction: (i32, i32),
+        missing: usize,
+    ) {
+        let (mut game, col, line) = almost_line(preset, start, direction, missing);
+        assert_eq!(game.place(col), Some(GameState::Won(Player::Yellow)));
+        let mut highlighted = game.get_winning_combination().unwrap().to_vec();
+        highlighted.sort_unstable();
+        let mut expected = line;
+        expected.sort_unstable();
+        assert_eq!(highlighted, expected);
+        assert_eq!(game.scan_win(), Some(Player::Yellow));
+    }
+
+    #[test]
+    fn opponent_swaps_back_to_the_same_player() {
+        for player in [Player::Yellow, Player::Red] {
+            assert_ne!(player.opponent(), player);
+            assert_eq!(player.opponent().opponent(), player);
+        }
+    }
+
+    #[test]
+    fn unplayable_configs_are_rejected_or_clamped() {
+        let config = |rows, cols, connect_length| GameConfig {
+            rows,
+            cols,
+            connect_length,
+            ..GameConfig::default()
+        };
+        let unplayable = [
+            config(0, 7, 4),
+            config(6, 0, 4),
+            config(0, 0, 0),
+            config(6, 7, 0),
+            config(6, 7, 1),
+            config(200, 7, 4),
+            config(6, 200, 4),
+            config(6, 7, 8),
+            config(6, 7, 500),
+        ];
+        for config in unplayable {
+            assert!(
+                GameConfig::custom(config.rows, config.cols, config.connect_length).is_err(),
+                "{:?} was accepted",
+                config
+            );
+
+            // Clamped into a board that holds a line, a game on it plays to the end
+            let clamped = config.clamped();
+            assert!(clamped.rows >= 1 && clamped.cols >= 1);
+            assert!(clamped.rows * clamped.cols <= Board::MAX_CELLS);
+            assert!(clamped.connect_length >= GameConfig::MIN_CONNECT_LENGTH);
+            assert!(clamped.connect_length <= clamped.rows.max(clamped.cols));
+            assert_eq!(clamped.clamped(), clamped);
+            let mut game = Game::with_config(config);
+            assert_eq!(*game.config(), clamped);
+            while let Some(&col) = game.valid_moves().first() {
+                if game.place(col) != Some(GameState::InProgress) {
+                    break;
+                }
+            }
+            assert_ne!(*game.state(), GameState::InProgress, "{:?}", config);
+        }
+        assert_eq!(config(6, 7, 4).clamped(), GameConfig::default());
+    }
+
+    #[test]
+    fn pieces_settle_on_top_of_obstacles() {
+        let config = GameConfig::default();
+        let game = Game::with_obstacles(config, &[(3, 0), (5, 1)]);
+        assert_eq!(game.landing_row(0), Some(2));
+        assert_eq!(game.landing_row(1), Some(4));
+
+        // The cells shut off under an obstacle are never reached
+        let game = game.play_notation("111").unwrap();
+        assert_eq!(game.get_cell(2, 0), Some(Player::Yellow));
+        assert_eq!(game.get_cell(0, 0), Some(Player::Yellow));
+        assert!((3..config.rows).all(|row| game.get_cell(row, 0).is_none()));
+        assert!(game.is_blocked(3, 0));
+        assert!(game.is_column_full(0));
+        assert!(!game.valid_moves().contains(&0));
+    }
+
+    #[test]
+    fn obstacles_break_lines() {
+        // An obstacle in the middle of the bottom row, Yellow takes the three cells left of it
+        // while Red stacks in the last column
+        let config = GameConfig::default();
+        let game = Game::with_obstacles(config, &[(5, 3)]);
+        let mut game = game.play_notation("172737").unwrap();
+        assert!(game.threats(Player::Yellow).is_empty());
+        // The piece right of the obstacle doesn't join them
+        assert_eq!(game.place(4), Some(GameState::InProgress));
+        assert_eq!(game.scan_win(), None);
+
+        // Without the obstacle the fourth piece completes the row
+        let open = Game::from_notation(config, "1727374").unwrap();
+        assert_eq!(*open.state(), GameState::Won(Player::Yellow));
+
+        // Windows through the obstacle are closed for both players from the start
+        let blocked = Game::with_obstacles(config, &[(5, 3)]);
+        let empty = Game::with_config(config);
+        for player in [Player::Yellow, Player::Red] {
+            assert_eq!(
+                empty.winnable_lines(player) - blocked.winnable_lines(player),
+                7
+            );
+        }
+    }
+
+    #[test]
+    fn lines_stop_at_the_edges_and_corners() {
+        let config = GameConfig::default();
+        let game = Game::with_obstacles(config, &[(5, 3)])
+            .play_notation("12")
+            .unwrap();
+        let line =
+            |start, direction, length| game.line(start, direction, length).collect::<Vec<_>>();
+
+        // Along the bottom row, the obstacle reads as an empty cell and doesn't end the line
+        assert_eq!(
+            line((5, 0), (0, 1), 7),
+            [
+                Some(Player::Yellow),
+                Some(Player::Red),
+                None,
+                None,
+                None,
+                None,
+                None
+            ]
+        );
+        // Up the first column to the top edge, however long the line asked for
+        assert_eq!(line((5, 0), (-1, 0), 10).len(), config.rows);
+
+        // Every corner has a single cell in the directions leaving the board
+        let corners = [(0, 0), (0, 6), (5, 0), (5, 6)];
+        for (row, col) in corners {
+            let row_dir = if row == 0 { -1 } else { 1 };
+            let col_dir = if col == 0 { -1 } else { 1 };
+            for direction in [
+                (row_dir, 0),
+                (0, col_dir),
+                (row_dir, col_dir),
+                (row_dir, -col_dir),
+            ] {
+                assert_eq!(
+                    line((row, col), direction, 4).len(),
+                    1,
+                    "{:?} {:?}",
+                    (row, col),
+                    direction
+                );
+            }
+            // And the full length along the diagonal into the board
+            assert_eq!(line((row, col), (-row_dir, -col_dir), 4).len(), 4);
+        }
+        // A diagonal from an edge runs out at the other edge
+        assert_eq!(line((2, 0), (1, 1), 4).len(), 4);
+        assert_eq!(line((3, 0), (1, 1), 4).len(), 3);
+        assert_eq!(line((5, 1), (-1, -1), 4), [Some(Player::Red), None]);
+    }
+
+    #[test]
+    fn connect_five_horizontal_wins_at_the_right_edge() {
+        assert_completes(GameConfigPreset::Large, (7, 3), (0, 1), 4);
+    }
+
+    #[test]
+    fn connect_five_vertical_wins_reaching_the_top_row() {
+        assert_completes(GameConfigPreset::Large, (0, 0), (1, 0), 0);
+    }
+
+    #[test]
+    fn connect_five_rising_diagonal_wins_from_the_bottom_left_corner() {
+        assert_completes(GameConfigPreset::Large, (7, 0), (-1, 1), 0);
+    }
+
+    #[test]
+    fn connect_five_falling_diagonal_wins_from_the_top_left_corner() {
+        assert_completes(GameConfigPreset::Large, (0, 0), (1, 1), 0);
+    }
+
+    #[test]
+    fn connect_six_horizontal_wins_at_the_right_edge() {
+        assert_completes(GameConfigPreset::Huge, (9, 4), (0, 1), 5);
+    }
+
+    #[test]
+    fn connect_six_vertical_wins_reaching_the_top_row() {
+        assert_completes(GameConfigPreset::Huge, (0, 7), (1, 0), 0);
+    }
+
+    #[test]
+    fn connect_six_rising_diagonal_wins_into_the_top_right_corner() {
+        assert_completes(GameConfigPreset::Huge, (5, 4), (-1, 1), 5);
+    }
+
+    #[test]
+    fn connect_six_falling_diagonal_wins_into_the_bottom_right_corner() {
+        assert_completes(GameConfigPreset::Huge, (4, 4), (1, 1), 5);
+    }
+
+    // Start cell, direction and index of the cell left to play of a line
+    type LineFixture = ((usize, usize), (i32, i32), usize);
+
+    // The line of every orientation that ends at the bottom, or the first column for the
+    // horizontal one
+    fn bottom_lines(preset: GameConfigPreset) -> [LineFixture; 4] {
+        let config = preset.into_config();
+        let (bottom, length) = (config.rows - 1, config.connect_length);
+        [
+            ((bottom, 0), (0, 1), length - 1),
+            ((config.rows - length, 0), (1, 0), 0),
+            ((bottom, 0), (-1, 1), 0),
+            ((config.rows - length, 0), (1, 1), length - 1),
+        ]
+    }
+
+    // A line of exactly the connect length wins in every orientation, one piece shorter
+    // does not
+    fn assert_wins_at_exactly_the_connect_length(preset: GameConfigPreset) {
+        for (start, direction, missing) in bottom_lines(preset) {
+            assert_completes(preset, start, direction, missing);
+
+            // Take the piece off the other end, or for the vertical line the one under the
+            // top, so the last move makes one piece less
+            let (mut game, col, line) = almost_line(preset, start, direction, missing);
+            let removed = match (direction, missing) {
+                ((1, 0), _) => 1,
+                (_, 0) => line.len() - 1,
+                _ => 0,
+            };
+            let (row, removed_col) = line[removed];
+            game.board.set(row, removed_col, Cell::Empty);
+            assert_eq!(
+                game.place(col),
+                Some(GameState::InProgress),
+                "{} won with a line of {} in direction {:?}",
+                preset,
+                line.len() - 1,
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn standard_wins_at_exactly_four() {
+        assert_wins_at_exactly_the_connect_length(GameConfigPreset::Standard);
+    }
+
+    #[test]
+    fn small_wins_at_exactly_three() {
+        assert_wins_at_exactly_the_connect_length(GameConfigPreset::Small);
+    }
+
+    #[test]
+    fn large_wins_at_exactly_five() {
+        assert_wins_at_exactly_the_connect_length(GameConfigPreset::Large);
+    }
+
+    #[test]
+    fn huge_wins_at_exactly_six() {
+        assert_wins_at_exactly_the_connect_length(GameConfigPreset::Huge);
+    }
+
+    #[test]
+    fn pop_out_wins_at_exactly_four() {
+        assert_wins_at_exactly_the_connect_length(GameConfigPreset::PopOut);
+    }
+
+    #[test]
+    fn the_cached_winning_line_matches_a_fresh_search_until_undone_or_reset() {
+        let presets = [
+            GameConfigPreset::Standard,
+            GameConfigPreset::Small,
+            GameConfigPreset::Large,
+            GameConfigPreset::Huge,
+        ];
+        for preset in presets {
+            let lines = bottom_lines(preset);
+            for direction in Game::LINE_DIRECTIONS {
+                let &(start, _, missing) = lines
+                    .iter()
+                    .find(|(_, line_direction, _)| *line_direction == direction)
+                    .unwrap();
+                let (mut game, col, _) = almost_line(preset, start, direction, missing);
+                assert_eq!(game.get_winning_combination(), None);
+                game.place(col);
+                let fresh = game.find_winning_combination();
+                assert!(fresh.is_some(), "{} {:?}", preset, direction);
+                assert_eq!(
+                    game.get_winning_combination(),
+                    fresh.as_deref(),
+                    "{} {:?}",
+                    preset,
+                    direction
+                );
+
+                // Undoing the winning move clears it, redoing it finds the same line again
+                game.undo();
+                assert_eq!(game.get_winning_combination(), None);
+                game.redo();
+                assert_eq!(game.get_winning_combination(), fresh.as_deref());
+
+                // So does resetting the won game
+                let mut app = crate::app::App::new().unwrap();
+                app.game = game;
+                app.reset();
+                assert_eq!(app.game.get_winning_combination(), None);
+            }
+        }
+    }
+
+    // Board kept as a plain grid of cells, applying every rule one cell at a time, for the
+    // packed board to agree with
+    struct ReferenceGrid {
+        cells: Vec<Vec<Cell>>,
+    }
+
+    impl ReferenceGrid {
+        fn of(game: &Game) -> Self {
+            let config = game.config();
+            let cells = (0..config.rows)
+                .map(|row| {
+                    (0..config.cols)
+                        .map(|col| match game.get_cell(row, col) {
+                            Some(player) => Cell::Piece(player),
+                            None if game.is_blocked(row, col) => Cell::Blocked,
+                            None => Cell::Empty,
+                        })
+                        .collect()
+                })
+                .collect();
+            ReferenceGrid { cells }
+        }
+
+        fn rows(&self) -> usize {
+            self.cells.len()
+        }
+
+        fn cols(&self) -> usize {
+            self.cells[0].len()
+        }
+
+        fn valid_moves(&self) -> Vec<usize> {
+            (0..self.cols())
+                .filter(|&col| self.cells[0][col] == Cell::Empty)
+                .collect()
+        }
+
+        fn drop(&mut self, col: usize, player: Player) {
+            let row = (0..self.rows())
+                .take_while(|&row| self.cells[row][col] == Cell::Empty)
+                .last()
+                .unwrap();
+            self.cells[row][col] = Cell::Piece(player);
+        }
+
+        fn can_pop(&self, col: usize, player: Player) -> bool {
+            self.cells[self.rows() - 1][col] == Cell::Piece(player)
+        }
+
+        // The pieces above the bottom one fall a row, up to the first gap or obstacle
+        fn pop(&mut self, col: usize) {
+            let mut row = self.rows() - 1;
+            while row > 0 && self.cells[row - 1][col].piece().is_some() {
+                self.cells[row][col] = self.cells[row - 1][col];
+                row -= 1;
+            }
+            self.cells[row][col] = Cell::Empty;
+        }
+
+        fn has_line(&self, player: Player, length: usize) -> bool {
+            let (rows, cols) = (self.rows() as i32, self.cols() as i32);
+            let owned = |row: i32, col: i32| {
+                (0..rows).contains(&row)
+                    && (0..cols).contains(&col)
+                    && self.cells[row as usize][col as usize] == Cell::Piece(player)
+            };
+            (0..rows).any(|row| {
+                (0..cols).any(|col| {
+                    [(0, 1), (1, 0), (1, 1), (-1, 1)].iter().any(|&(dr, dc)| {
+                        (0..length as i32).all(|i| owned(row + dr * i, col + dc * i))
+                    })
+                })
+            })
+        }
+    }
+
+    fn assert_matches_reference(game: &Game, reference: &ReferenceGrid) {
+        for row in 0..reference.rows() {
+            for col in 0..reference.cols() {
+                assert_eq!(
+                    game.get_cell(row, col),
+                    reference.cells[row][col].piece(),
+                    "cell ({}, {}) on {}",
+                    row,
+                    col,
+                    game.config()
+                );
+            }
+        }
+        assert_eq!(game.valid_moves(), reference.valid_moves());
+    }
+
+    #[test]
+    fn each_win_direction_gets_its_label() {
+        for (start, direction, missing) in bottom_lines(GameConfigPreset::Standard) {
+            let (mut game, col, _) =
+                almost_line(GameConfigPreset::Standard, start, direction, missing);
+            assert_eq!(game.win_direction(), None);
+            game.place(col);
+            let (expected, label) = match direction {
+                (0, 1) => (WinDirection::Horizontal, "a horizontal line"),
+                (1, 0) => (WinDirection::Vertical, "a vertical line"),
+                _ => (WinDirection::Diagonal, "a diagonal"),
+            };
+            assert_eq!(game.win_direction(), Some(expected), "{:?}", direction);
+            assert_eq!(expected.to_string(), label);
+        }
+    }
+
+    #[test]
+    fn random_games_agree_with_a_reference_grid() {
+        use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+        for index in 0..GameConfigPreset::amount_of_presets() {
+            let config = GameConfigPreset::from_index(index).into_config();
+            for seed in 0..40 {
+                let mut rng = StdRng::seed_from_u64(seed);
+                // Every other game with obstacles, which hold up the pieces above them
+                let mut game = if seed % 2 == 0 {
+                    Game::with_config(config)
+                } else {
+                    let obstacles = Game::random_obstacles(config, 4, &mut rng);
+                    Game::with_obstacles(config, &obstacles)
+                };
+                let mut reference = ReferenceGrid::of(&game);
+
+                for _ in 0..200 {
+                    if *game.state() != GameState::InProgress {
+                        break;
+                    }
+                    assert_matches_reference(&game, &reference);
+                    let mover = game.current_player();
+                    let pops: Vec<usize> = (0..reference.cols())
+                        .filter(|&col| config.pop_out && reference.can_pop(col, mover))
+                        .collect();
+                    for col in 0..reference.cols() {
+                        assert_eq!(game.can_pop(col, mover), pops.contains(&col));
+                    }
+
+                    let drops = reference.valid_moves();
+                    // A full Pop Out board goes on with pops only
+                    let expected = if !pops.is_empty() && (drops.is_empty() || rng.random_bool(0.3))
+                    {
+                        let col = *pops.choose(&mut rng).unwrap();
+                        game.pop(col).unwrap();
+                        reference.pop(col);
+                        // The mover wins a pop completing lines of both players
+                        [mover, mover.opponent()]
+                            .into_iter()
+                            .find(|&player| reference.has_line(player, game.win_length(player)))
+                    } else {
+                        let col = *drops.choose(&mut rng).unwrap();
+                        game.place(col).unwrap();
+                        reference.drop(col, mover);
+                        reference
+                            .has_line(mover, game.win_length(mover))
+                            .then_some(mover)
+                    };
+                    match expected {
+                        Some(winner) => assert_eq!(*game.state(), GameState::Won(winner)),
+                        None => assert!(!matches!(game.state(), GameState::Won(_))),
+                    }
+                }
+                assert_matches_reference(&game, &reference);
+            }
+        }
+    }
+
+    #[test]
+    fn incremental_and_full_scan_win_checks_agree() {
+        use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+        let config = GameConfig::default();
+        for seed in 0..100 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut incremental = Game::with_config(config);
+            let mut full_scan = Game::with_config(config);
+            full_scan.set_win_check(WinCheck::FullScan);
+            while *incremental.state() == GameState::InProgress {
+                let col = *incremental.valid_moves().choose(&mut rng).unwrap();
+                assert_eq!(
+                    incremental.place(col),
+                    full_scan.place(col),
+                    "seed {}",
+                    seed
+                );
+                assert_eq!(
+                    incremental.get_winning_combination(),
+                    full_scan.get_winning_combination()
+                );
+            }
+            assert_eq!(full_scan.state(), incremental.state());
+        }
+
+        // A line put on the board without a move is only found by scanning it
+        let mut incremental = Game::from_notation(config, "44").unwrap();
+        for col in 0..4 {
+            incremental
+                .board
+                .set(config.rows - 1, col, Cell::Piece(Player::Red));
+        }
+        let mut full_scan = incremental.clone();
+        full_scan.set_win_check(WinCheck::FullScan);
+        assert_eq!(incremental.scan_win(), Some(Player::Red));
+        assert_eq!(incremental.place(6), Some(GameState::InProgress));
+        assert_eq!(full_scan.place(6), Some(GameState::Won(Player::Red)));
+    }
+
+    #[test]
+    fn winnable_lines_close_as_the_opponent_blocks_them() {
+        let mut game = Game::new();
+        let windows = game.winnable_lines(Player::Yellow);
+        assert_eq!(windows, 69);
+        assert_eq!(game.winnable_lines(Player::Red), windows);
+
+        // A corner piece is on one line each way, a center one on four across, one up and
+        // one along each diagonal
+        game.place(0);
+        assert_eq!(game.winnable_lines(Player::Yellow), windows);
+        assert_eq!(game.winnable_lines(Player::Red), windows - 3);
+        game.place(3);
+        assert_eq!(game.winnable_lines(Player::Yellow), windows - 7);
+        // Next to the corner it's on four lines, one of them already closed to Red
+        game.place(1);
+        assert_eq!(game.winnable_lines(Player::Red), windows - 3 - 3);
+
+        // Once neither player can complete a line the game is drawn, before the board fills
+        let notation = "547245442566111124665256764377333233";
+        let (moves, last) = notation.split_at(notation.len() - 1);
+        let mut game = Game::from_notation(GameConfig::default(), moves).unwrap();
+        assert!(game.winnable_lines(Player::Yellow) + game.winnable_lines(Player::Red) > 0);
+        game = game.play_notation(last).unwrap();
+        assert_eq!(game.winnable_lines(Player::Yellow), 0);
+        assert_eq!(game.winnable_lines(Player::Red), 0);
+        assert_eq!(*game.state(), GameState::Draw);
+        assert!(!game.valid_moves().is_empty());
+    }
+
+    #[test]
+    fn pops_count_as_plies_but_take_pieces_off() {
+        let mut game = Game::with_config(GameConfigPreset::PopOut.into_config());
+        game.place(0);
+        game.place(1);
+        game.pop(0);
+        game.pop(1);
+        assert_eq!(game.plies(), 4);
+        assert_eq!(game.move_count(), 0);
+        // The board is empty again, but it is no longer the opening
+        assert_eq!(crate::agent::center_opening(&game), None);
+
+        game.undo();
+        assert_eq!(game.plies(), 3);
+        game.redo();
+        assert_eq!(game.plies(), 4);
+    }
+
+    #[test]
+    fn an_endless_pop_out_game_keeps_only_the_recent_moves_for_undo() {
+        let mut game = Game::with_config(GameConfigPreset::PopOut.into_config());
+        // Both players drop and pop their piece back out, the board is empty after each round
+        let rounds = Game::MOVE_HISTORY;
+        for _ in 0..rounds {
+            assert_eq!(game.place(0), Some(GameState::InProgress));
+            assert_eq!(game.place(1), Some(GameState::InProgress));
+            assert_eq!(game.pop(0), Some(GameState::InProgress));
+            assert_eq!(game.pop(1), Some(GameState::InProgress));
+        }
+        assert_eq!(game.move_stack.len(), Game::MOVE_HISTORY);
+
+        for _ in 0..Game::MOVE_HISTORY / 4 {
+            assert_eq!(game.undo(), Some(1));
+            assert_eq!(game.undo(), Some(0));
+            assert_eq!(
+                game.get_cell(game.config().rows - 1, 0),
+                Some(Player::Yellow)
+            );
+            assert_eq!(game.undo(), Some(1));
+            assert_eq!(game.undo(), Some(0));
+        }
+        assert_eq!(game.undo(), None);
+        assert_eq!(game.current_player(), Player::Yellow);
+        assert_eq!(game.popped(Player::Yellow), rounds - Game::MOVE_HISTORY / 4);
+    }
+
+    #[test]
+    fn saved_games_that_dont_add_up_are_refused() {
+        let load = |game: &Game| Game::from_json(&serde_json::to_string(game).unwrap());
+
+        // A Pop Out game with a pop in it comes back whole, and takes back like the original
+        let mut game = Game::from_notation(GameConfigPreset::PopOut.into_config(), "1122").unwrap();
+        assert_eq!(game.pop(0), Some(GameState::InProgress));
+        let mut loaded = load(&game).unwrap();
+        assert_eq!(loaded.to_key(), game.to_key());
+        assert_eq!((loaded.plies(), loaded.popped(Player::Yellow)), (5, 1));
+        assert_eq!(loaded.undo(), Some(0));
+        assert_eq!(loaded.popped(Player::Yellow), 0);
+
+        // Pieces off the board or two to a cell
+        let mut broken = game.clone();
+        broken.board.yellow |= 1 << 100;
+        assert!(load(&broken).is_err());
+        let mut broken = game.clone();
+        broken.board.red |= broken.board.yellow;
+        assert!(load(&broken).is_err());
+        // A pop nobody is counted for, undo would take the count below zero
+        let mut broken = game.clone();
+        broken.popped = [0, 0];
+        assert!(load(&broken).is_err());
+        // A history leading somewhere else, or with the wrong player to move
+        let mut broken = game.clone();
+        broken.move_stack[0].0 = Move::Drop(5);
+        assert!(load(&broken).is_err());
+        let mut broken = game.clone();
+        broken.current_player = Player::Yellow;
+        assert!(load(&broken).is_err());
+        // Undone moves that can't be played again
+        let mut broken = game.clone();
+        broken.redo_stack.push((Move::Drop(99), Player::Red));
+        assert!(load(&broken).is_err());
+
+        // A history cut short is checked by taking it back from the board
+        let mut cut_short = game.clone();
+        cut_short.plies += 10;
+        assert!(load(&cut_short).is_ok());
+        cut_short.popped = [0, 0];
+        assert!(load(&cut_short).is_err());
+        let mut endless = Game::with_config(GameConfigPreset::PopOut.into_config());
+        for _ in 0..Game::MOVE_HISTORY {
+            endless.place(0);
+            endless.place(1);
+            endless.pop(0);
+            endless.pop(1);
+        }
+        assert!(endless.plies() > endless.move_stack.len());
+        assert!(load(&endless).is_ok());
+
+        assert!(Game::from_json("{").is_err());
+    }
+
+    #[test]
+    fn searched_positions_leave_the_history_behind() {
+        let mut game = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        game.undo();
+        assert_eq!(game.move_stack.len(), 3);
+        assert_eq!(game.redo_stack.len(), 1);
+
+        let child = game.with_move(2).unwrap();
+        // Only the move played on it
+        assert_eq!(child.move_stack.len(), 1);
+        assert!(child.redo_stack.is_empty());
+        assert_eq!(
+            child.to_key(),
+            game.clone().play_notation("3").unwrap().to_key()
+        );
+        assert_eq!(child.plies(), 4);
+        assert_eq!(game.position().to_key(), game.to_key());
+
+        // Imported moves can still be taken back
+        assert_eq!(game.undo(), Some(4));
+    }
+
+    #[test]
+    fn mirroring_or_swapping_colors_twice_gives_back_the_position() {
+        use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+        let mut rng = StdRng::seed_from_u64(9);
+        for preset in (0..GameConfigPreset::amount_of_presets()).map(GameConfigPreset::from_index) {
+            let config = preset.into_config();
+            let obstacles = Game::random_obstacles(config, 3, &mut rng);
+            for _ in 0..20 {
+                let mut game = Game::with_obstacles(config, &obstacles);
+                while *game.state() == GameState::InProgress && rng.random_bool(0.9) {
+                    let col = *game.valid_moves().choose(&mut rng).unwrap();
+                    game.place(col);
+                }
+                let position = game.position();
+                assert_eq!(game.mirrored().mirrored(), position);
+                assert_eq!(game.colors_swapped().colors_swapped(), position);
+
+                let mirrored = game.mirrored();
+                for player in [Player::Yellow, Player::Red] {
+                    assert_eq!(mirrored.winnable_lines(player), game.winnable_lines(player));
+                    assert_eq!(
+                        game.colors_swapped().winnable_lines(player.opponent()),
+                        game.winnable_lines(player)
+                    );
+                }
+                assert_eq!(mirrored.scan_win(), game.scan_win());
+                for row in 0..config.rows {
+                    for col in 0..config.cols {
+                        let mirror_col = config.cols - 1 - col;
+                        assert_eq!(mirrored.get_cell(row, mirror_col), game.get_cell(row, col));
+                        assert_eq!(
+                            mirrored.is_blocked(row, mirror_col),
+                            game.is_blocked(row, col)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clicks_anywhere_in_a_column_map_to_it() {
+        let game = Game::new();
+        let grid = GridWidget {
+            game: &game,
+            hidden_player: None,
+            show_header: true,
+            preview_column: None,
+            column_scores: None,
+            alignment: Alignment::Left,
+            padding: 0,
+            title: None,
+            scroll: 0,
+            falling: None,
+            popped_column: None,
+        };
+        let area = Rect::new(10, 5, 60, 20);
+        let board = grid.board_rect(area);
+        assert_eq!((board.x, board.y), (10, 5));
+        // Cells start after the left border, each one followed by its divider
+        let cell_x =
+            |col: usize, offset: u16| board.x + 1 + (col * (CELL_WIDTH + 1)) as u16 + offset;
+        let (top, bottom) = (board.y, board.bottom() - 1);
+        let last = game.config.cols - 1;
+        for col in 0..=last {
+            let mut points = vec![
+                (cell_x(col, 0), top),
+                (cell_x(col, 1), top + 5),
+                (cell_x(col, 2), bottom),
+            ];
+            // The last column's divider is the right border
+            if col < last {
+                points.push((cell_x(col, 3), top + 2));
+            }
+            for (x, y) in points {
+                assert_eq!(grid.column_at(area, x, y), Some(col), "({}, {})", x, y);
+            }
+        }
+        // Borders and the space around the board
+        for (x, y) in [
+            (board.x, top + 1),
+            (board.right() - 1, top + 1),
+            (cell_x(3, 1), top - 1),
+            (cell_x(3, 1), bottom + 1),
+            (board.right() + 5, top + 1),
+        ] {
+            assert_eq!(grid.column_at(area, x, y), None, "({}, {})", x, y);
+        }
+    }
+}
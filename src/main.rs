@@ -1,37 +1,728 @@
 mod agent;
 mod app;
+mod benchmark;
+mod error;
+mod evaluator;
 mod game;
+mod mcts_agent;
 mod minimax_agent;
+mod negamax_agent;
+mod network;
+mod policy_agent;
+mod puzzle;
+mod recorder;
+mod records;
 mod rl_agent;
+mod rules_agent;
+mod solver;
 
 use std::{
+    fmt,
     io::{self, Stdout, stdout},
-    time::Duration,
-    u64::MAX,
+    net::TcpStream,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
 };
 
 use agent::Agents;
-use app::render;
-use color_eyre::Result;
+use app::{TimeControl, render};
+use color_eyre::{Result, eyre};
 use crossterm::{
-    event::{self, KeyCode, KeyEventKind},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, KeyCode, KeyEventKind, MouseButton,
+        MouseEventKind,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use game::{GameConfigPreset, GameState, Player};
-use ratatui::{DefaultTerminal, Terminal, prelude::CrosstermBackend};
+use game::{
+    Game, GameConfig, GameConfigPreset, GameState, Handicap, Obstacles, Player, WinCheck,
+    column_from_label, column_label,
+};
+use policy_agent::PolicyAgent;
+use ratatui::{
+    Terminal,
+    backend::{Backend, TestBackend},
+    buffer::Buffer,
+    layout::Alignment,
+    prelude::CrosstermBackend,
+};
+use recorder::FrameRecorder;
+use records::Records;
+use rl_agent::{LeagueOpponent, RLAgent, TrainingPlan};
 
 fn main() -> Result<()> {
     color_eyre::install()?;
+
+    // Headless commands, run without the terminal ui
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--bootstrap-qtable") {
+        return bootstrap_q_table(args.get(index + 1).map(String::as_str));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--convert-qtable") {
+        return convert_q_table(args.get(index + 1), args.get(index + 2));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--train") {
+        return train(&args[index + 1..]);
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--export-policy") {
+        return export_policy(args.get(index + 1).map(String::as_str));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--explain") {
+        return explain(args.get(index + 1), args.get(index + 2));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--snapshot") {
+        return snapshot(args.get(index + 1));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--check-agents") {
+        return check_agents(args.get(index + 1));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--check-wins") {
+        return check_wins(args.get(index + 1));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--check-tactics") {
+        return check_tactics(args.get(index + 1));
+    }
+
+    if let Some(index) = args.iter().position(|arg| arg == "--connect") {
+        return connect(args.get(index + 1));
+    }
+
+    let options = Options::from_args(&args)?;
+    // The other player has to be connected before the game starts
+    let remote = options.serve.map(network::wait_for_player).transpose()?;
+
     let mut terminal = init()?;
-    let app_result = run(&mut terminal);
+    let app_result = run(&mut terminal, options, remote);
     if let Err(err) = restore() {
         eprintln!(
             "failed to restore terminal. Run `reset` or restart your terminal to recover: {}",
             err
         );
     }
-    app_result
+    // Saved once the terminal is back, so a failure can be reported without losing the game
+    let records = app_result?;
+    let path = Records::path();
+    if let Err(err) = records.save(&path) {
+        eprintln!("Could not save records to {}: {}", path.display(), err);
+    }
+    Ok(())
+}
+
+/// Options for the terminal ui parsed from the command line
+struct Options {
+    /// Debug flag forcing a full board scan for wins after every move
+    win_check: WinCheck,
+    /// Directory to write every rendered frame to
+    record_frames: Option<PathBuf>,
+    /// Forced opening moves as 0-indexed columns
+    opening: Vec<usize>,
+    /// Pause after a reset before AI autoplay
+    startup_delay: Duration,
+    /// Swap the starting player between games
+    alternate_starter: bool,
+    /// Moves searched below the root by minimax agents
+    branch_cap: Option<usize>,
+    /// One player needing a different line length
+    handicap: Option<Handicap>,
+    /// Blocked cells placed at random on every board
+    obstacles: Option<Obstacles>,
+    /// Clock for timed games, Some to start with the clock on
+    time_control: Option<TimeControl>,
+    /// Window in which repeats of the same key are dropped
+    debounce: Option<Duration>,
+    /// Time between redraws when nothing else happens, None to redraw only on input and steps
+    frame_interval: Option<Duration>,
+    /// Board placement: alignment, padding on either side and whether to title it
+    board_alignment: Alignment,
+    board_padding: u16,
+    board_title: bool,
+    /// Drop to Manual speed whenever a human is to move
+    manual_for_humans: bool,
+    /// Port to host a game on, the player connecting to it plays Red
+    serve: Option<u16>,
+}
+
+impl Options {
+    fn from_args(args: &[String]) -> Result<Self> {
+        let win_check = if args.iter().any(|arg| arg == "--full-win-scan") {
+            WinCheck::FullScan
+        } else {
+            WinCheck::Incremental
+        };
+        let record_frames = args
+            .iter()
+            .position(|arg| arg == "--record-frames")
+            .and_then(|index| args.get(index + 1))
+            .map(PathBuf::from);
+
+        // Columns are given 1-indexed like the board header, e.g. `--opening 4,3,4`
+        let opening = match args.iter().position(|arg| arg == "--opening") {
+            Some(index) => {
+                let Some(moves) = args.get(index + 1) else {
+                    eyre::bail!("usage: --opening <columns>, e.g. --opening 4,3,4");
+                };
+                moves
+                    .split(',')
+                    .map(|col| {
+                        col.trim()
+                            .parse()
+                            .ok()
+                            .and_then(column_from_label)
+                            .ok_or_else(|| eyre::eyre!("invalid opening column '{}'", col))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+            None => Vec::new(),
+        };
+
+        let startup_delay = match args.iter().position(|arg| arg == "--startup-delay") {
+            Some(index) => {
+                let millis = args
+                    .get(index + 1)
+                    .and_then(|millis| millis.parse().ok())
+                    .ok_or_else(|| eyre::eyre!("usage: --startup-delay <milliseconds>"))?;
+                Duration::from_millis(millis)
+            }
+            None => app::DEFAULT_STARTUP_DELAY,
+        };
+
+        let board_alignment = match args.iter().position(|arg| arg == "--board-align") {
+            Some(index) => match args.get(index + 1).map(String::as_str) {
+                Some("left") => Alignment::Left,
+                Some("center") => Alignment::Center,
+                _ => eyre::bail!("usage: --board-align left|center"),
+            },
+            None => Alignment::Center,
+        };
+
+        let board_padding = match args.iter().position(|arg| arg == "--board-padding") {
+            Some(index) => args
+                .get(index + 1)
+                .and_then(|padding| padding.parse().ok())
+                .ok_or_else(|| eyre::eyre!("usage: --board-padding <columns>"))?,
+            None => 0,
+        };
+
+        let branch_cap = match args.iter().position(|arg| arg == "--branch-cap") {
+            Some(index) => Some(
+                args.get(index + 1)
+                    .and_then(|cap| cap.parse().ok())
+                    .filter(|&cap| cap >= 1)
+                    .ok_or_else(|| eyre::eyre!("usage: --branch-cap <moves, at least 1>"))?,
+            ),
+            None => None,
+        };
+
+        let debounce = match args.iter().position(|arg| arg == "--debounce") {
+            Some(index) => Some(
+                args.get(index + 1)
+                    .and_then(|millis| millis.parse().ok())
+                    .map(Duration::from_millis)
+                    .ok_or_else(|| eyre::eyre!("usage: --debounce <milliseconds>"))?,
+            ),
+            None => None,
+        };
+
+        // e.g. `--handicap yellow:5` for Yellow needing five in a row
+        let handicap = match args.iter().position(|arg| arg == "--handicap") {
+            Some(index) => {
+                let usage = || eyre::eyre!("usage: --handicap <yellow|red>:<length>");
+                let (player, length) = args
+                    .get(index + 1)
+                    .and_then(|handicap| handicap.split_once(':'))
+                    .ok_or_else(usage)?;
+                let player = match player.to_ascii_lowercase().as_str() {
+                    "yellow" => Player::Yellow,
+                    "red" => Player::Red,
+                    _ => return Err(usage()),
+                };
+                let connect_length = length
+                    .parse()
+                    .ok()
+                    .filter(|&length| length >= GameConfig::MIN_CONNECT_LENGTH)
+                    .ok_or_else(usage)?;
+                Some(Handicap {
+                    player,
+                    connect_length,
+                })
+            }
+            None => None,
+        };
+
+        // e.g. `--obstacles 4` for four random blocked cells, `--obstacles 4:7` for the same
+        // four every game
+        let obstacles = match args.iter().position(|arg| arg == "--obstacles") {
+            Some(index) => {
+                let usage = || eyre::eyre!("usage: --obstacles <count>[:seed]");
+                let arg = args.get(index + 1).ok_or_else(usage)?;
+                let (count, seed) = match arg.split_once(':') {
+                    Some((count, seed)) => (count, Some(seed.parse().map_err(|_| usage())?)),
+                    None => (arg.as_str(), None),
+                };
+                Some(Obstacles {
+                    count: count.parse().map_err(|_| usage())?,
+                    seed,
+                })
+            }
+            None => None,
+        };
+
+        // e.g. `--clock 3+2` for three minutes each plus two seconds per move
+        let time_control = match args.iter().position(|arg| arg == "--clock") {
+            Some(index) => {
+                let usage = || eyre::eyre!("usage: --clock <minutes>+<increment seconds>");
+                let (minutes, increment) = args
+                    .get(index + 1)
+                    .and_then(|clock| clock.split_once('+'))
+                    .ok_or_else(usage)?;
+                let minutes: u64 = minutes.parse().map_err(|_| usage())?;
+                let increment = increment.parse().map_err(|_| usage())?;
+                let Some(seconds) = minutes.checked_mul(60) else {
+                    eyre::bail!(
+                        "--clock: {} minutes is more than the clock can hold",
+                        minutes
+                    );
+                };
+                Some(TimeControl {
+                    base: Duration::from_secs(seconds),
+                    increment: Duration::from_secs(increment),
+                })
+            }
+            None => None,
+        };
+
+        let frame_interval = match args.iter().position(|arg| arg == "--fps") {
+            Some(index) => Some(
+                args.get(index + 1)
+                    .and_then(|fps| fps.parse::<u32>().ok())
+                    .filter(|&fps| fps > 0)
+                    .map(|fps| Duration::from_secs(1) / fps)
+                    .ok_or_else(|| eyre::eyre!("usage: --fps <frames per second>"))?,
+            ),
+            None => None,
+        };
+
+        let serve = match args.iter().position(|arg| arg == "--serve") {
+            Some(index) => Some(
+                args.get(index + 1)
+                    .and_then(|port| port.parse().ok())
+                    .ok_or_else(|| eyre::eyre!("usage: --serve <port>"))?,
+            ),
+            None => None,
+        };
+
+        Ok(Options {
+            win_check,
+            record_frames,
+            opening,
+            startup_delay,
+            alternate_starter: args.iter().any(|arg| arg == "--alternate-starter"),
+            branch_cap,
+            handicap,
+            obstacles,
+            time_control,
+            debounce,
+            frame_interval,
+            board_alignment,
+            board_padding,
+            board_title: args.iter().any(|arg| arg == "--board-title"),
+            manual_for_humans: !args.iter().any(|arg| arg == "--keep-speed-for-humans"),
+            serve,
+        })
+    }
+}
+
+/// Seed the RL agent's Q-table from the perfect-play solver
+fn bootstrap_q_table(preset: Option<&str>) -> Result<()> {
+    let config = match preset {
+        Some("small") => GameConfigPreset::Small.into_config(),
+        _ => eyre::bail!("usage: --bootstrap-qtable small (only the small board can be solved)"),
+    };
+    let states = RLAgent::bootstrap_q_table(config)?;
+    println!(
+        "Wrote bootstrapped Q-table for {}x{} with {} states",
+        config.cols, config.rows, states
+    );
+    Ok(())
+}
+
+/// Train the saved Q-table of a preset, Standard by default, with self-play games on every
+/// core unless a thread count is given. `--baseline` names the agent the table is evaluated
+/// against at every report, or `none` to skip the evaluation. `--league` trains against a
+/// pool of opponents instead of self-play, given as `name:weight` entries split by `;`
+/// with "Past self" for the learner's own earlier table
+fn train(args: &[String]) -> Result<()> {
+    const USAGE: &str = "usage: --train <games> [standard|small|large|huge|pop out] [threads] \
+                         [--baseline <agent name>|none] [--league [<name>:<weight>;...]]";
+    let flag = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .map(|index| args.get(index + 1).ok_or_else(|| eyre::eyre!(USAGE)))
+            .transpose()
+    };
+    let mut positional = args.iter().take_while(|arg| !arg.starts_with("--"));
+    let (games, preset, threads) = (positional.next(), positional.next(), positional.next());
+
+    let games: usize = games
+        .and_then(|games| games.parse().ok())
+        .ok_or_else(|| eyre::eyre!(USAGE))?;
+    let preset = match preset {
+        Some(name) => (0..GameConfigPreset::amount_of_presets())
+            .map(GameConfigPreset::from_index)
+            .find(|preset| preset.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(|| eyre::eyre!(USAGE))?,
+        None => GameConfigPreset::Standard,
+    };
+    let threads = match threads {
+        Some(threads) => threads
+            .parse()
+            .ok()
+            .filter(|&threads| threads > 0)
+            .ok_or_else(|| eyre::eyre!(USAGE))?,
+        None => std::thread::available_parallelism().map_or(1, usize::from),
+    };
+    let baseline = match flag("--baseline")? {
+        Some(name) if name.eq_ignore_ascii_case("none") => None,
+        Some(name) => Some(
+            name.parse::<Agents>()
+                .ok()
+                .filter(|agent| *agent != Agents::Human)
+                .ok_or_else(|| eyre::eyre!("unknown agent {}, {}", name, USAGE))?,
+        ),
+        None => Some(TrainingPlan::DEFAULT_BASELINE),
+    };
+    let league = match args.iter().position(|arg| arg == "--league") {
+        Some(index) => match args.get(index + 1).filter(|pool| !pool.starts_with("--")) {
+            Some(pool) => parse_league(pool).ok_or_else(|| eyre::eyre!(USAGE))?,
+            None => TrainingPlan::default_league(),
+        },
+        None => Vec::new(),
+    };
+
+    let plan = TrainingPlan {
+        games,
+        threads,
+        report_every: (games / 10).max(1),
+        baseline,
+        evaluation_games: TrainingPlan::DEFAULT_EVALUATION_GAMES,
+        league,
+    };
+    RLAgent::train_self_play(preset.into_config(), &plan, |report| {
+        let evaluation = match (&plan.baseline, report.evaluation) {
+            (Some(baseline), Some((win_rate, best))) => format!(
+                ", {:.1}% won against {}{}",
+                win_rate,
+                baseline,
+                if best { ", saved as the best" } else { "" }
+            ),
+            _ => String::new(),
+        };
+        println!(
+            "{} games: Yellow {:.1}%, Red {:.1}%, drawn {:.1}%, {} states{}",
+            report.games,
+            report.rate(report.yellow_wins),
+            report.rate(report.red_wins),
+            report.rate(report.draws),
+            report.states,
+            evaluation
+        );
+        if !report.league_games.is_empty() {
+            let faced: Vec<String> = plan
+                .league
+                .iter()
+                .zip(&report.league_games)
+                .map(|((opponent, _), games)| format!("{} {}", opponent, games))
+                .collect();
+            println!("  against {}", faced.join(", "));
+        }
+    })?;
+    println!("Saved the {} Q-table after {} games", preset, games);
+    Ok(())
+}
+
+// League entries like "Random:1;Minimax (3):2;Past self:2", a missing weight counts as 1.
+// None if a name is unknown or a weight isn't a number
+fn parse_league(pool: &str) -> Option<Vec<(LeagueOpponent, u32)>> {
+    pool.split(';')
+        .map(|entry| {
+            let (name, weight) = match entry.rsplit_once(':') {
+                Some((name, weight)) => (name.trim(), weight.trim().parse().ok()?),
+                None => (entry.trim(), 1),
+            };
+            let opponent = if name.eq_ignore_ascii_case(&LeagueOpponent::PastSelf.to_string()) {
+                LeagueOpponent::PastSelf
+            } else {
+                LeagueOpponent::Agent(
+                    name.parse::<Agents>()
+                        .ok()
+                        .filter(|agent| *agent != Agents::Human)?,
+                )
+            };
+            Some((opponent, weight))
+        })
+        .collect()
+}
+
+/// Strip the saved Q-table of a preset, Standard by default, down to its best moves
+fn export_policy(preset: Option<&str>) -> Result<()> {
+    let preset = match preset {
+        Some(name) => (0..GameConfigPreset::amount_of_presets())
+            .map(GameConfigPreset::from_index)
+            .find(|preset| preset.to_string().eq_ignore_ascii_case(name))
+            .ok_or_else(|| eyre::eyre!("usage: --export-policy [standard|small|large|huge]"))?,
+        None => GameConfigPreset::Standard,
+    };
+    let states = PolicyAgent::export(preset.into_config())?;
+    println!("Exported the {} policy with {} states", preset, states);
+    Ok(())
+}
+
+/// Play Red in a game hosted with `--serve`, from a plain text board
+fn connect(addr: Option<&String>) -> Result<()> {
+    let Some(addr) = addr else {
+        eyre::bail!("usage: --connect <host>:<port>");
+    };
+    network::play_remote(addr.as_str())?;
+    Ok(())
+}
+
+/// Convert a Q-table between JSON and binary, picked by file extension
+fn convert_q_table(from: Option<&String>, to: Option<&String>) -> Result<()> {
+    let (Some(from), Some(to)) = (from, to) else {
+        eyre::bail!("usage: --convert-qtable <from> <to> (use a .bin extension for binary)");
+    };
+    let states = RLAgent::convert_q_table(Path::new(from), Path::new(to))?;
+    println!("Converted {} states from {} to {}", states, from, to);
+    Ok(())
+}
+
+/// Load a standard board position and print the chosen agent's move with its reasoning
+fn explain(notation: Option<&String>, agent_name: Option<&String>) -> Result<()> {
+    let Some(notation) = notation else {
+        eyre::bail!("usage: --explain <moves> [agent name]");
+    };
+    let board = Game::from_notation(GameConfig::default(), notation)?;
+    if *board.state() != GameState::InProgress {
+        eyre::bail!("the game is already over after {}", notation);
+    }
+    let agent_name = agent_name.map_or("Minimax (5)", |name| name.as_str());
+    let mut agent = Agents::create_agent(agent_name, board.current_player(), *board.config())?;
+
+    let (_, lines) = explanation(&board, agent.as_mut());
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// The agent's move in the position, with the lines explaining it
+fn explanation(board: &Game, agent: &mut dyn agent::Agent) -> (Option<usize>, Vec<String>) {
+    let action = agent.get_action(board, None);
+    let mut lines = vec![match action {
+        Some(col) => format!(
+            "{} plays column {} for {}",
+            agent.get_type(),
+            column_label(col),
+            board.current_player()
+        ),
+        None => format!("{} has no move", agent.get_type()),
+    }];
+    lines.extend(agent.explain(board));
+    (action, lines)
+}
+
+/// Draw one frame of the ui with a position loaded into an in-memory backend and print it as
+/// plain text, e.g. for docs or to diff the rendering between versions
+fn snapshot(notation: Option<&String>) -> Result<()> {
+    const WIDTH: u16 = 120;
+    const HEIGHT: u16 = 40;
+
+    let mut app = app::App::new()?;
+    if let Some(notation) = notation {
+        app.position_prompt = Some(notation.clone());
+        app.load_position();
+        if app.position_prompt.is_some() {
+            eyre::bail!(
+                "usage: --snapshot [moves], {}",
+                app.message.take().unwrap_or_default()
+            );
+        }
+    }
+
+    for line in buffer_lines(&render_to_buffer(&mut app, WIDTH, HEIGHT)?) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Ask every AI agent for moves on random positions and report any illegal answer
+fn check_agents(positions: Option<&String>) -> Result<()> {
+    let positions = match positions {
+        Some(positions) => positions
+            .parse()
+            .map_err(|_| eyre::eyre!("usage: --check-agents [positions per preset]"))?,
+        None => 20,
+    };
+    let failures = benchmark::find_illegal_moves(positions);
+    for failure in &failures {
+        println!("{}", failure);
+    }
+    if !failures.is_empty() {
+        eyre::bail!("{} illegal moves found", failures.len());
+    }
+    println!(
+        "All agents played legal moves on {} positions per preset",
+        positions
+    );
+    Ok(())
+}
+
+/// Draw one frame of the app into an in-memory terminal of the given size
+fn render_to_buffer(app: &mut app::App, width: u16, height: u16) -> Result<Buffer> {
+    let mut terminal = Terminal::new(TestBackend::new(width, height))?;
+    let frame = terminal.draw(|frame| render(frame, app, &RunSpeed::Manual))?;
+    Ok(frame.buffer.clone())
+}
+
+/// The symbols of each row of a buffer, without trailing spaces
+fn buffer_lines(buffer: &Buffer) -> Vec<String> {
+    (buffer.area.top()..buffer.area.bottom())
+        .map(|y| {
+            let line: String = (buffer.area.left()..buffer.area.right())
+                .filter_map(|x| buffer.cell((x, y)).map(|cell| cell.symbol()))
+                .collect();
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Play random games and report any disagreement between the win detection and a brute force
+/// line search
+fn check_wins(games: Option<&String>) -> Result<()> {
+    let games = match games {
+        Some(games) => games
+            .parse()
+            .map_err(|_| eyre::eyre!("usage: --check-wins [games per preset]"))?,
+        None => 200,
+    };
+    let mismatches = benchmark::find_win_mismatches(games);
+    for mismatch in &mismatches {
+        println!("{}", mismatch);
+    }
+    if !mismatches.is_empty() {
+        eyre::bail!("{} win detection mismatches found", mismatches.len());
+    }
+    println!("Win detection agreed on {} games per preset", games);
+    Ok(())
+}
+
+/// Run one agent, Minimax (5) by default, through the tactical positions and report any miss
+fn check_tactics(agent_name: Option<&String>) -> Result<()> {
+    let agent_name = agent_name.map_or("Minimax (5)", |name| name.as_str());
+    let Ok(agent_type) = agent_name.parse::<Agents>() else {
+        eyre::bail!(
+            "usage: --check-tactics [agent name], unknown agent {}",
+            agent_name
+        );
+    };
+    let misses = benchmark::find_tactical_misses(agent_type);
+    for miss in &misses {
+        println!("{}", miss);
+    }
+    if !misses.is_empty() {
+        eyre::bail!("{} tactical positions missed", misses.len());
+    }
+    println!(
+        "{} solved all {} tactical positions",
+        agent_name,
+        benchmark::tactical_positions().len()
+    );
+    Ok(())
+}
+
+/// Drops presses of the same key arriving within a short window of the previous one, so
+/// holding a key down doesn't queue up a flood of steps. Different keys always get through
+struct KeyDebounce {
+    window: Option<Duration>,
+    last: Option<(KeyCode, Instant)>,
+}
+
+impl KeyDebounce {
+    fn new(window: Option<Duration>) -> Self {
+        KeyDebounce { window, last: None }
+    }
+
+    /// Whether a press of `code` at `now` should be handled
+    fn accept(&mut self, code: KeyCode, now: Instant) -> bool {
+        let Some(window) = self.window else {
+            return true;
+        };
+        if let Some((last_code, last_time)) = self.last
+            && last_code == code
+            && now.duration_since(last_time) < window
+        {
+            return false;
+        }
+        self.last = Some((code, now));
+        true
+    }
+}
+
+/// What the main loop has to do once it wakes up without input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Wake {
+    Attract,
+    Step,
+    Frame,
+}
+
+/// Keeps game steps on the RunSpeed schedule while frames are drawn at their own steady rate,
+/// so redraws (e.g. the running clock) don't depend on how fast the game is played
+struct LoopTimer {
+    frame_interval: Option<Duration>,
+    last_step: Instant,
+}
+
+impl LoopTimer {
+    fn new(frame_interval: Option<Duration>) -> Self {
+        LoopTimer {
+            frame_interval,
+            last_step: Instant::now(),
+        }
+    }
+
+    /// How long to wait for input and what is due after that, the earliest deadline wins and
+    /// attract mode goes before a step on a tie. A running game clock or falling piece redraws
+    /// when its display changes. None waits for input only
+    fn next_wake(
+        &self,
+        step_interval: Option<Duration>,
+        attract_wait: Option<Duration>,
+        redraw_wait: Option<Duration>,
+        now: Instant,
+    ) -> Option<(Duration, Wake)> {
+        let step_wait = step_interval.map(|interval| interval.saturating_sub(now - self.last_step));
+        [
+            (attract_wait, Wake::Attract),
+            (step_wait, Wake::Step),
+            (self.frame_interval, Wake::Frame),
+            (redraw_wait, Wake::Frame),
+        ]
+        .into_iter()
+        .filter_map(|(wait, wake)| Some((wait?, wake)))
+        .min_by_key(|&(wait, _)| wait)
+    }
+
+    fn stepped(&mut self, now: Instant) {
+        self.last_step = now;
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -42,24 +733,39 @@ pub enum RunSpeed {
     Manual,
 }
 
-impl ToString for RunSpeed {
-    fn to_string(&self) -> String {
+impl fmt::Display for RunSpeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            RunSpeed::Slow => "Slow".to_string(),
-            RunSpeed::Fast => "Fast".to_string(),
-            RunSpeed::Instant => "Instant".to_string(),
-            RunSpeed::Manual => "Manual".to_string(),
+            RunSpeed::Slow => write!(f, "Slow"),
+            RunSpeed::Fast => write!(f, "Fast"),
+            RunSpeed::Instant => write!(f, "Instant"),
+            RunSpeed::Manual => write!(f, "Manual"),
         }
     }
 }
 
 impl RunSpeed {
-    pub fn time(&self) -> Duration {
+    // Rows a falling piece can cover in one step
+    const FALL_FRAMES_PER_STEP: u32 = 8;
+
+    /// Time between automatic steps, None at Manual speed which only steps on input
+    pub fn time(&self) -> Option<Duration> {
+        match self {
+            RunSpeed::Slow => Some(Duration::from_millis(1000)),
+            RunSpeed::Fast => Some(Duration::from_millis(250)),
+            RunSpeed::Instant => Some(Duration::ZERO),
+            RunSpeed::Manual => None,
+        }
+    }
+
+    /// Time per row of a falling piece, a fraction of the step time so it lands well before
+    /// the next move. Instant and Manual drop pieces straight into place
+    pub fn fall_interval(&self) -> Option<Duration> {
         match self {
-            RunSpeed::Slow => Duration::from_millis(1000),
-            RunSpeed::Fast => Duration::from_millis(250),
-            RunSpeed::Instant => Duration::from_millis(0),
-            RunSpeed::Manual => Duration::from_millis(MAX),
+            RunSpeed::Slow | RunSpeed::Fast => {
+                self.time().map(|step| step / Self::FALL_FRAMES_PER_STEP)
+            }
+            RunSpeed::Instant | RunSpeed::Manual => None,
         }
     }
 }
@@ -68,7 +774,7 @@ pub type Tui = Terminal<CrosstermBackend<Stdout>>;
 
 /// Initialize the terminal
 pub fn init() -> io::Result<Tui> {
-    execute!(stdout(), EnterAlternateScreen)?;
+    execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
     enable_raw_mode()?;
     set_panic_hook();
     Terminal::new(CrosstermBackend::new(stdout()))
@@ -84,98 +790,436 @@ fn set_panic_hook() {
 
 /// Restore the terminal to its original state
 pub fn restore() -> io::Result<()> {
-    execute!(stdout(), LeaveAlternateScreen)?;
+    execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
     disable_raw_mode()?;
     Ok(())
 }
 
-fn run(terminal: &mut DefaultTerminal) -> Result<()> {
-    let mut app = app::App::new();
+/// The interactive loop, drawing to any backend while input comes from crossterm. Returns the
+/// records for the caller to save
+fn run<B: Backend>(
+    terminal: &mut Terminal<B>,
+    options: Options,
+    remote: Option<TcpStream>,
+) -> Result<Records> {
+    let mut app = app::App::new()?;
+    app.win_check = options.win_check;
+    app.opening = options.opening;
+    app.startup_delay = options.startup_delay;
+    app.branch_cap = options.branch_cap;
+    app.handicap = options.handicap;
+    app.obstacles = options.obstacles;
+    if let Some(time_control) = options.time_control {
+        app.time_control = time_control;
+        app.timed = true;
+    }
+    app.alternate_starter = options.alternate_starter;
+    app.board_alignment = options.board_alignment;
+    app.board_padding = options.board_padding;
+    app.board_title = options.board_title;
+    if let Some(stream) = remote {
+        app.set_remote_player(stream);
+    }
+    app.reset();
     let mut run_speed = RunSpeed::Manual;
+    let mut debounce = KeyDebounce::new(options.debounce);
+    let mut timer = LoopTimer::new(options.frame_interval);
+    // Speed to go back to once attract mode ends
+    let mut speed_before_attract = None;
+    let mut recorder = options.record_frames.map(FrameRecorder::new).transpose()?;
 
     loop {
-        terminal.draw(|frame| render(frame, &mut app, &run_speed))?;
+        app.advance_fall();
+        app.check_clock();
+        app.poll_search();
+
+        if options.manual_for_humans {
+            run_speed = app.speed_for_turn(run_speed);
+        }
+
+        app.fall_interval = run_speed.fall_interval();
+
+        // Show the AI's next move before Space plays it
+        if run_speed == RunSpeed::Manual {
+            app.prepare_preview();
+        }
+        if app.show_heatmap {
+            app.prepare_column_scores();
+        }
+        let frame = terminal.draw(|frame| render(frame, &mut app, &run_speed))?;
+        if let Some(recorder) = &mut recorder {
+            recorder.record(frame.buffer)?;
+        }
+
+        // Plays the remote player's move once it has arrived, without waiting for it. Drawn
+        // right away, or the game ended by a lost connection
+        if app.remote_to_move() && !app.summary_open {
+            app.step(None)?;
+            if !app.remote_to_move() {
+                continue;
+            }
+        }
 
-        let event_exists = event::poll(run_speed.time())?;
-        if event_exists || run_speed == RunSpeed::Manual {
+        // Sleep until input or the next attract, step or frame deadline, even at Manual speed.
+        // No step is due while a search runs, its move is played as soon as it is found
+        let step_interval = run_speed.time().filter(|_| !app.searching());
+        let wake = timer.next_wake(
+            step_interval,
+            app.time_until_attract(),
+            [
+                app.time_until_clock_tick(),
+                app.time_until_fall_frame(),
+                app.time_until_remote_poll(),
+                app.time_until_search_poll(),
+            ]
+            .into_iter()
+            .flatten()
+            .min(),
+            Instant::now(),
+        );
+        let event_exists = match wake {
+            Some((timeout, _)) => event::poll(timeout)?,
+            None => true,
+        };
+        if event_exists {
             let event = event::read()?;
-            match event {
-                event::Event::Key(key) => {
-                    if key.kind == KeyEventKind::Press {
-                        match key.code {
-                            KeyCode::Char('q') => break Ok(()),
-                            KeyCode::Char('s') => run_speed = RunSpeed::Slow,
-                            KeyCode::Char('f') => run_speed = RunSpeed::Fast,
-                            KeyCode::Char('i') => run_speed = RunSpeed::Instant,
-                            KeyCode::Char('m') => run_speed = RunSpeed::Manual,
-                            KeyCode::Char('r') => {
-                                app.menu_open = false;
-                                app.reset();
-                            }
-                            KeyCode::Char('p') => {
-                                app.menu_open = true;
-                            }
-                            KeyCode::Char(' ') => app.step(None)?,
+            if let event::Event::Key(key) = event
+                && key.kind == KeyEventKind::Press
+                // Typing into the prompt repeats characters on purpose
+                && (app.position_prompt.is_some()
+                    || app.size_prompt.is_some()
+                    || debounce.accept(key.code, Instant::now()))
+            {
+                // Any key dismisses the session summary and quits
+                if app.summary_open {
+                    break;
+                }
 
-                            // List
-                            KeyCode::Char('g') => app.agent_list.state.select_first(),
-                            KeyCode::Char('G') => app.agent_list.state.select_last(),
-                            KeyCode::Char('j') | KeyCode::Down => {
-                                app.agent_list.state.select_next()
-                            }
-                            KeyCode::Char('k') | KeyCode::Up => {
-                                app.agent_list.state.select_previous()
-                            }
-                            KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
-                                let selected = app.agent_list.state.selected();
-                                // first two are the players
-                                match selected {
-                                    Some(0) => app.agent_list.selected_player = Player::Yellow,
-                                    Some(1) => app.agent_list.selected_player = Player::Red,
-                                    Some(x) => {
-                                        // Handle from AGENTS list
-                                        let agent_index = x - 2;
-                                        app.set_agent(
-                                            app.agent_list.selected_player,
-                                            Agents::agent_types()[agent_index].clone(),
-                                        );
+                // Any key takes over from the attract mode demo
+                if app.register_input() {
+                    if let Some(speed) = speed_before_attract.take() {
+                        run_speed = speed;
+                    }
+                    continue;
+                }
+
+                // Any key closes the records screen
+                if app.records_open {
+                    app.records_open = false;
+                    continue;
+                }
+
+                // The load position prompt takes all keys while open
+                if let Some(notation) = &mut app.position_prompt {
+                    match key.code {
+                        KeyCode::Char(c) => notation.push(c),
+                        KeyCode::Backspace => {
+                            notation.pop();
+                        }
+                        KeyCode::Enter => app.load_position(),
+                        KeyCode::Esc => app.position_prompt = None,
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                // So does the custom board size prompt
+                if let Some(size) = &mut app.size_prompt {
+                    match key.code {
+                        KeyCode::Char(c) => size.push(c),
+                        KeyCode::Backspace => {
+                            size.pop();
+                        }
+                        KeyCode::Enter => app.apply_custom_size(),
+                        KeyCode::Esc => app.cancel_size_prompt(),
+                        _ => {}
+                    }
+                    continue;
+                }
+
+                match key.code {
+                    KeyCode::Char('L') => app.position_prompt = Some(String::new()),
+                    KeyCode::Char('h') => app.records_open = true,
+                    KeyCode::Char('w') => app.save_game(),
+                    KeyCode::Char('q') => {
+                        if app.session.games == 0 {
+                            break;
+                        }
+                        app.summary_open = true;
+                    }
+                    KeyCode::Char('s') => run_speed = RunSpeed::Slow,
+                    KeyCode::Char('f') => run_speed = RunSpeed::Fast,
+                    KeyCode::Char('i') => run_speed = RunSpeed::Instant,
+                    KeyCode::Char('m') => run_speed = RunSpeed::Manual,
+                    KeyCode::Char('r') => {
+                        app.menu_open = false;
+                        app.reset();
+                    }
+                    KeyCode::Char('R') => {
+                        run_speed = RunSpeed::Manual;
+                        app.reset_to_defaults()?;
+                    }
+                    KeyCode::Char('p') => {
+                        app.menu_open = true;
+                    }
+                    KeyCode::Char('v') => run_speed = app.practice_vs_rl(),
+                    KeyCode::Char('a') => app.auto_reset = !app.auto_reset,
+                    KeyCode::Char('o') => app.fog = !app.fog,
+                    KeyCode::Char('e') => app.show_eval_graph = !app.show_eval_graph,
+                    KeyCode::Char('b') => app.show_eval_bar = !app.show_eval_bar,
+                    KeyCode::Char('K') => app.show_coverage = !app.show_coverage,
+                    KeyCode::Char('H') => app.show_heatmap = !app.show_heatmap,
+                    KeyCode::Char('T') => app.timed = !app.timed,
+                    KeyCode::Char('P') => app.toggle_puzzles(),
+                    KeyCode::Char('u') => app.undo_move(),
+                    KeyCode::Char('x') => app.toggle_pop(),
+                    KeyCode::Char('U') => app.redo_move(),
+                    KeyCode::Char(',') => app.cycle_puzzle(false),
+                    KeyCode::Char('.') => app.cycle_puzzle(true),
+                    KeyCode::Char('(') => app.pan_board(-1),
+                    KeyCode::Char(')') => app.pan_board(1),
+                    KeyCode::Char('n') => app.header_mode = app.header_mode.toggled(),
+                    KeyCode::Char('t') => app.score_mode = app.score_mode.toggled(),
+                    KeyCode::Char('z') => app.clear_scoreboard(),
+                    KeyCode::Char('+') | KeyCode::Char('=') => app.adjust_depth(1),
+                    KeyCode::Char('-') => app.adjust_depth(-1),
+                    KeyCode::Char(' ') => app.step(None)?,
+                    KeyCode::Char('>') => app.step_moves(app::BATCH_STEP_MOVES)?,
+
+                    // Hidden debug keys ending the game, not in release builds
+                    #[cfg(debug_assertions)]
+                    KeyCode::Char('D') => app.force_result(GameState::Draw),
+                    #[cfg(debug_assertions)]
+                    KeyCode::Char('W') => {
+                        app.force_result(GameState::Won(app.game.current_player()))
+                    }
+
+                    // Quick agent cycling
+                    KeyCode::Char('[') => app.cycle_agent(Player::Yellow, false),
+                    KeyCode::Char(']') => app.cycle_agent(Player::Yellow, true),
+                    KeyCode::Char('{') => app.cycle_agent(Player::Red, false),
+                    KeyCode::Char('}') => app.cycle_agent(Player::Red, true),
+
+                    // List
+                    KeyCode::Char('g') => app.agent_list.state.select_first(),
+                    KeyCode::Char('G') => app.agent_list.state.select_last(),
+                    KeyCode::Char('j') | KeyCode::Down => app.agent_list.state.select_next(),
+                    KeyCode::Char('k') | KeyCode::Up => app.agent_list.state.select_previous(),
+                    KeyCode::Char('l') | KeyCode::Right | KeyCode::Enter => {
+                        let selected = app.agent_list.state.selected();
+                        // first two are the players
+                        match selected {
+                            Some(0) => app.agent_list.selected_player = Player::Yellow,
+                            Some(1) => app.agent_list.selected_player = Player::Red,
+                            Some(x) => {
+                                // Handle from AGENTS list, the entry after it loads the save
+                                let agent_index = x - 2;
+                                match Agents::agent_types().get(agent_index) {
+                                    Some(agent) => {
+                                        app.set_agent(app.agent_list.selected_player, agent.clone())
                                     }
-                                    None => {}
-                                }
-                                app.agent_list.state.select(None);
-                            }
-                            KeyCode::Char('c') | KeyCode::Char('C') => {
-                                // Cycle through config
-                                if app
-                                    .config_list
-                                    .state
-                                    .selected()
-                                    .is_none_or(|i| i == GameConfigPreset::amount_of_presets() - 1)
-                                {
-                                    app.config_list.state.select_first();
-                                } else {
-                                    app.config_list.state.select_next();
-                                }
-                                // Reset game with new config
-                                app.config_list.selected_game = GameConfigPreset::from_index(
-                                    app.config_list.state.selected().unwrap_or(0),
-                                );
-                                app.reset();
-                            }
-                            _ => {
-                                if *app.game.state() == GameState::InProgress {
-                                    app.step(Some(event))?;
+                                    None => app.load_saved_game(),
                                 }
                             }
+                            None => {}
+                        }
+                        app.agent_list.state.select(None);
+                    }
+                    KeyCode::Char('c') | KeyCode::Char('C') => app.cycle_config(),
+                    _ => {
+                        if *app.game.state() == GameState::InProgress {
+                            app.step(Some(event))?;
+                        } else if app.awaiting_human_continue() {
+                            app.reset();
                         }
                     }
                 }
-                _ => {}
+            } else if let event::Event::Mouse(mouse) = event
+                && mouse.kind == MouseEventKind::Down(MouseButton::Left)
+                && !app.menu_open
+                && !app.summary_open
+                && app.position_prompt.is_none()
+                && app.size_prompt.is_none()
+            {
+                app.click(mouse.column, mouse.row);
             }
-        } else {
-            if *app.game.state() == GameState::InProgress {
+        } else if let Some((_, Wake::Attract)) = wake {
+            speed_before_attract = Some(run_speed);
+            run_speed = RunSpeed::Fast;
+            app.start_attract();
+        } else if let Some((_, Wake::Step)) = wake {
+            timer.stepped(Instant::now());
+            if !app.summary_open && app.auto_reset_due() {
+                app.reset();
+            } else if !app.summary_open
+                && app.autoplay_ready()
+                && *app.game.state() == GameState::InProgress
+            {
                 app.step(None)?;
             }
         }
     }
+
+    // Quitting mid-search doesn't wait for it to finish
+    app.cancel_search();
+    Ok(std::mem::take(&mut app.records))
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::*;
+
+    #[test]
+    fn explain_puts_an_immediate_win_first() {
+        // Yellow to move wins in the fourth column and nowhere else
+        let board = Game::from_notation(GameConfig::default(), "112233").unwrap();
+        let mut agent =
+            Agents::create_agent("Minimax (5)", Player::Yellow, GameConfig::default()).unwrap();
+        let (action, lines) = explanation(&board, agent.as_mut());
+        assert_eq!(action, Some(3));
+        assert_eq!(lines[0], "Minimax (5) plays column 4 for Yellow");
+        let top = lines
+            .iter()
+            .position(|line| line.starts_with("Top columns"))
+            .unwrap();
+        assert!(lines[top + 1].starts_with("  column 4:"), "{:?}", lines);
+    }
+
+    #[test]
+    fn a_known_game_renders_into_the_test_backend() {
+        let mut app = app::App::new().unwrap();
+        app.position_prompt = Some("44".to_string());
+        app.load_position();
+        let buffer = render_to_buffer(&mut app, 120, 40).unwrap();
+        let lines = buffer_lines(&buffer);
+
+        // Both pieces stand in the fourth column, Yellow under Red
+        let empty_row = "│ · │ · │ · │ · │ · │ · │ · │";
+        let filled_row = "│ · │ · │ · │ ● │ · │ · │ · │";
+        let rows: Vec<usize> = (0..lines.len())
+            .filter(|&y| lines[y].contains(empty_row) || lines[y].contains(filled_row))
+            .collect();
+        assert_eq!(rows.len(), 6, "{}", lines.join("\n"));
+        let filled: Vec<usize> = rows
+            .iter()
+            .copied()
+            .filter(|&y| lines[y].contains(filled_row))
+            .collect();
+        assert_eq!(filled, rows[4..]);
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("1   2   3   4   5   6   7"))
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|line| line.contains("Current player: Yellow"))
+        );
+
+        // One cell per character, the piece sits in the fourth column's cell
+        let piece_color = |y: usize| {
+            let start = lines[y][..lines[y].find(filled_row).unwrap()]
+                .chars()
+                .count();
+            let offset = filled_row.chars().position(|c| c == '●').unwrap();
+            buffer.cell(((start + offset) as u16, y as u16)).unwrap().fg
+        };
+        assert_eq!(piece_color(rows[4]), Color::Red);
+        assert_eq!(piece_color(rows[5]), Color::Yellow);
+    }
+
+    #[test]
+    fn clocks_too_long_to_count_in_seconds_are_refused() {
+        let args = |clock: &str| vec!["--clock".to_string(), clock.to_string()];
+        let options = Options::from_args(&args("3+2")).unwrap();
+        assert_eq!(
+            options.time_control,
+            Some(TimeControl {
+                base: Duration::from_secs(180),
+                increment: Duration::from_secs(2),
+            })
+        );
+
+        let huge = (u64::MAX / 60 + 1).to_string();
+        let error = Options::from_args(&args(&format!("{}+2", huge)))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains(&huge), "{}", error);
+    }
+
+    #[test]
+    fn a_burst_of_one_key_is_handled_once() {
+        let start = Instant::now();
+        let at = |millis| start + Duration::from_millis(millis);
+        let mut debounce = KeyDebounce::new(Some(Duration::from_millis(150)));
+
+        // Key repeat every 30ms while Space is held
+        let handled = (0..5)
+            .filter(|&i| debounce.accept(KeyCode::Char(' '), at(i * 30)))
+            .count();
+        assert_eq!(handled, 1);
+        // Another key gets through right away, and Space again once the window has passed
+        assert!(debounce.accept(KeyCode::Right, at(130)));
+        assert!(debounce.accept(KeyCode::Char(' '), at(140)));
+        assert!(!debounce.accept(KeyCode::Char(' '), at(200)));
+        assert!(debounce.accept(KeyCode::Char(' '), at(300)));
+
+        let mut off = KeyDebounce::new(None);
+        assert!((0..5).all(|i| off.accept(KeyCode::Char(' '), at(i))));
+    }
+
+    // Wakes over the first second of a loop that sleeps exactly as long as it is told to,
+    // stepping whenever a step is due
+    fn simulate_wakes(timer: &mut LoopTimer, step_interval: Option<Duration>) -> Vec<(u128, Wake)> {
+        let start = timer.last_step;
+        let mut now = start;
+        let mut wakes = Vec::new();
+        while let Some((wait, wake)) = timer.next_wake(step_interval, None, None, now) {
+            now += wait;
+            if now - start > Duration::from_secs(1) {
+                break;
+            }
+            if wake == Wake::Step {
+                timer.stepped(now);
+            }
+            wakes.push(((now - start).as_millis(), wake));
+        }
+        wakes
+    }
+
+    #[test]
+    fn frames_are_drawn_at_their_own_rate_between_steps() {
+        let frame = Duration::from_millis(50);
+        let frames_only = |wakes: &[(u128, Wake)]| {
+            wakes
+                .iter()
+                .filter(|&&(_, wake)| wake == Wake::Frame)
+                .count()
+        };
+
+        // Slow steps every half second, with nine frames before each
+        let mut timer = LoopTimer::new(Some(frame));
+        let wakes = simulate_wakes(&mut timer, Some(Duration::from_millis(500)));
+        let steps: Vec<u128> = wakes
+            .iter()
+            .filter(|&&(_, wake)| wake == Wake::Step)
+            .map(|&(at, _)| at)
+            .collect();
+        assert_eq!(steps, [500, 1000]);
+        assert_eq!(frames_only(&wakes), 18);
+
+        // Without steps, at Manual speed, frames keep coming
+        let mut timer = LoopTimer::new(Some(frame));
+        let wakes = simulate_wakes(&mut timer, None);
+        assert_eq!(frames_only(&wakes), 20);
+        assert!(wakes.iter().all(|&(at, _)| at % 50 == 0));
+
+        // Nothing to wait for without a frame rate, the loop only waits for input
+        let timer = LoopTimer::new(None);
+        assert_eq!(timer.next_wake(None, None, None, Instant::now()), None);
+    }
 }
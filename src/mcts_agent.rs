@@ -0,0 +1,227 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::event::Event;
+use rand::{Rng, seq::IndexedRandom};
+
+use crate::{
+    agent::{Agent, center_opening},
+    game::{Game, GameState, Player, column_label},
+};
+
+/// AI agent using Monte Carlo Tree Search: grows a tree of moves from the current position,
+/// scores new leaves with random playouts and steers the search with UCT. Its cost depends on the
+/// iteration count rather than the board size, so it copes with the larger boards better than a
+/// fixed depth search
+pub struct MctsAgent {
+    pub iterations: usize,
+    // Win rate of the last chosen move, scaled to a search score
+    last_score: Option<i32>,
+    // Set by the app when the position being searched is abandoned
+    cancel: Arc<AtomicBool>,
+}
+
+/// Node of the search tree, stored in a flat arena and linked by index
+struct Node {
+    // Move leading to this node, None at the root
+    col: Option<usize>,
+    // Player who made that move, results are counted from their perspective
+    mover: Player,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    // Moves not expanded into children yet
+    untried: Vec<usize>,
+    visits: u32,
+    // Wins count 1 and draws count half
+    reward: f64,
+}
+
+impl Node {
+    fn new(col: Option<usize>, mover: Player, parent: Option<usize>, board: &Game) -> Self {
+        let untried = match board.state() {
+            GameState::InProgress => board.valid_moves(),
+            _ => Vec::new(),
+        };
+        Node {
+            col,
+            mover,
+            parent,
+            children: Vec::new(),
+            untried,
+            visits: 0,
+            reward: 0.0,
+        }
+    }
+
+    fn win_rate(&self) -> f64 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            self.reward / self.visits as f64
+        }
+    }
+}
+
+impl MctsAgent {
+    // Exploration constant of UCT, sqrt(2) suits rewards in [0, 1]
+    const EXPLORATION: f64 = std::f64::consts::SQRT_2;
+    // Score reported for a certain win, matching the minimax win score
+    const WIN_SCORE: f64 = 1000.0;
+
+    pub fn new(iterations: usize) -> Self {
+        MctsAgent {
+            iterations,
+            last_score: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Run the search from the given position and return the tree, the root is node 0
+    fn search(&self, board: &Game) -> Vec<Node> {
+        let mut rng = rand::rng();
+        let mut tree = vec![Node::new(
+            None,
+            board.current_player().opponent(),
+            None,
+            board,
+        )];
+
+        for _ in 0..self.iterations.max(1) {
+            if self.cancel.load(Ordering::Relaxed) {
+                break;
+            }
+            let mut node = 0;
+            let mut position = board.position();
+
+            // Selection: descend through fully expanded nodes
+            while tree[node].untried.is_empty() && !tree[node].children.is_empty() {
+                node = self.select_child(&tree, node);
+                if let Some(col) = tree[node].col {
+                    position.place(col);
+                }
+            }
+
+            // Expansion: add one untried move as a new leaf
+            if !tree[node].untried.is_empty() {
+                let index = rng.random_range(0..tree[node].untried.len());
+                let col = tree[node].untried.swap_remove(index);
+                let mover = position.current_player();
+                position.place(col);
+                tree.push(Node::new(Some(col), mover, Some(node), &position));
+                let child = tree.len() - 1;
+                tree[node].children.push(child);
+                node = child;
+            }
+
+            // Simulation: random moves until the game ends
+            let winner = Self::rollout(position, &mut rng);
+
+            // Backpropagation: credit every node on the path from its mover's perspective
+            let mut current = Some(node);
+            while let Some(index) = current {
+                let entry = &mut tree[index];
+                entry.visits += 1;
+                entry.reward += match winner {
+                    Some(player) if player == entry.mover => 1.0,
+                    Some(_) => 0.0,
+                    None => 0.5,
+                };
+                current = entry.parent;
+            }
+        }
+
+        tree
+    }
+
+    /// Child of the node with the best upper confidence bound
+    fn select_child(&self, tree: &[Node], node: usize) -> usize {
+        let parent_visits = (tree[node].visits.max(1) as f64).ln();
+        let uct = |child: usize| {
+            let child = &tree[child];
+            child.win_rate() + Self::EXPLORATION * (parent_visits / child.visits as f64).sqrt()
+        };
+        tree[node]
+            .children
+            .iter()
+            .copied()
+            .max_by(|&a, &b| uct(a).total_cmp(&uct(b)))
+            .unwrap_or(node)
+    }
+
+    /// Play random moves to the end of the game, returns the winner (None for a draw)
+    fn rollout(mut position: Game, rng: &mut impl Rng) -> Option<Player> {
+        loop {
+            match position.state() {
+                GameState::Won(player) => return Some(*player),
+                GameState::Draw => return None,
+                GameState::InProgress => {}
+            }
+            // Pop Out positions can be stuck with only pops left, score them as drawn
+            let col = *position.valid_moves().choose(rng)?;
+            position.place(col);
+        }
+    }
+
+    /// Root children, most visited first
+    fn ranked_moves(tree: &[Node]) -> Vec<&Node> {
+        let mut children: Vec<&Node> = tree[0].children.iter().map(|&child| &tree[child]).collect();
+        children.sort_by_key(|child| std::cmp::Reverse(child.visits));
+        children
+    }
+}
+
+impl Agent for MctsAgent {
+    fn get_action(&mut self, board: &Game, _event: Option<Event>) -> Option<usize> {
+        if let Some(col) = center_opening(board) {
+            self.last_score = None;
+            return Some(col);
+        }
+
+        let tree = self.search(board);
+        if self.cancel.load(Ordering::Relaxed) {
+            self.last_score = None;
+            return None;
+        }
+        let best = *Self::ranked_moves(&tree).first()?;
+        self.last_score = Some(((best.win_rate() * 2.0 - 1.0) * Self::WIN_SCORE) as i32);
+        best.col
+    }
+
+    fn get_type(&self) -> String {
+        format!("MCTS ({} iterations)", self.iterations)
+    }
+
+    fn last_evaluation(&self) -> Option<i32> {
+        self.last_score
+    }
+
+    fn set_cancel_token(&mut self, token: Arc<AtomicBool>) {
+        self.cancel = token;
+    }
+
+    fn explain(&self, board: &Game) -> Vec<String> {
+        let tree = self.search(board);
+        let mut lines = vec![format!("Top columns after {} iterations:", self.iterations)];
+        for child in Self::ranked_moves(&tree).iter().take(3) {
+            if let Some(col) = child.col {
+                lines.push(format!(
+                    "  column {}: {} visits, {:.0}% wins",
+                    column_label(col),
+                    child.visits,
+                    child.win_rate() * 100.0
+                ));
+            }
+        }
+        lines
+    }
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    fn learn(&mut self, _board: &Game, _player: Player) {
+        // No learning for MCTS agent
+    }
+}
@@ -1,16 +1,235 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
 use crossterm::event::Event;
 
 use crate::{
-    agent::Agent,
-    game::{Game, GameState, Player},
+    agent::{Agent, center_opening},
+    evaluator::{ClassicEvaluator, Evaluator},
+    game::{Game, GameConfig, GameState, Player, column_from_label, column_label},
 };
 
 /// AI agent using minimax algorithm with alpha-beta pruning
 pub struct MinimaxAgent {
     pub max_depth: usize,
+    /// Never play under an opponent's threat (unless every move does)
+    pub avoid_traps: bool,
+    /// Search with iterative deepening until the budget runs out (up to max_depth)
+    pub time_budget: Option<Duration>,
+    /// Depth always searched to completion, even when over the time budget
+    pub min_depth: usize,
+    /// Static evaluation of the positions at the search horizon
+    pub evaluator: Box<dyn Evaluator>,
+    /// Search only this many of the most promising moves below the root, plus any immediate
+    /// wins and blocks. Trades accuracy for speed on wide boards
+    pub branch_cap: Option<usize>,
+    /// Play the first moves on the standard board from `OPENING_BOOK` instead of searching
+    pub opening_book: bool,
+
+    // Deadline of the current search iteration, None while within min_depth
+    deadline: Cell<Option<Instant>>,
+    // Set when the current iteration ran past the deadline and must be discarded
+    timed_out: Cell<bool>,
+    // Set by the app when the position being searched is abandoned
+    cancel: Arc<AtomicBool>,
+    // Root score of the last searched move
+    last_score: Option<i32>,
+    // Deepest iteration the last search completed, None when it didn't search
+    last_depth: Option<usize>,
+    // Scores of positions searched this move, keyed by position and the perspective they were
+    // scored from
+    transpositions: RefCell<HashMap<String, TableEntry>>,
+    // Lookups answered by the transposition table since it was last cleared
+    table_hits: Cell<usize>,
+}
+
+// What a stored score says about the position's true score
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Bound {
+    Exact,
+    // A cutoff stopped the search, the true score is at least this
+    Lower,
+    // No move reached alpha, the true score is at most this
+    Upper,
+}
+
+// Transposition table score with the depth it was searched to
+#[derive(Clone, Copy, Debug)]
+struct TableEntry {
+    value: i32,
+    depth: usize,
+    bound: Bound,
 }
 
 impl MinimaxAgent {
+    const WIN_SCORE: i32 = 1000;
+    // Standard board replies keyed by the moves so far, both in 1-indexed move notation.
+    // The center is the strongest first move and the strongest answer to any first move, which
+    // shallow searches only find slowly since early evaluations are flat
+    const OPENING_BOOK: [(&str, usize); 8] = [
+        ("", 4),
+        ("1", 4),
+        ("2", 4),
+        ("3", 4),
+        ("4", 4),
+        ("5", 4),
+        ("6", 4),
+        ("7", 4),
+    ];
+
+    pub fn new(max_depth: usize) -> Self {
+        Self::with_evaluator(max_depth, Box::new(ClassicEvaluator))
+    }
+
+    /// Agent scoring the positions at its search horizon with a custom evaluator
+    pub fn with_evaluator(max_depth: usize, evaluator: Box<dyn Evaluator>) -> Self {
+        MinimaxAgent {
+            max_depth,
+            evaluator,
+            avoid_traps: true,
+            time_budget: None,
+            min_depth: 1,
+            branch_cap: None,
+            opening_book: true,
+            deadline: Cell::new(None),
+            timed_out: Cell::new(false),
+            cancel: Arc::new(AtomicBool::new(false)),
+            last_score: None,
+            last_depth: None,
+            transpositions: RefCell::new(HashMap::new()),
+            table_hits: Cell::new(0),
+        }
+    }
+
+    // Transposition table key, scores depend on whose perspective they were taken from
+    fn table_key(board: &Game, player: Player) -> String {
+        let mut key = board.to_key();
+        key.push(match player {
+            Player::Yellow => 'y',
+            Player::Red => 'r',
+        });
+        key
+    }
+
+    /// Column the opening book recommends in the position, None once it is out of the book or
+    /// on any other board
+    fn book_move(&self, board: &Game) -> Option<usize> {
+        if !self.opening_book || *board.config() != GameConfig::default() {
+            return None;
+        }
+        let key = board.to_key();
+        Self::OPENING_BOOK
+            .iter()
+            .filter(|(moves, _)| moves.len() == board.move_count())
+            .find(|(moves, _)| {
+                let mut book_board = Game::new();
+                book_board.set_starting_player(board.first_player());
+                book_board
+                    .play_notation(moves)
+                    .is_ok_and(|book_board| book_board.to_key() == key)
+            })
+            .and_then(|&(_, label)| column_from_label(label))
+    }
+
+    /// Depth of the per-column scores shown by explain, timed agents use the depth they
+    /// always complete
+    fn explain_depth(&self) -> usize {
+        match self.time_budget {
+            Some(_) => self.min_depth,
+            None => self.max_depth,
+        }
+        .max(1)
+    }
+
+    /// Agent which deepens its search while time remains, always completing min_depth
+    pub fn with_time_budget(max_depth: usize, min_depth: usize, time_budget: Duration) -> Self {
+        MinimaxAgent {
+            time_budget: Some(time_budget),
+            min_depth,
+            ..Self::new(max_depth)
+        }
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.load(Ordering::Relaxed)
+    }
+
+    /// Check the deadline of the current iteration and the cancel token, flagging a timeout
+    /// once either is hit
+    fn out_of_time(&self) -> bool {
+        if self.timed_out.get() {
+            return true;
+        }
+        if self.cancelled() {
+            self.timed_out.set(true);
+            return true;
+        }
+        if self
+            .deadline
+            .get()
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.timed_out.set(true);
+        }
+        self.timed_out.get()
+    }
+
+    /// Search all root moves to the given depth, returning the best column and its score
+    fn search_root(&self, board: &Game, valid_moves: &[usize], depth: usize) -> (usize, i32) {
+        let current_player = board.current_player();
+
+        // Center first, so equal evaluations resolve toward the center
+        let center = board.config().cols as i32 / 2;
+        let mut candidates = valid_moves.to_vec();
+        candidates.sort_by_key(|&col| (col as i32 - center).abs());
+
+        let mut best_col = candidates[0];
+        let mut best_value = i32::MIN;
+        let mut alpha = i32::MIN;
+        let beta = i32::MAX;
+
+        for col in candidates {
+            if let Some(board_copy) = board.with_move(col) {
+                let value =
+                    self.minimax(current_player, &board_copy, depth - 1, alpha, beta, false);
+
+                if value > best_value {
+                    best_value = value;
+                    best_col = col;
+                }
+                alpha = alpha.max(best_value);
+            }
+        }
+
+        (best_col, best_value)
+    }
+
+    /// Line of best play from the position, each move found by a search of the remaining depth
+    fn principal_variation(&self, board: &Game, depth: usize) -> Vec<usize> {
+        let mut line = Vec::new();
+        let mut board = board.position();
+        for remaining in (1..=depth).rev() {
+            let valid_moves = board.valid_moves();
+            if valid_moves.is_empty() || *board.state() != GameState::InProgress {
+                break;
+            }
+            let (col, _) = self.search_root(&board, &valid_moves, remaining);
+            line.push(col);
+            match board.with_move(col) {
+                Some(next) => board = next,
+                None => break,
+            }
+        }
+        line
+    }
+
     /// Minimax algorithm with alpha-beta pruning
     fn minimax(
         &self,
@@ -21,37 +240,60 @@ impl MinimaxAgent {
         beta: i32,
         is_maximizing: bool,
     ) -> i32 {
+        // Abandon the search, the caller discards the result
+        if self.out_of_time() {
+            return 0;
+        }
+
         // Evaluate the current board state
         let board_state = self.evaluate_board(board);
 
         // Terminal conditions
         match board_state {
             GameState::Won(p) => {
-                return if p == player { 1000 } else { -1000 };
+                return if p == player {
+                    Self::WIN_SCORE
+                } else {
+                    -Self::WIN_SCORE
+                };
             }
             GameState::Draw => return 0,
             GameState::InProgress => {
                 // If we've reached max depth, evaluate the position
                 if depth == 0 {
-                    return self.eval_position(board);
+                    return self.evaluator.evaluate(board, player);
                 }
             }
         }
 
+        // Reuse the score of an earlier search of the position at least as deep, a bound only
+        // when it is enough to cut this window off
+        let key = Self::table_key(board, player);
+        if let Some(&entry) = self.transpositions.borrow().get(&key)
+            && entry.depth >= depth
+            && match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => entry.value >= beta,
+                Bound::Upper => entry.value <= alpha,
+            }
+        {
+            self.table_hits.set(self.table_hits.get() + 1);
+            return entry.value;
+        }
+
         // Get valid actions based on the board state
-        let valid_moves: Vec<usize> = board.valid_moves();
+        let valid_moves: Vec<usize> = self.candidate_moves(board);
 
         if valid_moves.is_empty() {
             return 0; // No valid moves, treat as neutral
         }
 
-        if is_maximizing {
+        let value = if is_maximizing {
             let mut max_eval = i32::MIN;
             let mut alpha = alpha;
 
             for &col in &valid_moves {
-                let mut board_copy = board.clone();
-                if board_copy.place(col).is_some() {
+                if let Some(board_copy) = board.with_move(col) {
                     let eval = self.minimax(player, &board_copy, depth - 1, alpha, beta, false);
                     max_eval = max_eval.max(eval);
                     alpha = alpha.max(eval);
@@ -67,8 +309,7 @@ impl MinimaxAgent {
             let mut beta = beta;
 
             for &col in &valid_moves {
-                let mut board_copy = board.clone();
-                if board_copy.place(col).is_some() {
+                if let Some(board_copy) = board.with_move(col) {
                     let eval = self.minimax(player, &board_copy, depth - 1, alpha, beta, true);
                     min_eval = min_eval.min(eval);
                     beta = beta.min(eval);
@@ -79,18 +320,76 @@ impl MinimaxAgent {
             }
 
             min_eval
+        };
+
+        // Scores outside the window are only bounds, and an abandoned search returns nothing
+        // meaningful
+        if !self.timed_out.get() {
+            let bound = if value <= alpha {
+                Bound::Upper
+            } else if value >= beta {
+                Bound::Lower
+            } else {
+                Bound::Exact
+            };
+            self.transpositions.borrow_mut().insert(
+                key,
+                TableEntry {
+                    value,
+                    depth,
+                    bound,
+                },
+            );
+        }
+        value
+    }
+
+    /// Valid moves to search at an inner node. With a branch cap only the best moves by a one
+    /// ply static evaluation are kept, but wins and blocks of the opponent's wins always are
+    fn candidate_moves(&self, board: &Game) -> Vec<usize> {
+        let valid_moves = board.valid_moves();
+        let Some(cap) = self.branch_cap else {
+            return valid_moves;
+        };
+        if valid_moves.len() <= cap {
+            return valid_moves;
         }
+
+        let mover = board.current_player();
+        let forced = i32::MAX;
+        let mut scored: Vec<(i32, usize)> = valid_moves
+            .into_iter()
+            .filter_map(|col| {
+                let child = board.with_move(col)?;
+                let score = if matches!(child.state(), GameState::Won(_))
+                    || self.is_winning_move(board, col, mover.opponent())
+                {
+                    forced
+                } else {
+                    self.evaluator.evaluate(&child, mover)
+                };
+                Some((score, col))
+            })
+            .collect();
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+
+        scored
+            .into_iter()
+            .enumerate()
+            .filter(|&(rank, (score, _))| rank < cap || score == forced)
+            .map(|(_, (_, col))| col)
+            .collect()
     }
 
     /// Evaluate if the board is in a terminal state
     fn evaluate_board(&self, board: &Game) -> GameState {
         // The game already tracks its state, so we can just return it
-        board.state().clone()
+        *board.state()
     }
 
     /// Checks if playing in the given column would result in a win
     fn is_winning_move(&self, board: &Game, column: usize, player: Player) -> bool {
-        let mut board_copy = board.clone();
+        let mut board_copy = board.position();
 
         // Try to place a piece for the specified player
         let current_player = board_copy.current_player();
@@ -98,10 +397,11 @@ impl MinimaxAgent {
             // If it's not the player's turn, we need two moves to test
             // First, place a piece for the current player in a different column if possible
             for col in 0..board.config().cols {
-                if col != column && !board_copy.is_column_full(col) {
-                    if board_copy.place(col).is_some() {
-                        break;
-                    }
+                if col != column
+                    && !board_copy.is_column_full(col)
+                    && board_copy.place(col).is_some()
+                {
+                    break;
                 }
             }
 
@@ -112,104 +412,27 @@ impl MinimaxAgent {
         }
 
         // Place the piece and check if it results in a win
-        if board_copy.place(column).is_some() {
-            match board_copy.state() {
-                GameState::Won(p) if *p == player => true,
-                _ => false,
-            }
-        } else {
-            false
-        }
-    }
-
-    /// Evaluation function for non-terminal board positions
-    fn eval_position(&self, board: &Game) -> i32 {
-        let mut score = 0;
-        let my_color = board.current_player();
-        let opponent_color = match my_color {
-            Player::Yellow => Player::Red,
-            Player::Red => Player::Yellow,
-        };
-
-        // Evaluate center control (column 3, which is index 3)
-        let center_col = board.config().cols / 2;
-        for row in 0..board.config().rows {
-            match board.get_cell(row, center_col) {
-                Some(player) if player == my_color => score += 5, // Prioritize center control
-                Some(player) if player == opponent_color => score -= 2, // Penalize opponent's center control
-                _ => {}
-            }
-        }
-
-        // Evaluate pieces with their positions
-        for row in 0..board.config().rows {
-            for col in 0..board.config().cols {
-                match board.get_cell(row, col) {
-                    Some(player) if player == my_color => {
-                        // Pieces closer to the center are more valuable
-                        score += 5 - (col as i32 - center_col as i32).abs();
-
-                        // Check for adjacent friendly pieces
-                        if self.has_adjacent_same_color(board, row, col, my_color) {
-                            score += 2;
-                        }
-                    }
-                    Some(player) if player == opponent_color => {
-                        // Opponent pieces are bad (especially in the center)
-                        score -= 6 - (col as i32 - center_col as i32).abs();
-
-                        // Check for adjacent enemy pieces (potential threats)
-                        if self.has_adjacent_same_color(board, row, col, opponent_color) {
-                            score -= 2;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-        }
-
-        score
-    }
-
-    /// Helper method to check if a position has adjacent pieces of the same color
-    fn has_adjacent_same_color(&self, board: &Game, row: usize, col: usize, color: Player) -> bool {
-        let directions = [
-            (0, -1), // left
-            (0, 1),  // right
-            (1, 0),  // down
-            (1, -1), // diagonal down-left
-            (1, 1),  // diagonal down-right
-        ];
-
-        for &(row_dir, col_dir) in &directions {
-            let new_row = row as i32 + row_dir;
-            let new_col = col as i32 + col_dir;
-
-            // Check if position is valid and has the same color
-            if new_row >= 0
-                && new_row < board.config().rows as i32
-                && new_col >= 0
-                && new_col < board.config().cols as i32
-            {
-                if let Some(player) = board.get_cell(new_row as usize, new_col as usize) {
-                    if player == color {
-                        return true;
-                    }
-                }
-            }
-        }
-
-        false
+        board_copy
+            .with_move_for(column, player)
+            .is_some_and(|next| matches!(next.state(), GameState::Won(p) if *p == player))
     }
 }
 
 impl Agent for MinimaxAgent {
     fn get_action(&mut self, board: &Game, _event: Option<Event>) -> Option<usize> {
+        self.last_score = None;
+        self.last_depth = None;
+        self.transpositions.get_mut().clear();
+        self.table_hits.set(0);
+        if let Some(col) = self.book_move(board).or_else(|| center_opening(board)) {
+            return Some(col);
+        }
+
         let valid_moves: Vec<usize> = board.valid_moves();
 
-        // If only one action is available, return it immediately
-        if valid_moves.len() == 1 {
-            return Some(valid_moves[0]);
+        // If at most one action is available, return it immediately
+        if valid_moves.len() <= 1 {
+            return valid_moves.first().copied();
         }
 
         // This is us
@@ -218,15 +441,13 @@ impl Agent for MinimaxAgent {
         // Check if we can win in one move
         for &col in &valid_moves {
             if self.is_winning_move(board, col, current_player) {
+                self.last_score = Some(Self::WIN_SCORE);
                 return Some(col);
             }
         }
 
         // Check if we need to block opponent's winning move
-        let opponent = match current_player {
-            Player::Yellow => Player::Red,
-            Player::Red => Player::Yellow,
-        };
+        let opponent = current_player.opponent();
 
         for &col in &valid_moves {
             if self.is_winning_move(board, col, opponent) {
@@ -234,38 +455,134 @@ impl Agent for MinimaxAgent {
             }
         }
 
+        // Filter out moves which hand the opponent a win directly above
+        let valid_moves = if self.avoid_traps {
+            let safe_moves: Vec<usize> = valid_moves
+                .iter()
+                .copied()
+                .filter(|&col| !board.enables_opponent_win(col))
+                .collect();
+            if safe_moves.is_empty() {
+                valid_moves
+            } else {
+                safe_moves
+            }
+        } else {
+            valid_moves
+        };
+
         // Run minimax to find the best move
-        let mut best_col = valid_moves[valid_moves.len() - 1];
-        let mut best_value = i32::MIN;
-        let mut alpha = i32::MIN;
-        let beta = i32::MAX;
+        let Some(time_budget) = self.time_budget else {
+            let (col, score) = self.search_root(board, &valid_moves, self.max_depth);
+            if self.cancelled() {
+                self.timed_out.set(false);
+                return None;
+            }
+            self.last_score = Some(score);
+            self.last_depth = Some(self.max_depth);
+            return Some(col);
+        };
 
-        for &col in &valid_moves {
-            let mut board_copy = board.clone();
-            if board_copy.place(col).is_some() {
-                let value = self.minimax(
-                    current_player,
-                    &board_copy,
-                    self.max_depth - 1,
-                    alpha,
-                    beta,
-                    false,
-                );
+        // Iterative deepening, iterations past min_depth stop once the budget is spent
+        let start = Instant::now();
+        let mut best_col = None;
+        for depth in 1..=self.max_depth.max(self.min_depth) {
+            let deadline = (depth > self.min_depth).then_some(start + time_budget);
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+            self.deadline.set(deadline);
+            self.timed_out.set(false);
 
-                if value > best_value {
-                    best_value = value;
-                    best_col = col;
-                }
-                alpha = alpha.max(best_value);
+            let (col, score) = self.search_root(board, &valid_moves, depth);
+            if self.timed_out.get() {
+                break;
             }
+            best_col = Some(col);
+            self.last_score = Some(score);
+            self.last_depth = Some(depth);
         }
+        self.deadline.set(None);
+        self.timed_out.set(false);
 
-        Some(best_col)
+        if self.cancelled() {
+            return None;
+        }
+        best_col
     }
 
     fn get_type(&self) -> String {
-        // Display type + depth
-        format!("Minimax ({})", self.max_depth)
+        // Display type + depth (or time budget)
+        match self.time_budget {
+            Some(time_budget) => format!("Minimax ({}ms)", time_budget.as_millis()),
+            None => format!("Minimax ({})", self.max_depth),
+        }
+    }
+
+    fn last_evaluation(&self) -> Option<i32> {
+        self.last_score
+    }
+
+    fn search_depth(&self) -> Option<usize> {
+        Some(self.max_depth)
+    }
+
+    fn set_search_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+        self.min_depth = self.min_depth.min(depth);
+    }
+
+    fn set_branch_cap(&mut self, cap: Option<usize>) {
+        self.branch_cap = cap;
+    }
+
+    fn set_cancel_token(&mut self, token: Arc<AtomicBool>) {
+        self.cancel = token;
+    }
+
+    fn evaluate_columns(&self, board: &Game) -> Vec<(usize, i32)> {
+        let depth = self.explain_depth();
+        let player = board.current_player();
+        // Full window per move, so every score is exact rather than a pruning bound
+        board
+            .valid_moves()
+            .into_iter()
+            .filter_map(|col| {
+                let child = board.with_move(col)?;
+                let score = self.minimax(player, &child, depth - 1, i32::MIN, i32::MAX, false);
+                Some((col, score))
+            })
+            .collect()
+    }
+
+    fn explain(&self, board: &Game) -> Vec<String> {
+        let depth = self.explain_depth();
+        if let Some(col) = self.book_move(board) {
+            return vec![format!("Opening book: column {}", column_label(col))];
+        }
+
+        let mut scores = self.evaluate_columns(board);
+        scores.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+        let mut lines = vec![format!("Top columns at depth {}:", depth)];
+        for (col, score) in scores.iter().take(3) {
+            lines.push(format!("  column {}: {}", column_label(*col), score));
+        }
+        let line: Vec<String> = self
+            .principal_variation(board, depth)
+            .into_iter()
+            .map(|col| column_label(col).to_string())
+            .collect();
+        lines.push(format!("Principal variation: {}", line.join(" ")));
+        if let Some(depth) = self.last_depth {
+            lines.push(format!("Last search completed depth {}", depth));
+        }
+        lines.push(format!(
+            "Transposition table: {} positions, {} hits",
+            self.transpositions.borrow().len(),
+            self.table_hits.get()
+        ));
+        lines
     }
 
     fn is_human(&self) -> bool {
@@ -276,3 +593,176 @@ impl Agent for MinimaxAgent {
         // No learning for minimax agent
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameConfigPreset;
+
+    #[test]
+    fn cancelled_searches_return_no_move() {
+        let board = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        let mut agent = MinimaxAgent::new(5);
+        assert!(agent.get_action(&board, None).is_some());
+
+        let cancel = Arc::new(AtomicBool::new(true));
+        agent.set_cancel_token(Arc::clone(&cancel));
+        assert_eq!(agent.get_action(&board, None), None);
+        let mut timed = MinimaxAgent::with_time_budget(9, 3, Duration::from_secs(1));
+        timed.set_cancel_token(cancel);
+        assert_eq!(timed.get_action(&board, None), None);
+    }
+
+    #[test]
+    fn searches_stop_when_cancelled_from_another_thread() {
+        // Far too deep to finish, only the token set while it runs can end the search
+        let board = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        let mut agent = MinimaxAgent::new(30);
+        let cancel = Arc::new(AtomicBool::new(false));
+        agent.set_cancel_token(Arc::clone(&cancel));
+
+        let start = Instant::now();
+        let canceller = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            cancel.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(agent.get_action(&board, None), None);
+        canceller.join().unwrap();
+        assert!(
+            start.elapsed() < Duration::from_secs(2),
+            "{:?}",
+            start.elapsed()
+        );
+        assert_eq!(board.move_count(), 4);
+    }
+
+    #[test]
+    fn a_second_search_of_a_position_hits_the_table() {
+        let board = Game::from_notation(GameConfig::default(), "44536").unwrap();
+        let agent = MinimaxAgent::new(6);
+        let valid_moves = board.valid_moves();
+
+        let first = agent.search_root(&board, &valid_moves, 6);
+        let first_hits = agent.table_hits.get();
+        // Cutoffs below the root leave bounds in the table, not only exact scores
+        assert!(
+            agent
+                .transpositions
+                .borrow()
+                .values()
+                .any(|entry| entry.bound != Bound::Exact)
+        );
+
+        let second = agent.search_root(&board, &valid_moves, 6);
+        assert_eq!(second, first);
+        assert!(agent.table_hits.get() > first_hits);
+    }
+
+    // Rewards the root player's pieces in one column and nothing else
+    struct ColumnBonus(usize);
+
+    impl Evaluator for ColumnBonus {
+        fn evaluate(&self, board: &Game, root_player: Player) -> i32 {
+            (0..board.config().rows)
+                .filter(|&row| board.get_cell(row, self.0) == Some(root_player))
+                .count() as i32
+        }
+    }
+
+    #[test]
+    fn a_custom_evaluator_decides_the_move() {
+        // Nothing is threatened yet, so only the evaluation tells the columns apart
+        let board = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        for col in [0, 6] {
+            let mut agent = MinimaxAgent::with_evaluator(3, Box::new(ColumnBonus(col)));
+            assert_eq!(agent.get_action(&board, None), Some(col));
+            // Its piece there and another one after the reply
+            assert_eq!(agent.last_evaluation(), Some(2));
+        }
+        assert_ne!(MinimaxAgent::new(3).get_action(&board, None), Some(0));
+    }
+
+    #[test]
+    fn a_zero_budget_still_completes_the_minimum_depth() {
+        let board = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        let mut agent = MinimaxAgent::with_time_budget(9, 4, Duration::ZERO);
+        assert!(agent.get_action(&board, None).is_some());
+        assert!(
+            agent.last_depth.is_some_and(|depth| depth >= 4),
+            "{:?}",
+            agent.last_depth
+        );
+        assert!(
+            agent
+                .explain(&board)
+                .contains(&"Last search completed depth 4".to_string())
+        );
+    }
+
+    #[test]
+    fn a_branch_cap_keeps_wins_and_blocks_and_searches_less() {
+        let config = GameConfigPreset::Huge.into_config();
+        // Yellow has five along the bottom and Red five on top of them, Yellow to move
+        let mut board = Game::with_config(config);
+        for col in 0..5 {
+            board.place(col);
+            board.place(col);
+        }
+        let mut capped = MinimaxAgent::new(3);
+        capped.set_branch_cap(Some(2));
+        assert!(capped.candidate_moves(&board).contains(&5));
+        assert_eq!(capped.get_action(&board, None), Some(5));
+
+        // Once Yellow plays elsewhere, Red's block is kept as well
+        board.place(9);
+        let candidates = capped.candidate_moves(&board);
+        assert!(candidates.contains(&5), "{:?}", candidates);
+        assert!(candidates.len() < board.valid_moves().len());
+
+        // The capped search of a quiet position visits far fewer positions
+        let quiet = Game::from_notation(config, "5566").unwrap();
+        let valid_moves = quiet.valid_moves();
+        let full = MinimaxAgent::new(4);
+        full.search_root(&quiet, &valid_moves, 4);
+        capped.search_root(&quiet, &valid_moves, 4);
+        let (full, capped) = (
+            full.transpositions.borrow().len(),
+            capped.transpositions.borrow().len(),
+        );
+        assert!(capped * 4 < full, "{} vs {}", capped, full);
+    }
+
+    #[test]
+    fn the_only_move_not_under_a_threat_is_played() {
+        // Red to move, and either of the other open columns lets Yellow win on top of it
+        let board =
+            Game::from_notation(GameConfig::default(), "247474572557477143225522543").unwrap();
+        assert_eq!(board.valid_moves(), [0, 2, 5]);
+        assert!(!board.enables_opponent_win(0));
+        assert!(board.enables_opponent_win(2) && board.enables_opponent_win(5));
+        assert_eq!(MinimaxAgent::new(1).get_action(&board, None), Some(0));
+
+        // Too shallow to see the reply, the search alone walks into it
+        let mut careless = MinimaxAgent::new(1);
+        careless.avoid_traps = false;
+        assert_ne!(careless.get_action(&board, None), Some(0));
+    }
+
+    #[test]
+    fn a_new_search_depth_applies_to_the_next_search() {
+        let board = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        let mut agent = MinimaxAgent::new(5);
+        agent.get_action(&board, None);
+        assert_eq!(agent.last_depth, Some(5));
+        agent.set_search_depth(2);
+        assert_eq!(agent.search_depth(), Some(2));
+        agent.get_action(&board, None);
+        assert_eq!(agent.last_depth, Some(2));
+
+        // A timed agent's guaranteed depth comes down with it
+        let mut timed = MinimaxAgent::with_time_budget(9, 4, Duration::ZERO);
+        timed.set_search_depth(2);
+        timed.get_action(&board, None);
+        assert_eq!(timed.last_depth, Some(2));
+    }
+}
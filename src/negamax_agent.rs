@@ -0,0 +1,196 @@
+use crossterm::event::Event;
+
+use crate::{
+    agent::{Agent, center_opening},
+    evaluator::{ClassicEvaluator, Evaluator},
+    game::{Game, GameState, Player, column_label},
+};
+
+/// AI agent using negamax with alpha-beta pruning: one search branch scored from the side to
+/// move, negated on the way up. Keeps the principal variation of its last search, the line
+/// it expects both players to follow
+pub struct NegamaxAgent {
+    pub max_depth: usize,
+    /// Static evaluation of the positions at the search horizon, shared with minimax
+    pub evaluator: Box<dyn Evaluator>,
+
+    // Root score of the last searched move
+    last_score: Option<i32>,
+    // Best line found by the last search, starting with the chosen move
+    principal_variation: Vec<usize>,
+}
+
+impl NegamaxAgent {
+    const WIN_SCORE: i32 = 1000;
+    // Bound of every score, kept well clear of i32::MIN so it can be negated. Evaluations are
+    // clamped below WIN_SCORE, so no score reaches it
+    const INFINITY: i32 = Self::WIN_SCORE * 2;
+
+    pub fn new(max_depth: usize) -> Self {
+        NegamaxAgent {
+            max_depth,
+            evaluator: Box::new(ClassicEvaluator),
+            last_score: None,
+            principal_variation: Vec::new(),
+        }
+    }
+
+    /// Valid moves ordered center first, which improves pruning and breaks ties toward the center
+    fn ordered_moves(board: &Game) -> Vec<usize> {
+        let center = board.config().cols as i32 / 2;
+        let mut moves = board.valid_moves();
+        moves.sort_by_key(|&col| (col as i32 - center).abs());
+        moves
+    }
+
+    /// Score of the position for the side to move, filling `line` with the best line from it.
+    /// Wins score higher the sooner they come
+    fn negamax(
+        &self,
+        board: &Game,
+        depth: usize,
+        alpha: i32,
+        beta: i32,
+        line: &mut Vec<usize>,
+    ) -> i32 {
+        line.clear();
+        if depth == 0 {
+            // A custom evaluator's score must not pass for a win or fall outside the window
+            return self
+                .evaluator
+                .evaluate(board, board.current_player())
+                .clamp(-Self::WIN_SCORE + 1, Self::WIN_SCORE - 1);
+        }
+
+        let mut any_move = false;
+        let mut best = -Self::INFINITY;
+        let mut alpha = alpha;
+        let mut child_line = Vec::new();
+        for col in Self::ordered_moves(board) {
+            let Some(child) = board.with_move(col) else {
+                continue;
+            };
+            any_move = true;
+            let value = match child.state() {
+                GameState::Won(_) => {
+                    child_line.clear();
+                    Self::WIN_SCORE + depth as i32
+                }
+                GameState::Draw => {
+                    child_line.clear();
+                    0
+                }
+                GameState::InProgress => {
+                    -self.negamax(&child, depth - 1, -beta, -alpha, &mut child_line)
+                }
+            };
+            if value > best {
+                best = value;
+                line.clear();
+                line.push(col);
+                line.extend_from_slice(&child_line);
+            }
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        // No valid moves, treat as a draw
+        if any_move { best } else { 0 }
+    }
+
+    /// Search the position to the full depth, returning the score and principal variation
+    fn search(&self, board: &Game) -> (i32, Vec<usize>) {
+        let mut line = Vec::new();
+        let score = self.negamax(
+            board,
+            self.max_depth.max(1),
+            -Self::INFINITY,
+            Self::INFINITY,
+            &mut line,
+        );
+        (score, line)
+    }
+}
+
+impl Agent for NegamaxAgent {
+    fn get_action(&mut self, board: &Game, _event: Option<Event>) -> Option<usize> {
+        self.last_score = None;
+        self.principal_variation.clear();
+        if let Some(col) = center_opening(board) {
+            self.principal_variation.push(col);
+            return Some(col);
+        }
+
+        let (score, line) = self.search(board);
+        self.last_score = Some(score);
+        self.principal_variation = line;
+        self.principal_variation.first().copied()
+    }
+
+    fn get_type(&self) -> String {
+        format!("Negamax ({})", self.max_depth)
+    }
+
+    fn last_evaluation(&self) -> Option<i32> {
+        self.last_score
+    }
+
+    fn expected_line(&self) -> Option<Vec<usize>> {
+        Some(self.principal_variation.clone())
+    }
+
+    fn search_depth(&self) -> Option<usize> {
+        Some(self.max_depth)
+    }
+
+    fn set_search_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    fn explain(&self, board: &Game) -> Vec<String> {
+        let (score, line) = self.search(board);
+        let line: Vec<String> = line
+            .into_iter()
+            .map(|col| column_label(col).to_string())
+            .collect();
+        vec![
+            format!("Score at depth {}: {}", self.max_depth.max(1), score),
+            format!("Principal variation: {}", line.join(" ")),
+        ]
+    }
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    fn learn(&mut self, _board: &Game, _player: Player) {
+        // No learning for negamax agent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::GameConfig;
+
+    // Scores every position far below any win or loss
+    struct Hopeless;
+
+    impl Evaluator for Hopeless {
+        fn evaluate(&self, _board: &Game, _root_player: Player) -> i32 {
+            -1_000_000
+        }
+    }
+
+    #[test]
+    fn out_of_range_evaluations_still_give_a_move() {
+        let board = Game::from_notation(GameConfig::default(), "4453").unwrap();
+        let mut agent = NegamaxAgent::new(2);
+        agent.evaluator = Box::new(Hopeless);
+        let col = agent.get_action(&board, None);
+        assert!(col.is_some_and(|col| board.valid_moves().contains(&col)));
+        assert!(agent.last_evaluation().unwrap().abs() < NegamaxAgent::WIN_SCORE);
+    }
+}
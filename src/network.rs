@@ -0,0 +1,337 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use crossterm::event::Event;
+
+use crate::{
+    agent::Agent,
+    game::{Game, GameState, Player, column_from_label, column_label},
+};
+
+/// Longest wait for the remote player's move before giving up on them
+const MOVE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Agent for a player on another machine. On its turn the game is sent over the connection
+/// as one line of JSON and the reply is a line holding the 1-indexed column to drop in.
+/// Finished games are sent the same way so the remote player sees the result. Pops are not
+/// supported, and a lost connection forfeits the game like an agent without a move.
+/// The connection is only read without blocking, so the app keeps drawing while it waits
+pub struct NetworkAgent {
+    connection: Option<TcpStream>,
+    peer: String,
+    // Bytes received but not yet ended by a newline
+    received: Vec<u8>,
+    // When the current position was sent, None while no reply is awaited
+    asked: Option<Instant>,
+}
+
+impl NetworkAgent {
+    /// Agent over the connection, or one that never moves without it
+    pub fn new(stream: Option<TcpStream>) -> Self {
+        let peer = stream
+            .as_ref()
+            .and_then(|stream| stream.peer_addr().ok())
+            .map_or_else(|| "disconnected".to_string(), |addr| addr.to_string());
+        NetworkAgent {
+            connection: stream,
+            peer,
+            received: Vec::new(),
+            asked: None,
+        }
+    }
+
+    // Send the game, dropping the connection if it fails
+    fn send(&mut self, board: &Game) -> Option<()> {
+        let connection = self.connection.as_mut()?;
+        let sent = send_game(connection, board);
+        if sent.is_err() {
+            self.connection = None;
+        }
+        sent.ok()
+    }
+
+    // Send the position and start waiting for the reply to it. Anything the peer sent
+    // before is an answer to an older position and is dropped
+    fn ask(&mut self, board: &Game) -> Option<()> {
+        if self.next_line().is_err() {
+            self.connection = None;
+            return None;
+        }
+        self.received.clear();
+        self.send(board)?;
+        self.asked = Some(Instant::now());
+        Some(())
+    }
+
+    // Next complete line from the peer without waiting for one, Err once the connection is
+    // closed or broken
+    fn next_line(&mut self) -> io::Result<Option<String>> {
+        let connection = self
+            .connection
+            .as_mut()
+            .ok_or(io::ErrorKind::NotConnected)?;
+        connection.set_nonblocking(true)?;
+        let mut chunk = [0; 256];
+        let read = loop {
+            match connection.read(&mut chunk) {
+                Ok(0) => break Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(length) => self.received.extend_from_slice(&chunk[..length]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        // Sends block as before
+        connection.set_nonblocking(false)?;
+        read?;
+
+        let Some(end) = self.received.iter().position(|&byte| byte == b'\n') else {
+            return Ok(None);
+        };
+        let line: Vec<u8> = self.received.drain(..=end).collect();
+        Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+    }
+}
+
+impl Agent for NetworkAgent {
+    fn get_action(&mut self, board: &Game, _event: Option<Event>) -> Option<usize> {
+        if self.asked.is_none() {
+            self.ask(board)?;
+        }
+        match self.next_line() {
+            Ok(Some(reply)) => {
+                self.asked = None;
+                match reply.trim().parse().ok().and_then(column_from_label) {
+                    Some(col) if board.valid_moves().contains(&col) => Some(col),
+                    // Not a move, show the peer the position again for another answer
+                    _ => {
+                        self.ask(board);
+                        None
+                    }
+                }
+            }
+            Ok(None)
+                if self
+                    .asked
+                    .is_some_and(|asked| asked.elapsed() < MOVE_TIMEOUT) =>
+            {
+                None
+            }
+            // Closed, broken or silent for too long
+            _ => {
+                self.connection = None;
+                self.asked = None;
+                None
+            }
+        }
+    }
+
+    fn get_type(&self) -> String {
+        format!("Network ({})", self.peer)
+    }
+
+    fn awaiting_move(&self) -> bool {
+        self.connection.is_some() && self.asked.is_some()
+    }
+
+    fn game_over(&mut self, board: &Game) {
+        self.asked = None;
+        self.send(board);
+    }
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    fn learn(&mut self, _board: &Game, _player: Player) {
+        // No learning for network agent, the remote player does their own
+    }
+}
+
+// One game per line, so a reader can split on newlines
+fn send_game(stream: &mut TcpStream, board: &Game) -> io::Result<()> {
+    let mut line = serde_json::to_vec(board)?;
+    line.push(b'\n');
+    stream.write_all(&line)
+}
+
+/// Wait for a player to connect on the port, for hosting a game with `--serve`
+pub fn wait_for_player(port: u16) -> io::Result<TcpStream> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!(
+        "Waiting for a player to connect on port {} (connect4_ratatui --connect <host>:{})",
+        port, port
+    );
+    let (stream, addr) = listener.accept()?;
+    println!("{} connected", addr);
+    Ok(stream)
+}
+
+/// Play the hosted game from this terminal: print every position sent by the host and answer
+/// with a column typed on stdin. Runs until the host closes the connection
+pub fn play_remote(addr: impl ToSocketAddrs) -> io::Result<()> {
+    let mut stream = TcpStream::connect(addr)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    println!("Connected, waiting for the host to move");
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            println!("The host closed the connection");
+            return Ok(());
+        }
+        let board = Game::from_json(&line)?;
+        print_board(&board);
+
+        match board.state() {
+            GameState::Won(player) => {
+                println!("{} wins, waiting for the next game", player);
+                continue;
+            }
+            GameState::Draw => {
+                println!("Draw, waiting for the next game");
+                continue;
+            }
+            GameState::InProgress => {}
+        }
+
+        let col = loop {
+            print!(
+                "Your move as {}, column 1-{}: ",
+                board.current_player(),
+                board.config().cols
+            );
+            io::stdout().flush()?;
+            let mut answer = String::new();
+            if stdin.read_line(&mut answer)? == 0 {
+                return Ok(());
+            }
+            match answer.trim().parse().ok().and_then(column_from_label) {
+                Some(col) if board.valid_moves().contains(&col) => break col,
+                _ => println!("Not a playable column"),
+            }
+        };
+        writeln!(stream, "{}", column_label(col))?;
+    }
+}
+
+// Column numbers above one line per row, Y and R for the pieces and # for obstacles
+fn print_board(board: &Game) {
+    let config = board.config();
+    let header: Vec<String> = (0..config.cols)
+        .map(|col| format!("{:>2}", column_label(col)))
+        .collect();
+    println!("\n{}", header.join(""));
+    for row in 0..config.rows {
+        let line: String = (0..config.cols)
+            .map(|col| match board.get_cell(row, col) {
+                Some(Player::Yellow) => " Y",
+                Some(Player::Red) => " R",
+                None if board.is_blocked(row, col) => " #",
+                None => " .",
+            })
+            .collect();
+        println!("{}", line);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::KeyCode;
+
+    use crate::agent::HumanAgent;
+
+    use super::*;
+
+    // Ask the agent until it has a move or gives up waiting
+    fn wait_for_move(agent: &mut NetworkAgent, board: &Game) -> Option<usize> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let action = agent.get_action(board, None);
+            if action.is_some() || !agent.awaiting_move() || Instant::now() > deadline {
+                return action;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    // Ask the agent, which has no move yet, until it has sent the peer a position
+    fn wait_for_position(agent: &mut NetworkAgent, board: &Game, peer: &TcpStream) {
+        peer.set_read_timeout(Some(Duration::from_millis(5)))
+            .unwrap();
+        while peer.peek(&mut [0]).is_err() {
+            assert_eq!(agent.get_action(board, None), None);
+        }
+        peer.set_read_timeout(None).unwrap();
+    }
+
+    #[test]
+    fn a_reply_that_is_no_move_asks_the_peer_again() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (host, _) = listener.accept().unwrap();
+        let mut agent = NetworkAgent::new(Some(host));
+        let mut peer_lines = BufReader::new(peer.try_clone().unwrap()).lines();
+        let mut peer = peer;
+        let board = Game::new();
+
+        // Nothing sent yet, the agent waits without blocking
+        assert_eq!(agent.get_action(&board, None), None);
+        assert!(agent.awaiting_move());
+        assert!(Game::from_json(&peer_lines.next().unwrap().unwrap()).is_ok());
+
+        writeln!(peer, "banana").unwrap();
+        wait_for_position(&mut agent, &board, &peer);
+        assert!(agent.awaiting_move());
+        let board_again = Game::from_json(&peer_lines.next().unwrap().unwrap()).unwrap();
+        assert_eq!(board_again, board);
+
+        writeln!(peer, "4").unwrap();
+        assert_eq!(wait_for_move(&mut agent, &board), Some(3));
+
+        // A closed connection ends the wait
+        assert_eq!(agent.get_action(&board, None), None);
+        drop(peer_lines);
+        drop(peer);
+        assert_eq!(wait_for_move(&mut agent, &board), None);
+        assert!(!agent.awaiting_move());
+    }
+
+    #[test]
+    fn notation_keys_and_the_remote_protocol_all_count_columns_from_1() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let peer = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (host, _) = listener.accept().unwrap();
+        let mut agent = NetworkAgent::new(Some(host));
+        let mut peer_lines = BufReader::new(peer.try_clone().unwrap()).lines();
+        let mut peer = peer;
+        let board = Game::new();
+
+        for label in 1..=board.config().cols {
+            let col = label - 1;
+            assert_eq!(column_label(col), label);
+            assert_eq!(column_from_label(label), Some(col));
+
+            // Move notation drops into the labelled column
+            let game = Game::from_notation(*board.config(), &label.to_string()).unwrap();
+            assert_eq!(game.landing_row(col), Some(board.config().rows - 2));
+
+            // So does the key a human presses
+            let digit = char::from_digit(label as u32, 10).unwrap();
+            let key = Event::Key(KeyCode::Char(digit).into());
+            assert_eq!(HumanAgent.get_action(&board, Some(key)), Some(col));
+
+            // And the reply of the remote player
+            assert_eq!(agent.get_action(&board, None), None);
+            peer_lines.next().unwrap().unwrap();
+            writeln!(peer, "{}", label).unwrap();
+            assert_eq!(wait_for_move(&mut agent, &board), Some(col));
+        }
+        assert_eq!(column_from_label(0), None);
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crossterm::event::Event;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    agent::Agent,
+    game::{Game, GameConfig, GameState, Player},
+    rl_agent::RLAgent,
+};
+
+/// Best move per state, stripped from a Q-table
+#[derive(Serialize, Deserialize)]
+struct PolicyTable {
+    encoding_version: u32,
+    policy: HashMap<String, usize>,
+}
+
+/// Agent playing straight from an exported policy, without Q-values, learning or exploration.
+/// Unknown states fall back to the column nearest the center
+pub struct PolicyAgent {
+    policy: HashMap<String, usize>,
+    agent_color: Player,
+    board_config: GameConfig,
+    // Obstacles on the boards played, which have a policy of their own
+    obstacles: usize,
+    // Why the policy couldn't be loaded, until the app shows it
+    warning: Option<String>,
+}
+
+impl PolicyAgent {
+    pub fn new(agent_color: Player, board_config: GameConfig) -> Self {
+        let mut agent = PolicyAgent {
+            policy: HashMap::new(),
+            agent_color,
+            board_config,
+            obstacles: 0,
+            warning: None,
+        };
+        agent.load();
+        agent
+    }
+
+    // Read the policy for the agent's config and obstacles, an empty one if there is none
+    fn load(&mut self) {
+        (self.policy, self.warning) = match Self::load_policy(self.board_config, self.obstacles) {
+            Ok(policy) => (policy, None),
+            Err(e) => (
+                HashMap::new(),
+                Some(format!("Failed to load policy, playing the center: {}", e)),
+            ),
+        };
+    }
+
+    /// Write the policy of the saved Q-table for the config next to it.
+    /// Returns the number of states exported
+    pub fn export(board_config: GameConfig) -> io::Result<usize> {
+        Self::write_policy(
+            RLAgent::policy(board_config)?,
+            &Self::save_path(&board_config, 0),
+        )
+    }
+
+    // Write the policy to the file, returning the number of states
+    fn write_policy(policy: HashMap<String, usize>, path: &Path) -> io::Result<usize> {
+        let table = PolicyTable {
+            encoding_version: RLAgent::ENCODING_VERSION,
+            policy,
+        };
+        fs::write(path, serde_json::to_vec(&table)?)?;
+        Ok(table.policy.len())
+    }
+
+    // Stored next to the Q-tables, see RLAgent::save_path
+    fn save_path(config: &GameConfig, obstacles: usize) -> PathBuf {
+        [
+            "connect4_learn",
+            &format!("policy_{}.json", RLAgent::table_name(config, obstacles)),
+        ]
+        .iter()
+        .collect()
+    }
+
+    fn load_policy(
+        board_config: GameConfig,
+        obstacles: usize,
+    ) -> io::Result<HashMap<String, usize>> {
+        Self::read_policy(&Self::save_path(&board_config, obstacles))
+    }
+
+    fn read_policy(path: &Path) -> io::Result<HashMap<String, usize>> {
+        let table: PolicyTable = serde_json::from_slice(&fs::read(path)?)?;
+        if table.encoding_version != RLAgent::ENCODING_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} uses state encoding v{} but this version reads v{}, export it again",
+                    path.display(),
+                    table.encoding_version,
+                    RLAgent::ENCODING_VERSION
+                ),
+            ));
+        }
+        Ok(table.policy)
+    }
+}
+
+impl Agent for PolicyAgent {
+    fn get_action(&mut self, board: &Game, _event: Option<Event>) -> Option<usize> {
+        let valid_moves = board.valid_moves();
+
+        // Same as the RL agent, take an immediate win before looking at the table
+        let winning_move = valid_moves.iter().copied().find(|&col| {
+            board
+                .with_move(col)
+                .is_some_and(|next| matches!(next.state(), GameState::Won(_)))
+        });
+        if winning_move.is_some() {
+            return winning_move;
+        }
+
+        let (state, frame) = RLAgent::canonical_state(board, self.agent_color);
+        let col = self
+            .policy
+            .get(&state)
+            .map(|&stored| frame.board_column(stored, board.config().cols));
+        match col {
+            Some(col) if valid_moves.contains(&col) => Some(col),
+            _ => {
+                let center = board.config().cols as i32 / 2;
+                valid_moves
+                    .into_iter()
+                    .min_by_key(|&col| (col as i32 - center).abs())
+            }
+        }
+    }
+
+    fn get_type(&self) -> String {
+        "RL Policy".to_string()
+    }
+
+    fn take_warning(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+
+    fn set_obstacles(&mut self, count: usize) {
+        if count != self.obstacles {
+            self.obstacles = count;
+            self.load();
+        }
+    }
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    fn learn(&mut self, _board: &Game, _player: Player) {
+        // The policy is fixed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+    use super::*;
+
+    #[test]
+    fn an_exported_policy_plays_the_tables_best_moves() {
+        // A table of made up values for the positions of some random games, seen by the
+        // side to move in each
+        let config = GameConfig::custom(5, 5, 4).unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut boards = Vec::new();
+        let mut q_table = HashMap::new();
+        for _ in 0..20 {
+            let mut board = Game::with_config(config);
+            while *board.state() == GameState::InProgress {
+                let (state, _) = RLAgent::canonical_state(&board, board.current_player());
+                let values: Vec<f64> = (0..config.cols)
+                    .map(|_| rng.random_range(-1.0..1.0))
+                    .collect();
+                q_table.entry(state).or_insert(values);
+                boards.push(board.clone());
+                board.place(*board.valid_moves().choose(&mut rng).unwrap());
+            }
+        }
+        let dir = std::env::temp_dir();
+        let table_path = dir.join(format!("policy_source_{}.json", std::process::id()));
+        let policy_path = dir.join(format!("policy_{}.json", std::process::id()));
+        fs::write(
+            &table_path,
+            serde_json::to_vec(&serde_json::json!({
+                "encoding_version": RLAgent::ENCODING_VERSION,
+                "q_table": q_table,
+            }))
+            .unwrap(),
+        )
+        .unwrap();
+
+        let policy = RLAgent::policy_from(&table_path, config).unwrap();
+        let exported = PolicyAgent::write_policy(policy, &policy_path).unwrap();
+        assert_eq!(exported, q_table.len());
+        let policy = PolicyAgent::read_policy(&policy_path).unwrap();
+        let mut agents = [Player::Yellow, Player::Red].map(|player| {
+            let source = RLAgent::from_table_file(&table_path, player, config).unwrap();
+            let agent = PolicyAgent {
+                policy: policy.clone(),
+                agent_color: player,
+                board_config: config,
+                obstacles: 0,
+                warning: None,
+            };
+            (source, agent)
+        });
+        for board in boards {
+            let (source, agent) = match board.current_player() {
+                Player::Yellow => &mut agents[0],
+                Player::Red => &mut agents[1],
+            };
+            assert_eq!(
+                agent.get_action(&board, None),
+                source.get_action(&board, None),
+                "{}",
+                board.to_key()
+            );
+        }
+        fs::remove_file(table_path).unwrap();
+        fs::remove_file(policy_path).unwrap();
+    }
+}
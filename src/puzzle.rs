@@ -0,0 +1,97 @@
+use crate::{
+    benchmark::{Expectation, tactical_positions},
+    game::Game,
+};
+
+/// Puzzle mode: the tactical benchmark positions, played by a human who has to find the answer.
+/// Each puzzle is scored on the first move made in it
+pub struct Puzzles {
+    positions: Vec<(Game, &'static str, Expectation)>,
+    index: usize,
+    /// Whether the move made in the current puzzle was right, None until one is made
+    pub outcome: Option<bool>,
+    pub solved: usize,
+    pub attempted: usize,
+}
+
+impl Puzzles {
+    pub fn new() -> Self {
+        Puzzles {
+            positions: tactical_positions(),
+            index: 0,
+            outcome: None,
+            solved: 0,
+            attempted: 0,
+        }
+    }
+
+    /// Position of the current puzzle, its motif is not given away
+    pub fn board(&self) -> Game {
+        self.positions[self.index].0.clone()
+    }
+
+    /// Short description of the current puzzle, e.g. "Puzzle 2/6"
+    pub fn title(&self) -> String {
+        format!("Puzzle {}/{}", self.index + 1, self.positions.len())
+    }
+
+    /// Motif and answer of the current puzzle, for after the move is made
+    pub fn solution(&self) -> String {
+        let (_, motif, expectation) = &self.positions[self.index];
+        format!("{}: {}", motif, expectation)
+    }
+
+    /// Move to the next puzzle, wrapping around
+    pub fn next(&mut self) {
+        self.index = (self.index + 1) % self.positions.len();
+        self.outcome = None;
+    }
+
+    /// Move to the previous puzzle, wrapping around
+    pub fn previous(&mut self) {
+        self.index = (self.index + self.positions.len() - 1) % self.positions.len();
+        self.outcome = None;
+    }
+
+    /// Check a move played in the current puzzle's position against its answer. Only the first
+    /// move counts towards the score, later ones return the recorded outcome
+    pub fn submit(&mut self, board: &Game, col: usize) -> bool {
+        if let Some(outcome) = self.outcome {
+            return outcome;
+        }
+        let (_, _, expectation) = &self.positions[self.index];
+        let correct = expectation.met_by(board, Some(col));
+        self.outcome = Some(correct);
+        self.attempted += 1;
+        if correct {
+            self.solved += 1;
+        }
+        correct
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_first_move_in_a_puzzle_scores_a_success_or_a_failure() {
+        let mut puzzles = Puzzles::new();
+        // Win in one on the bottom row, the fourth column completes it
+        let board = puzzles.board();
+        assert!(puzzles.submit(&board, 3));
+        assert_eq!(puzzles.outcome, Some(true));
+        // Later moves don't change the score
+        assert!(puzzles.submit(&board, 6));
+        assert_eq!((puzzles.solved, puzzles.attempted), (1, 1));
+
+        // Win in one up the first column, anything else misses it
+        puzzles.next();
+        assert_eq!(puzzles.outcome, None);
+        let board = puzzles.board();
+        assert!(!puzzles.submit(&board, 1));
+        assert_eq!(puzzles.outcome, Some(false));
+        assert!(!puzzles.submit(&board, 0));
+        assert_eq!((puzzles.solved, puzzles.attempted), (1, 2));
+    }
+}
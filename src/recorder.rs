@@ -0,0 +1,100 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crossterm::{
+    queue,
+    style::{
+        Attribute, Color as CColor, Print, ResetColor, SetAttribute, SetBackgroundColor,
+        SetForegroundColor,
+    },
+};
+use ratatui::{buffer::Buffer, style::Modifier};
+
+/// Writes every drawn frame to a numbered text file, keeping colors as ANSI escape codes.
+/// The files can be played back with `cat` or converted to a GIF with external tools.
+pub struct FrameRecorder {
+    dir: PathBuf,
+    frame_count: usize,
+}
+
+impl FrameRecorder {
+    pub fn new(dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(FrameRecorder {
+            dir,
+            frame_count: 0,
+        })
+    }
+
+    /// Serialize the visible cells of a frame to the next frame file
+    pub fn record(&mut self, buffer: &Buffer) -> io::Result<()> {
+        let mut out = Vec::new();
+        let area = buffer.area;
+
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                let Some(cell) = buffer.cell((x, y)) else {
+                    continue;
+                };
+                // Styling hints for each cell, reset again after
+                queue!(
+                    out,
+                    SetForegroundColor(CColor::from(cell.fg)),
+                    SetBackgroundColor(CColor::from(cell.bg))
+                )?;
+                if cell.modifier.contains(Modifier::BOLD) {
+                    queue!(out, SetAttribute(Attribute::Bold))?;
+                }
+                queue!(out, Print(cell.symbol()), SetAttribute(Attribute::Reset))?;
+            }
+            queue!(out, ResetColor, Print("\n"))?;
+        }
+
+        self.frame_count += 1;
+        let path = self.dir.join(format!("frame_{:05}.txt", self.frame_count));
+        fs::File::create(path)?.write_all(&out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        app::App,
+        game::{GameState, Player},
+    };
+
+    use super::*;
+
+    #[test]
+    fn a_scripted_game_writes_one_file_per_frame() {
+        let dir = std::env::temp_dir().join(format!("frames_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut recorder = FrameRecorder::new(dir.clone()).unwrap();
+        let mut app = App::new().unwrap();
+
+        // The empty board, then one frame after each move of a seven move game
+        recorder
+            .record(&crate::render_to_buffer(&mut app, 80, 30).unwrap())
+            .unwrap();
+        for col in [0, 1, 0, 1, 0, 1, 0] {
+            app.game.place(col);
+            recorder
+                .record(&crate::render_to_buffer(&mut app, 80, 30).unwrap())
+                .unwrap();
+        }
+        assert_eq!(*app.game.state(), GameState::Won(Player::Yellow));
+
+        let mut frames: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        frames.sort();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(frames.len(), 8);
+        assert_eq!(frames.first().unwrap(), "frame_00001.txt");
+        assert_eq!(frames.last().unwrap(), "frame_00008.txt");
+    }
+}
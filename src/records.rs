@@ -0,0 +1,128 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// All-time bests of human players against the AI, kept across sessions.
+/// Only games with exactly one human count, AI against AI has nobody to reward
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Records {
+    /// Most human wins in a single session
+    pub most_session_wins: usize,
+    /// Longest run of human wins in a row, keyed by the opposing agent type
+    pub longest_streaks: BTreeMap<String, usize>,
+    /// Fewest moves in a human win, counting both players' moves
+    pub fastest_win: Option<usize>,
+
+    #[serde(skip)]
+    session_wins: usize,
+    #[serde(skip)]
+    current_streaks: BTreeMap<String, usize>,
+}
+
+impl Records {
+    /// Records file next to the Q-tables
+    pub fn path() -> PathBuf {
+        ["connect4_learn", "records.json"].iter().collect()
+    }
+
+    /// Records saved at the path, empty ones if the file is missing or unreadable
+    pub fn load(path: &Path) -> Self {
+        fs::read(path)
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    /// Start the session's win count and streaks over, the all-time bests are kept
+    pub fn new_session(&mut self) {
+        self.session_wins = 0;
+        self.current_streaks.clear();
+    }
+
+    /// Count a finished human game against the given opponent, a draw or loss ends the streak.
+    /// Returns whether any record was beaten
+    pub fn record_game(&mut self, opponent: &str, human_won: bool, moves: usize) -> bool {
+        if !human_won {
+            self.current_streaks.remove(opponent);
+            return false;
+        }
+
+        let mut improved = false;
+
+        self.session_wins += 1;
+        if self.session_wins > self.most_session_wins {
+            self.most_session_wins = self.session_wins;
+            improved = true;
+        }
+
+        let streak = self
+            .current_streaks
+            .entry(opponent.to_string())
+            .or_default();
+        *streak += 1;
+        let longest = self
+            .longest_streaks
+            .entry(opponent.to_string())
+            .or_default();
+        if *streak > *longest {
+            *longest = *streak;
+            improved = true;
+        }
+
+        if self.fastest_win.is_none_or(|fastest| moves < fastest) {
+            self.fastest_win = Some(moves);
+            improved = true;
+        }
+
+        improved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_faster_win_is_kept_and_saved_but_a_slower_one_is_not() {
+        let path = std::env::temp_dir().join(format!("records_{}.json", std::process::id()));
+        let mut records = Records::default();
+        assert!(records.record_game("Minimax (3)", true, 20));
+        assert!(records.record_game("Minimax (3)", true, 14));
+        records.save(&path).unwrap();
+
+        let mut loaded = Records::load(&path);
+        assert_eq!(loaded.fastest_win, Some(14));
+        assert_eq!(loaded.longest_streaks["Minimax (3)"], 2);
+        // A fresh session has to win more than the saved best to beat it
+        assert!(!loaded.record_game("Minimax (3)", true, 30));
+        assert_eq!(loaded.fastest_win, Some(14));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn a_new_session_starts_the_session_wins_and_streaks_over() {
+        let mut records = Records::default();
+        for _ in 0..3 {
+            records.record_game("Greedy", true, 10);
+        }
+        records.new_session();
+        assert!(!records.record_game("Greedy", true, 10));
+        assert_eq!(records.session_wins, 1);
+        assert_eq!(records.current_streaks["Greedy"], 1);
+        assert_eq!(
+            (records.most_session_wins, records.longest_streaks["Greedy"]),
+            (3, 3)
+        );
+    }
+}
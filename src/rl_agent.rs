@@ -1,35 +1,296 @@
-use std::{collections::HashMap, fs, io, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard, mpsc},
+    thread,
+};
 
+use bincode::Options;
 use crossterm::event::Event;
-use rand::Rng;
+use rand::{Rng, distr::Distribution, distr::weighted::WeightedIndex};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    agent::Agent,
-    game::{Game, GameConfig, GameState, Player},
+    agent::{Agent, Agents},
+    game::{Game, GameConfig, GameState, Player, column_label},
+    solver::Solver,
 };
 
+/// On-disk format of a Q-table, chosen by file extension
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum QTableFormat {
+    // Human-inspectable, the default
+    #[default]
+    Json,
+    // bincode encoding, much faster to parse than JSON for large tables
+    Binary,
+}
+
+impl QTableFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bin") => QTableFormat::Binary,
+            _ => QTableFormat::Json,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            QTableFormat::Json => "json",
+            QTableFormat::Binary => "bin",
+        }
+    }
+}
+
+/// How the agent picks moves when it isn't playing the best known one
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum Exploration {
+    // Uniformly random move with probability epsilon, otherwise the best Q-value
+    #[default]
+    EpsilonGreedy,
+    // Sample moves weighted by exp(Q / temperature), higher temperatures are more random
+    Softmax {
+        temperature: f64,
+    },
+}
+
+type QTable = HashMap<String, Vec<f64>>;
+// One table per config, shared by every agent playing on it and by the session cache
+type SharedTable = Arc<RwLock<QTable>>;
+
+// JSON layout of a saved table
+#[derive(Serialize)]
+struct TableFile<'a> {
+    encoding_version: u32,
+    q_table: &'a QTable,
+}
+
+#[derive(Deserialize)]
+struct LoadedTableFile {
+    // Tables saved before versioning was added use the first encoding
+    #[serde(default = "RLAgent::first_encoding_version")]
+    encoding_version: u32,
+    #[serde(default)]
+    q_table: QTable,
+}
+
+/// How a board relates to the canonical state its values are stored under. A board and its
+/// mirror image share one state, so mirrored openings train the same values
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum StateFrame {
+    AsIs,
+    // Stored as the mirror image, columns are flipped
+    Mirrored,
+    // The board is its own mirror image, both halves are stored in the left one
+    Symmetric,
+}
+
+impl StateFrame {
+    /// Index of the stored values for a column of the board
+    pub fn stored_column(self, col: usize, cols: usize) -> usize {
+        match self {
+            StateFrame::AsIs => col,
+            StateFrame::Mirrored => cols - 1 - col,
+            StateFrame::Symmetric => col.min(cols - 1 - col),
+        }
+    }
+
+    /// Column of the board for an index of the stored values
+    pub fn board_column(self, stored: usize, cols: usize) -> usize {
+        match self {
+            StateFrame::Mirrored => cols - 1 - stored,
+            StateFrame::AsIs | StateFrame::Symmetric => stored,
+        }
+    }
+}
+
+// Board config and obstacle count a table is learned for, each with its own file
+type TableKey = (GameConfig, usize);
+
+// Table shared by the agents on a key, with where they write it
+#[derive(Clone)]
+struct CachedTable {
+    format: QTableFormat,
+    q_table: SharedTable,
+    // None once a write failed, e.g. on a read-only filesystem. The table is then only kept
+    // here for the rest of the session instead of failing on every game
+    save_dir: Option<PathBuf>,
+}
+
+// Q-tables already loaded or saved this session, keyed like their files.
+// Switching configs back and forth reuses these instead of reading the file again, and
+// agents hold the same table rather than a copy of it
+static TABLE_CACHE: LazyLock<Mutex<HashMap<TableKey, CachedTable>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
 /// RL agent implementation using Q-learning algorithm with history
-#[derive(Serialize, Deserialize)]
 pub struct RLAgent {
+    // Version of the board_to_state encoding the table's keys use
+    encoding_version: u32,
     // Q-table mapping board state to action values
-    #[serde(skip_serializing_if = "HashMap::is_empty")]
-    q_table: HashMap<String, Vec<f64>>,
+    q_table: SharedTable,
 
-    #[serde(skip)]
     epsilon: f64,
-    #[serde(skip)]
+    exploration: Exploration,
     learning: bool,
-    #[serde(skip)]
+    // Discount of the next state's value when learning, 0 only credits the final result
+    gamma: f64,
     turn: usize,
-    #[serde(skip)]
     agent_color: Player,
     // Game history for learning from sequences
-    #[serde(skip)]
     move_history: Vec<(String, usize)>,
+    // Most recent moves kept in move_history, older ones are never learned from
+    max_history: usize,
 
-    #[serde(skip)]
     board_config: GameConfig,
+    // Obstacles on the boards played, games with them learn into a table of their own
+    obstacles: usize,
+    // Format the table was loaded in, saves keep using it
+    format: QTableFormat,
+    // Directory the table is written to, None keeps it in memory only
+    save_dir: Option<PathBuf>,
+    // Learned games between writes of the table to disk, 0 leaves writing to the caller
+    save_interval: usize,
+    // Games learned since the table was last written
+    unsaved_games: usize,
+    // Load or save failure waiting to be shown by the app
+    warning: Option<String>,
+}
+
+/// Results of self-play training games since the last report
+#[derive(Debug, Default)]
+pub struct TrainingReport {
+    /// Games played in total so far
+    pub games: usize,
+    pub yellow_wins: usize,
+    pub red_wins: usize,
+    pub draws: usize,
+    /// States in the shared Q-table when the latest game of the report finished
+    pub states: usize,
+    /// Share of the evaluation games the table won against the baseline, in percent, and
+    /// whether that beat every earlier evaluation so the table was kept as the best one
+    pub evaluation: Option<(f64, bool)>,
+    /// Games against each entry of the league since the last report, empty for self-play
+    pub league_games: Vec<usize>,
+}
+
+/// Opponent a league run can draw for a training game
+#[derive(Debug, Clone, PartialEq)]
+pub enum LeagueOpponent {
+    Agent(Agents),
+    /// The learner's own table as it was at the last report
+    PastSelf,
+}
+
+impl LeagueOpponent {
+    // Agent for a game as the player, the past self playing greedily on its table
+    fn agent(
+        &self,
+        player: Player,
+        board_config: GameConfig,
+        past_self: &SharedTable,
+    ) -> Box<dyn Agent> {
+        match self {
+            LeagueOpponent::Agent(agent) => agent.clone().into_agent(player, board_config),
+            LeagueOpponent::PastSelf => {
+                let mut past = RLAgent::untrained(board_config);
+                past.q_table = Arc::clone(past_self);
+                past.agent_color = player;
+                Box::new(past)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LeagueOpponent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LeagueOpponent::Agent(agent) => write!(f, "{}", agent),
+            LeagueOpponent::PastSelf => write!(f, "Past self"),
+        }
+    }
+}
+
+/// How a self-play training run plays its games
+#[derive(Debug, Clone)]
+pub struct TrainingPlan {
+    pub games: usize,
+    /// Threads the games are spread over
+    pub threads: usize,
+    /// Games between reports, and between evaluations against the baseline
+    pub report_every: usize,
+    /// Agent the table is measured against at every report, None to skip the evaluation.
+    /// The table with the best win rate is saved next to the trained one, so a run that gets
+    /// worse later on doesn't lose its best checkpoint
+    pub baseline: Option<Agents>,
+    /// Games per evaluation, half of them as each color
+    pub evaluation_games: usize,
+    /// Opponents with their sampling weights. Each game the learner takes a random side
+    /// against one drawn from the pool, and only it learns. Empty for plain self-play
+    pub league: Vec<(LeagueOpponent, u32)>,
+}
+
+impl TrainingPlan {
+    /// Agent the table is evaluated against unless another one is given
+    pub const DEFAULT_BASELINE: Agents = Agents::Minimax(3);
+    pub const DEFAULT_EVALUATION_GAMES: usize = 20;
+
+    /// League used when none is given: the simple agents, shallow searches and the
+    /// learner's past self, which gets the most games
+    pub fn default_league() -> Vec<(LeagueOpponent, u32)> {
+        vec![
+            (LeagueOpponent::Agent(Agents::Random), 1),
+            (LeagueOpponent::Agent(Agents::Greedy), 1),
+            (LeagueOpponent::Agent(Agents::Minimax(1)), 1),
+            (LeagueOpponent::Agent(Agents::Minimax(3)), 1),
+            (LeagueOpponent::PastSelf, 2),
+        ]
+    }
+}
+
+// Best evaluated table of a training run
+struct BestTable {
+    // None when the run's table is only kept in memory
+    path: Option<PathBuf>,
+    best: Option<(f64, SharedTable)>,
+}
+
+impl BestTable {
+    fn new(path: Option<PathBuf>) -> Self {
+        BestTable { path, best: None }
+    }
+
+    // Keep the agent's table if it beat every earlier one, writing it out when there's a
+    // path. Returns whether it was kept
+    fn offer(&mut self, win_rate: f64, agent: &RLAgent) -> io::Result<bool> {
+        if self
+            .best
+            .as_ref()
+            .is_some_and(|(best, _)| win_rate <= *best)
+        {
+            return Ok(false);
+        }
+        self.best = Some((win_rate, Arc::clone(&agent.q_table)));
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            agent.write_table(path)?;
+        }
+        Ok(true)
+    }
+}
+
+impl TrainingReport {
+    fn window(&self) -> usize {
+        self.yellow_wins + self.red_wins + self.draws
+    }
+
+    /// Share of the games since the last report with the given result, in percent
+    pub fn rate(&self, count: usize) -> f64 {
+        count as f64 * 100.0 / self.window().max(1) as f64
+    }
 }
 
 impl RLAgent {
@@ -38,47 +299,491 @@ impl RLAgent {
     const LOSS_REWARD: f64 = -10.0; // Doubled loss penalty
     const DRAW_REWARD: f64 = 1.0;
     const DURATION_REWARD: f64 = 0.02;
-    const MAX_HISTORY: usize = 3; // Number of previous moves to consider
+    /// Discount of future values used by the agents in the menu
+    pub const DEFAULT_GAMMA: f64 = 0.9;
+    // Exploration of both sides during self-play training
+    const TRAINING_EPSILON: f64 = 0.2;
+    // Learned games between table writes, the rest are written when the agent is dropped
+    const SAVE_INTERVAL: usize = 10;
+    // Directory tables are saved in and loaded from
+    const SAVE_DIR: &str = "connect4_learn";
+    /// Bump whenever board_to_state changes, so tables keyed by the old encoding are refused
+    /// instead of being silently misread. Version 2 folds mirror images into one state
+    pub const ENCODING_VERSION: u32 = 2;
+
+    fn first_encoding_version() -> u32 {
+        1
+    }
+
+    /// History long enough to learn from every move of a game on the board, the default.
+    /// Each entry is a state key of about one byte per cell, so a full history costs up to
+    /// the number of cells squared in bytes per game rather than anything table sized
+    pub fn full_history(board_config: &GameConfig) -> usize {
+        board_config.rows * board_config.cols
+    }
 
     pub fn new(
         epsilon: f64,
         learning: bool,
+        gamma: f64,
+        max_history: usize,
         agent_color: Player,
         board_config: GameConfig,
     ) -> Self {
         // Create a new agent
         let mut agent = RLAgent {
-            q_table: HashMap::new(),
+            encoding_version: Self::ENCODING_VERSION,
+            q_table: SharedTable::default(),
             epsilon,
+            exploration: Exploration::default(),
             learning,
+            gamma,
             agent_color,
             turn: 0,
             move_history: Vec::new(),
+            max_history,
             board_config,
+            obstacles: 0,
+            format: QTableFormat::default(),
+            save_dir: Some(PathBuf::from(Self::SAVE_DIR)),
+            save_interval: Self::SAVE_INTERVAL,
+            unsaved_games: 0,
+            warning: None,
+        };
+        agent.attach_table();
+        agent
+    }
+
+    // Take up the table for the agent's config and obstacles: the one shared earlier in the
+    // session, else the saved one, else an empty one. An agent or cached table kept in
+    // memory stays that way
+    fn attach_table(&mut self) {
+        self.q_table = SharedTable::default();
+        self.format = QTableFormat::default();
+        self.warning = None;
+
+        let key = (self.board_config, self.obstacles);
+        let cached = TABLE_CACHE
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(&key).cloned());
+        if let Some(cached) = cached {
+            self.format = cached.format;
+            self.q_table = cached.q_table;
+            self.save_dir = self.save_dir.take().and(cached.save_dir);
+            return;
+        }
+
+        // Try to load existing Q-table if available, preferring a binary table
+        let existing = [QTableFormat::Binary, QTableFormat::Json]
+            .into_iter()
+            .find(|&format| Self::save_path(&self.board_config, self.obstacles, format).exists());
+        if let Some(format) = existing {
+            self.format = format;
+            if let Err(e) = self.load_q_table() {
+                self.warning = Some(format!("Failed to load Q-table, starting empty: {}", e));
+            }
+        }
+        self.update_cache();
+    }
+
+    /// Agent choosing moves by softmax over the Q-values instead of epsilon-greedy
+    pub fn with_softmax(
+        temperature: f64,
+        learning: bool,
+        agent_color: Player,
+        board_config: GameConfig,
+    ) -> Self {
+        let mut agent = Self::new(
+            0.0,
+            learning,
+            Self::DEFAULT_GAMMA,
+            Self::full_history(&board_config),
+            agent_color,
+            board_config,
+        );
+        agent.exploration = Exploration::Softmax { temperature };
+        agent
+    }
+
+    /// Agent playing greedily on the table in the file, which stays in memory
+    #[cfg(test)]
+    pub fn from_table_file(
+        path: &Path,
+        agent_color: Player,
+        board_config: GameConfig,
+    ) -> io::Result<Self> {
+        let mut agent = Self::untrained(board_config);
+        agent.agent_color = agent_color;
+        agent.save_dir = None;
+        *agent.table_mut() = Self::read_table(path)?;
+        Ok(agent)
+    }
+
+    // Agent with an empty table which neither explores nor learns
+    fn untrained(board_config: GameConfig) -> Self {
+        RLAgent {
+            encoding_version: Self::ENCODING_VERSION,
+            q_table: SharedTable::default(),
+            epsilon: 0.0,
+            exploration: Exploration::default(),
+            learning: false,
+            gamma: 0.0,
+            agent_color: Player::default(),
+            turn: 0,
+            move_history: Vec::new(),
+            max_history: 0,
+            board_config,
+            obstacles: 0,
+            format: QTableFormat::default(),
+            save_dir: Some(PathBuf::from(Self::SAVE_DIR)),
+            save_interval: 0,
+            unsaved_games: 0,
+            warning: None,
+        }
+    }
+
+    /// Train the saved Q-table of a config by playing learning agents against each other
+    /// without the ui, with games spread over the plan's threads. Every agent shares one
+    /// table, which works since states are encoded from the mover's perspective: moves take
+    /// the read lock for a lookup and each finished game takes the write lock once for its
+    /// updates. `report` is called every `report_every` games and after the last one, the
+    /// table is saved once at the end
+    pub fn train_self_play(
+        board_config: GameConfig,
+        plan: &TrainingPlan,
+        mut report: impl FnMut(&TrainingReport),
+    ) -> io::Result<()> {
+        let games = plan.games;
+        let mut owner = Self::training_agent(Player::Yellow, board_config);
+        // Training from scratch would overwrite the table on save
+        if let Some(warning) = owner.take_warning() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, warning));
+        }
+        let mut best = BestTable::new(owner.best_path());
+        let threads = plan.threads.clamp(1, games.max(1));
+        let league = match plan.league.as_slice() {
+            [] => None,
+            pool => Some(
+                WeightedIndex::new(pool.iter().map(|(_, weight)| *weight))
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?,
+            ),
         };
+        let fresh_league_games = || vec![0; plan.league.len()];
+        // Replaced at every report, games in between play against the same past self
+        let past_self = RwLock::new(Arc::new(RwLock::new(owner.table().clone())));
+        let (results, finished) = mpsc::channel();
+        thread::scope(|scope| {
+            for worker in 0..threads {
+                let worker_games = games / threads + usize::from(worker < games % threads);
+                let results = results.clone();
+                let (table, league, past_self) = (&owner.q_table, &league, &past_self);
+                scope.spawn(move || {
+                    let mut agents = [Player::Yellow, Player::Red].map(|player| {
+                        let mut agent = Self::training_agent(player, board_config);
+                        agent.q_table = Arc::clone(table);
+                        agent
+                    });
+                    let mut rng = rand::rng();
+                    for _ in 0..worker_games {
+                        let result = match league {
+                            Some(league) => {
+                                let index = league.sample(&mut rng);
+                                let player = if rng.random_bool(0.5) {
+                                    Player::Yellow
+                                } else {
+                                    Player::Red
+                                };
+                                let past_self =
+                                    past_self.read().unwrap_or_else(PoisonError::into_inner);
+                                let mut opponent = plan.league[index].0.agent(
+                                    player.opponent(),
+                                    board_config,
+                                    &past_self,
+                                );
+                                drop(past_self);
+                                let learner = &mut agents[0];
+                                (
+                                    learner.play_league_game(opponent.as_mut(), player),
+                                    Some(index),
+                                )
+                            }
+                            None => (Self::play_training_game(&mut agents), None),
+                        };
+                        // Counted here rather than when the report is made, by then the
+                        // other threads may have finished every game
+                        let states = agents[0].table().len();
+                        if results.send((result, states)).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(results);
+
+            let mut window = TrainingReport {
+                league_games: fresh_league_games(),
+                ..TrainingReport::default()
+            };
+            for (game, ((result, opponent), states)) in (1..).zip(finished) {
+                window.states = window.states.max(states);
+                if let Some(index) = opponent {
+                    window.league_games[index] += 1;
+                }
+                match result {
+                    GameState::Won(Player::Yellow) => window.yellow_wins += 1,
+                    GameState::Won(Player::Red) => window.red_wins += 1,
+                    _ => window.draws += 1,
+                }
+                if game % plan.report_every.max(1) == 0 || game == games {
+                    window.games = game;
+                    if let Some(baseline) = &plan.baseline {
+                        // Evaluated on a copy, the other threads keep learning meanwhile
+                        let mut snapshot = Self::untrained(board_config);
+                        *snapshot.table_mut() = owner.table().clone();
+                        let win_rate = snapshot.win_rate(baseline, plan.evaluation_games);
+                        window.evaluation = Some((win_rate, best.offer(win_rate, &snapshot)?));
+                    }
+                    if plan
+                        .league
+                        .iter()
+                        .any(|(opponent, _)| *opponent == LeagueOpponent::PastSelf)
+                    {
+                        *past_self.write().unwrap_or_else(PoisonError::into_inner) =
+                            Arc::new(RwLock::new(owner.table().clone()));
+                    }
+                    report(&window);
+                    window = TrainingReport {
+                        league_games: fresh_league_games(),
+                        ..TrainingReport::default()
+                    };
+                }
+            }
+            Ok::<_, io::Error>(())
+        })?;
+
+        owner.save_q_table()
+    }
 
-        // Try to load existing Q-table if available
-        if Self::save_path(&board_config).exists() {
-            if let Err(e) = agent.load_q_table() {
-                eprintln!("Failed to load Q-table: {}", e);
+    // Share of the games against the baseline the agent wins without exploring, in percent,
+    // playing Yellow in the first half and Red in the second
+    fn win_rate(&mut self, baseline: &Agents, games: usize) -> f64 {
+        let board_config = self.board_config;
+        let mut wins = 0;
+        for game in 0..games {
+            let player = if game < games / 2 {
+                Player::Yellow
+            } else {
+                Player::Red
+            };
+            self.agent_color = player;
+            let mut opponent = baseline.clone().into_agent(player.opponent(), board_config);
+            let board = match player {
+                Player::Yellow => play_game(board_config, self, opponent.as_mut()),
+                Player::Red => play_game(board_config, opponent.as_mut(), self),
+            };
+            if *board.state() == GameState::Won(player) {
+                wins += 1;
             }
         }
+        wins as f64 * 100.0 / games.max(1) as f64
+    }
 
+    // Learning agent for one side of the training games, saved by the trainer
+    fn training_agent(player: Player, board_config: GameConfig) -> Self {
+        let mut agent = Self::new(
+            Self::TRAINING_EPSILON,
+            true,
+            Self::DEFAULT_GAMMA,
+            Self::full_history(&board_config),
+            player,
+            board_config,
+        );
+        agent.save_interval = 0;
         agent
     }
 
+    // Play one game against a league opponent from the player's side, learning from the result
+    fn play_league_game(&mut self, opponent: &mut dyn Agent, player: Player) -> GameState {
+        let board_config = self.board_config;
+        self.agent_color = player;
+        let board = match player {
+            Player::Yellow => play_game(board_config, self, opponent),
+            Player::Red => play_game(board_config, opponent, self),
+        };
+        self.learn(&board, player);
+        *board.state()
+    }
+
+    // Play one game between the Yellow and Red agents, both learning from the result
+    fn play_training_game(agents: &mut [RLAgent; 2]) -> GameState {
+        let board_config = agents[0].board_config;
+        let [yellow, red] = agents;
+        let board = play_game(board_config, yellow, red);
+
+        for agent in agents.iter_mut() {
+            let player = agent.agent_color;
+            agent.learn(&board, player);
+        }
+        *board.state()
+    }
+
+    /// Convert a Q-table file to another format, formats are picked by file extension
+    /// (`.bin` for binary, anything else for JSON). Returns the number of states converted.
+    pub fn convert_q_table(from: &Path, to: &Path) -> io::Result<usize> {
+        let agent = Self::untrained(GameConfig::default());
+        *agent.table_mut() = Self::read_table(from)?;
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        agent.write_table(to)?;
+        Ok(agent.table().len())
+    }
+
+    /// Build an initial Q-table from the perfect-play solver and save it for the given config.
+    /// Every reachable position is enumerated, so this is only feasible on small boards.
+    /// Returns the number of states written.
+    pub fn bootstrap_q_table(board_config: GameConfig) -> io::Result<usize> {
+        let agent = Self::solved(board_config);
+        agent.save_q_table()?;
+        Ok(agent.table().len())
+    }
+
+    // Non-learning agent whose table holds the solver's value of every reachable position
+    fn solved(board_config: GameConfig) -> Self {
+        let mut agent = Self::untrained(board_config);
+        let mut solver = Solver::new();
+        let mut visited = HashSet::new();
+        let mut stack = vec![Game::with_config(board_config)];
+
+        while let Some(board) = stack.pop() {
+            // Encoding is relative to the side to move
+            agent.agent_color = board.current_player();
+            let (state, frame) = agent.board_to_state(&board);
+            if !visited.insert(state.clone()) {
+                continue;
+            }
+
+            // Targets: win = 1, draw = 0, loss = -1
+            let mut q_values = vec![0.0; board_config.cols];
+            for (col, value) in solver.move_values(&board) {
+                q_values[frame.stored_column(col, board_config.cols)] = value as f64;
+            }
+            agent.table_mut().insert(state, q_values);
+
+            for col in board.valid_moves() {
+                if let Some(next) = board.with_move(col)
+                    && *next.state() == GameState::InProgress
+                {
+                    stack.push(next);
+                }
+            }
+        }
+        agent
+    }
+
+    // Where training keeps the best evaluated table, beside the trained one
+    fn best_path(&self) -> Option<PathBuf> {
+        let path = self.table_path()?;
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        Some(path.with_file_name(format!("{}_best.{}", stem, self.format.extension())))
+    }
+
+    // Where the agent writes its table, None when it's kept in memory
+    fn table_path(&self) -> Option<PathBuf> {
+        let dir = self.save_dir.as_ref()?;
+        Some(dir.join(Self::file_name(
+            &self.board_config,
+            self.obstacles,
+            self.format,
+        )))
+    }
+
     // Computes save path in directory based on game config
-    fn save_path(config: &GameConfig) -> PathBuf {
-        [
-            "connect4_learn",
-            &format!("q_table_{}x{}.json", config.cols, config.rows),
-        ]
-        .iter()
-        .collect()
+    fn save_path(config: &GameConfig, obstacles: usize, format: QTableFormat) -> PathBuf {
+        Path::new(Self::SAVE_DIR).join(Self::file_name(config, obstacles, format))
+    }
+
+    fn file_name(config: &GameConfig, obstacles: usize, format: QTableFormat) -> String {
+        format!(
+            "q_table_{}.{}",
+            Self::table_name(config, obstacles),
+            format.extension()
+        )
+    }
+
+    /// File name part telling the rules a table is learned for apart, e.g. "7x6_connect4" or
+    /// "7x6_connect4_pop_out_red5_obstacles3". Tables of different rules never share a file
+    pub fn table_name(config: &GameConfig, obstacles: usize) -> String {
+        let mut name = format!(
+            "{}x{}_connect{}",
+            config.cols, config.rows, config.connect_length
+        );
+        if config.pop_out {
+            name.push_str("_pop_out");
+        }
+        if let Some(handicap) = config.handicap {
+            name.push_str(&format!(
+                "_{}{}",
+                handicap.player.to_string().to_lowercase(),
+                handicap.connect_length
+            ));
+        }
+        if obstacles > 0 {
+            name.push_str(&format!("_obstacles{}", obstacles));
+        }
+        name
     }
 
     // Convert board to a string representation for the Q-table
-    fn board_to_state(&self, board: &Game) -> String {
+    fn board_to_state(&self, board: &Game) -> (String, StateFrame) {
+        Self::canonical_state(board, self.agent_color)
+    }
+
+    /// Whether the table already holds values for the board as this agent sees it
+    pub fn contains_state(&self, board: &Game) -> bool {
+        self.table().contains_key(&self.board_to_state(board).0)
+    }
+
+    /// Q-table key of a board seen from the given player: the smaller of the keys of the
+    /// board and its mirror image, with how the board's columns map onto it
+    pub fn canonical_state(board: &Game, perspective: Player) -> (String, StateFrame) {
+        let state = Self::state_key(board, perspective);
+        let mirrored = Self::mirror_state(&state, board.config().rows);
+        match mirrored.cmp(&state) {
+            std::cmp::Ordering::Less => (mirrored, StateFrame::Mirrored),
+            std::cmp::Ordering::Equal => (state, StateFrame::Symmetric),
+            std::cmp::Ordering::Greater => (state, StateFrame::AsIs),
+        }
+    }
+
+    // Key of the board mirrored left to right, the same columns in reverse order
+    fn mirror_state(state: &str, rows: usize) -> String {
+        Self::column_segments(state, rows)
+            .into_iter()
+            .rev()
+            .map(|(_, segment)| segment)
+            .collect()
+    }
+
+    // Q-values of the board's columns, None for states not in the table
+    fn board_values(&self, board: &Game) -> Option<Vec<f64>> {
+        let (state, frame) = self.board_to_state(board);
+        let table = self.table();
+        let stored = table.get(&state)?;
+        let cols = self.board_config.cols;
+        Some(
+            (0..cols)
+                .map(|col| {
+                    let stored_col = frame.stored_column(col, cols);
+                    stored.get(stored_col).copied().unwrap_or(0.0)
+                })
+                .collect(),
+        )
+    }
+
+    /// Q-table key of a board seen from the given player, whose pieces are encoded as 'm'
+    fn state_key(board: &Game, perspective: Player) -> String {
         let mut state = String::with_capacity(21);
 
         // For each column, encode the pieces from bottom to top
@@ -89,19 +794,22 @@ impl RLAgent {
             for row in (0..board.config().rows).rev() {
                 if let Some(player) = board.get_cell(row, col) {
                     // agent-centric encoding
-                    if player == self.agent_color {
+                    if player == perspective {
                         col_pieces.push('m');
                     } else {
                         col_pieces.push('o');
                     }
+                } else if board.is_blocked(row, col) {
+                    col_pieces.push('x');
                 }
             }
 
-            // Add column encoding: <length><pieces>
+            // Add column encoding: <length><pieces>, lengths padded to the same width
             state.push_str(&format!(
-                "{}{}",
+                "{:0width$}{}",
                 col_pieces.len(),
-                col_pieces.iter().collect::<String>()
+                col_pieces.iter().collect::<String>(),
+                width = Self::length_width(board.config().rows)
             ));
         }
 
@@ -110,15 +818,9 @@ impl RLAgent {
 
     // Check if a move would result in an immediate win
     fn is_winning_move(&self, board: &Game, col: usize) -> bool {
-        let mut board_copy = board.clone();
-        if board_copy.place(col).is_some() {
-            match board_copy.state() {
-                GameState::Won(_) => true,
-                _ => false,
-            }
-        } else {
-            false
-        }
+        board
+            .with_move(col)
+            .is_some_and(|next| matches!(next.state(), GameState::Won(_)))
     }
 
     // Select the best action based on Q-values
@@ -137,6 +839,16 @@ impl RLAgent {
             }
         }
 
+        let q_values = self.board_values(board).unwrap_or_default();
+        if let Exploration::Softmax { temperature } = self.exploration {
+            return Some(Self::softmax_choice(
+                &q_values,
+                &valid_moves,
+                temperature,
+                &mut rand::rng(),
+            ));
+        }
+
         // With probability epsilon, choose random action (exploration)
         if rand::rng().random::<f64>() < self.epsilon {
             let idx = rand::rng().random_range(0..valid_moves.len());
@@ -144,16 +856,20 @@ impl RLAgent {
         }
 
         // Otherwise, choose best action (exploitation)
-        let state = self.board_to_state(board);
-        let zeroes = vec![0.0; board.config().cols];
-        let q_values = self.q_table.get(&state).unwrap_or(&zeroes);
+        Some(Self::best_move(
+            &q_values,
+            &valid_moves,
+            self.board_config.cols,
+        ))
+    }
 
-        // Find move with highest Q-value
-        // If tie, prefer center columns
+    // Valid move with the highest Q-value, ties go to the column nearest the center.
+    // Missing Q-values count as 0, valid_moves must not be empty
+    fn best_move(q_values: &[f64], valid_moves: &[usize], cols: usize) -> usize {
         let mut best_value = f64::NEG_INFINITY;
         let mut best_moves = Vec::new();
 
-        for &col in &valid_moves {
+        for &col in valid_moves {
             let value = q_values.get(col).unwrap_or(&0.0);
 
             if *value > best_value {
@@ -168,57 +884,314 @@ impl RLAgent {
 
         // If multiple best moves, prefer center columns
         if best_moves.len() > 1 {
-            best_moves.sort_by_key(|&col| (col as i32 - self.board_config.cols as i32 / 2).abs());
+            best_moves.sort_by_key(|&col| (col as i32 - cols as i32 / 2).abs());
         }
 
-        Some(best_moves[0])
+        best_moves[0]
     }
 
-    // Update Q-values based on reward
-    fn update_q_value(&mut self, state: &str, action: usize, reward: f64) {
-        let q_values = self
-            .q_table
+    // Digits of every column length in state_key, as many as the row count has
+    fn length_width(rows: usize) -> usize {
+        rows.to_string().len()
+    }
+
+    // Pieces in each column of an encoded state with the column's <length><pieces> encoding
+    fn column_segments(state: &str, rows: usize) -> Vec<(usize, &str)> {
+        let width = Self::length_width(rows);
+        let mut segments = Vec::new();
+        let mut rest = state;
+        while rest.len() >= width {
+            let length: usize = rest[..width].parse().unwrap_or(0);
+            let end = (width + length).min(rest.len());
+            segments.push((length, &rest[..end]));
+            rest = &rest[end..];
+        }
+        segments
+    }
+
+    // Columns holding values of an encoded state that still have room. The right half of a
+    // symmetric state is left out, its values are stored in the left half
+    fn open_columns(state: &str, rows: usize) -> Vec<usize> {
+        let segments = Self::column_segments(state, rows);
+        let symmetric = segments.iter().eq(segments.iter().rev());
+        let cols = segments.len();
+        segments
+            .iter()
+            .enumerate()
+            .filter(|&(col, _)| !symmetric || col <= cols - 1 - col)
+            .filter(|(_, (length, _))| *length < rows)
+            .map(|(col, _)| col)
+            .collect()
+    }
+
+    /// Best move of every state in the saved Q-table for the config, the same move a
+    /// non-exploring agent picks from the table. Full boards are left out
+    pub fn policy(board_config: GameConfig) -> io::Result<HashMap<String, usize>> {
+        let path = [QTableFormat::Binary, QTableFormat::Json]
+            .into_iter()
+            .map(|format| Self::save_path(&board_config, 0, format))
+            .find(|path| path.exists())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no Q-table saved for {}", board_config),
+                )
+            })?;
+        Self::policy_from(&path, board_config)
+    }
+
+    /// Best move of every state in the Q-table file, which holds a table for the config
+    pub fn policy_from(
+        path: &Path,
+        board_config: GameConfig,
+    ) -> io::Result<HashMap<String, usize>> {
+        Ok(Self::read_table(path)?
+            .into_iter()
+            .filter_map(|(state, q_values)| {
+                let open = Self::open_columns(&state, board_config.rows);
+                if open.is_empty() {
+                    return None;
+                }
+                let col = Self::best_move(&q_values, &open, board_config.cols);
+                Some((state, col))
+            })
+            .collect())
+    }
+
+    // Sample one of the valid moves with probability proportional to exp(Q / temperature).
+    // Missing Q-values count as 0, valid_moves must not be empty
+    fn softmax_choice(
+        q_values: &[f64],
+        valid_moves: &[usize],
+        temperature: f64,
+        rng: &mut impl Rng,
+    ) -> usize {
+        let temperature = temperature.max(f64::EPSILON);
+        let values: Vec<f64> = valid_moves
+            .iter()
+            .map(|&col| q_values.get(col).copied().unwrap_or(0.0))
+            .collect();
+        // Shift by the maximum so the exponentials can't overflow
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = values
+            .iter()
+            .map(|value| ((value - max) / temperature).exp())
+            .collect();
+
+        let mut pick = rng.random::<f64>() * weights.iter().sum::<f64>();
+        for (&col, weight) in valid_moves.iter().zip(&weights) {
+            if pick < *weight {
+                return col;
+            }
+            pick -= weight;
+        }
+        // Rounding left a sliver at the end
+        valid_moves[valid_moves.len() - 1]
+    }
+
+    // Remember the move for learning, in the frame of the state its values are stored under
+    fn record_move(&mut self, board: &Game, action: usize) {
+        let (state, frame) = self.board_to_state(board);
+        let stored_action = frame.stored_column(action, self.board_config.cols);
+        self.move_history.push((state, stored_action));
+
+        // Limit history size
+        if self.move_history.len() > self.max_history {
+            self.move_history.remove(0);
+        }
+    }
+
+    // Reward for the result of a finished game from the player's side, scaled by its length
+    fn final_reward(&self, board: &Game, player: Player) -> Option<f64> {
+        // Calculate final reward based on game outcome
+        let mut reward = match board.state() {
+            GameState::Won(winner) if *winner == player => Self::WIN_REWARD,
+            GameState::Won(_) => Self::LOSS_REWARD, // Double penalty for losses
+            GameState::Draw => Self::DRAW_REWARD,
+            GameState::InProgress => return None,
+        };
+
+        // Apply duration bonus
+        let duration_bonus = self.turn as f64 * Self::DURATION_REWARD;
+
+        if reward < 0.0 {
+            // for losses, reduce penalty based on game length
+            reward += duration_bonus;
+        } else {
+            // for wins, increase reward by a bit
+            reward += duration_bonus * 0.5;
+        }
+        Some(reward)
+    }
+
+    // Highest Q-value among the moves still open in a state, 0 for states not in the table
+    fn best_value(table: &QTable, state: &str, rows: usize) -> f64 {
+        let Some(q_values) = table.get(state) else {
+            return 0.0;
+        };
+        Self::open_columns(state, rows)
+            .into_iter()
+            .map(|col| q_values.get(col).copied().unwrap_or(0.0))
+            .reduce(f64::max)
+            .unwrap_or(0.0)
+    }
+
+    // Move the Q-value of a state-action pair toward a target value
+    fn update_q_value(table: &mut QTable, state: &str, action: usize, target: f64, cols: usize) {
+        let q_values = table
             .entry(state.to_string())
-            .or_insert_with(|| vec![0.0; self.board_config.cols]);
+            .or_insert_with(|| vec![0.0; cols]);
 
         if q_values.len() <= action {
-            q_values.resize(self.board_config.cols, 0.0);
+            q_values.resize(cols, 0.0);
         }
 
         let old_value = q_values[action];
 
         // Q-learning update rule
-        q_values[action] = old_value + Self::LEARNING_RATE * (reward - old_value);
+        q_values[action] = old_value + Self::LEARNING_RATE * (target - old_value);
     }
 
     // Save Q-table to disk
     fn save_q_table(&self) -> io::Result<()> {
-        // Create directory if it doesn't exist
-        if let Some(parent) = Self::save_path(&self.board_config).parent() {
-            fs::create_dir_all(parent)?;
+        // Only save if we have data
+        if self.table().is_empty() {
+            return Ok(());
         }
 
-        // Only save if we have data
-        if self.q_table.is_empty() {
+        // The session keeps the learned table even when it can't be written
+        self.update_cache();
+        let Some(path) = self.table_path() else {
             return Ok(());
+        };
+
+        // Create directory if it doesn't exist
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
 
-        // Serialize and save
-        let serialized = serde_json::to_string(&self)?;
-        fs::write(Self::save_path(&self.board_config), serialized)?;
+        self.write_table(&path)
+    }
 
-        Ok(())
+    // Write games learned since the last write, a failure keeps the table in memory for the
+    // rest of the session. Agents with no save interval are saved by whoever set them up
+    fn flush(&mut self) {
+        if self.unsaved_games == 0 || self.save_interval == 0 {
+            return;
+        }
+        self.unsaved_games = 0;
+        if let Err(e) = self.save_q_table() {
+            let path = self.table_path().unwrap_or_default();
+            self.save_dir = None;
+            self.update_cache();
+            self.warning = Some(format!(
+                "Q-table saving disabled, could not write {}: {}",
+                path.display(),
+                e
+            ));
+        }
+    }
+
+    // Make the agent's table the one for this config, shared rather than copied
+    fn update_cache(&self) {
+        if let Ok(mut cache) = TABLE_CACHE.lock() {
+            cache.insert(
+                (self.board_config, self.obstacles),
+                CachedTable {
+                    format: self.format,
+                    q_table: Arc::clone(&self.q_table),
+                    save_dir: self.save_dir.clone(),
+                },
+            );
+        }
     }
 
     // Load Q-table from disk
     fn load_q_table(&mut self) -> io::Result<()> {
-        let data = fs::read_to_string(Self::save_path(&self.board_config))?;
-        let loaded: RLAgent = serde_json::from_str(&data)?;
-
-        self.q_table = loaded.q_table;
+        let path = Self::save_path(&self.board_config, self.obstacles, self.format);
+        *self.table_mut() = Self::read_table(&path)?;
 
         Ok(())
     }
+
+    // Values shared with the other agents on the config, a panic while holding the lock
+    // leaves them usable
+    fn table(&self) -> RwLockReadGuard<'_, QTable> {
+        self.q_table.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn table_mut(&self) -> RwLockWriteGuard<'_, QTable> {
+        self.q_table.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Serialize the Q-table in the format matching the path's extension
+    fn write_table(&self, path: &Path) -> io::Result<()> {
+        let serialized = match QTableFormat::from_path(path) {
+            QTableFormat::Json => serde_json::to_vec(&TableFile {
+                encoding_version: self.encoding_version,
+                q_table: &self.table(),
+            })?,
+            QTableFormat::Binary => bincode::DefaultOptions::new()
+                .serialize(&(self.encoding_version, &*self.table()))
+                .map_err(io::Error::other)?,
+        };
+        fs::write(path, serialized)
+    }
+
+    // Deserialize a Q-table in the format matching the path's extension
+    fn read_table(path: &Path) -> io::Result<QTable> {
+        let data = fs::read(path)?;
+        let (encoding_version, q_table) = match QTableFormat::from_path(path) {
+            QTableFormat::Json => {
+                let loaded: LoadedTableFile = serde_json::from_slice(&data)?;
+                (loaded.encoding_version, loaded.q_table)
+            }
+            QTableFormat::Binary => bincode::DefaultOptions::new()
+                .deserialize(&data)
+                .map_err(io::Error::other)?,
+        };
+
+        if encoding_version != Self::ENCODING_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} uses state encoding v{} but this version reads v{}, retrain or delete it",
+                    path.display(),
+                    encoding_version,
+                    Self::ENCODING_VERSION
+                ),
+            ));
+        }
+        Ok(q_table)
+    }
+}
+
+// Play a game from the empty board, a side without a move forfeits
+fn play_game(board_config: GameConfig, yellow: &mut dyn Agent, red: &mut dyn Agent) -> Game {
+    let mut board = Game::with_config(board_config);
+    while *board.state() == GameState::InProgress {
+        let player = board.current_player();
+        let action = match player {
+            Player::Yellow => yellow.get_action(&board, None),
+            Player::Red => red.get_action(&board, None),
+        };
+        match action {
+            Some(col) => {
+                board.place(col);
+            }
+            None => {
+                board.forfeit(player);
+            }
+        }
+    }
+    board
+}
+
+// Agents are replaced on every reset and dropped on exit, which writes their last games
+impl Drop for RLAgent {
+    fn drop(&mut self) {
+        self.flush();
+    }
 }
 
 impl Agent for RLAgent {
@@ -230,26 +1203,76 @@ impl Agent for RLAgent {
 
         // Record state-action pair for learning
         if let (Some(action), true) = (action, self.learning) {
-            let state = self.board_to_state(board);
-            self.move_history.push((state, action));
-
-            // Limit history size
-            if self.move_history.len() > Self::MAX_HISTORY {
-                self.move_history.remove(0);
-            }
+            self.record_move(board, action);
         }
 
         action
     }
 
+    fn take_warning(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+
+    fn set_obstacles(&mut self, count: usize) {
+        if count != self.obstacles {
+            self.obstacles = count;
+            self.attach_table();
+        }
+    }
+
     fn get_type(&self) -> String {
+        let exploration = match self.exploration {
+            Exploration::EpsilonGreedy => format!("ε={:.1}", self.epsilon),
+            Exploration::Softmax { temperature } => format!("T={:.1}", temperature),
+        };
         if self.learning {
-            format!("RL (ε={:.1}, Learning)", self.epsilon)
+            format!("RL ({}, Learning)", exploration)
         } else {
-            format!("RL (ε={:.1})", self.epsilon)
+            format!("RL ({})", exploration)
         }
     }
 
+    fn knows_state(&self, board: &Game) -> Option<bool> {
+        Some(self.contains_state(board))
+    }
+
+    fn evaluate_columns(&self, board: &Game) -> Vec<(usize, i32)> {
+        // Q-values in hundredths, columns of unknown states score 0
+        let q_values = self.board_values(board).unwrap_or_default();
+        board
+            .valid_moves()
+            .into_iter()
+            .map(|col| {
+                let value = q_values.get(col).copied().unwrap_or(0.0);
+                (col, (value * 100.0).round() as i32)
+            })
+            .collect()
+    }
+
+    fn explain(&self, board: &Game) -> Vec<String> {
+        let (state, frame) = self.board_to_state(board);
+        let frame = match frame {
+            StateFrame::AsIs => "",
+            StateFrame::Mirrored => " (mirrored)",
+            StateFrame::Symmetric => " (symmetric)",
+        };
+        let mut lines = vec![format!("State key: {}{}", state, frame)];
+        match self.board_values(board) {
+            Some(q_values) => {
+                lines.push(format!(
+                    "Known state ({} states in the table)",
+                    self.table().len()
+                ));
+                for col in board.valid_moves() {
+                    let value = q_values.get(col).copied().unwrap_or(0.0);
+                    lines.push(format!("  column {}: Q = {:.3}", column_label(col), value));
+                }
+            }
+            None => lines.push("Unknown state, all Q-values are 0".to_string()),
+        }
+        lines
+    }
+
     fn is_human(&self) -> bool {
         false
     }
@@ -260,54 +1283,635 @@ impl Agent for RLAgent {
             return;
         }
 
-        // Calculate final reward based on game outcome
-        let mut reward = match board.state() {
-            GameState::Won(winner) if *winner == player => Self::WIN_REWARD,
-            GameState::Won(_) => Self::LOSS_REWARD, // Double penalty for losses
-            GameState::Draw => Self::DRAW_REWARD,
-            GameState::InProgress => return, // Game not over
+        let Some(reward) = self.final_reward(board, player) else {
+            return; // Game not over
         };
 
-        // Apply duration bonus
-        let duration_bonus = self.turn as f64 * Self::DURATION_REWARD;
+        // Learn from the game history, walking back from the final move so each earlier move
+        // bootstraps from the values just updated after it. The last move is credited with
+        // the result, earlier ones only with the discounted best value of the agent's next
+        // state (history is taken out of self so it can be walked without cloning)
+        let move_history = std::mem::take(&mut self.move_history);
+        let (rows, cols) = (self.board_config.rows, self.board_config.cols);
+        let mut table = self.table_mut();
+        let mut next_state: Option<&str> = None;
+        for (state, action) in move_history.iter().rev() {
+            let target = match next_state {
+                Some(next_state) => self.gamma * Self::best_value(&table, next_state, rows),
+                None => reward,
+            };
+            Self::update_q_value(&mut table, state, *action, target, cols);
+            next_state = Some(state);
+        }
+        drop(table);
 
-        if reward < 0.0 {
-            // for losses, reduce penalty based on game length
-            reward += duration_bonus;
-        } else {
-            // for wins, increase reward by a bit
-            reward += duration_bonus * 0.5;
+        // Agents built later share the values right away, the disk write is throttled
+        self.update_cache();
+        self.unsaved_games += 1;
+        if self.save_interval > 0 && self.unsaved_games >= self.save_interval {
+            self.flush();
         }
 
-        // Learn from the game history, back propagation from winning state
-        let history_len = self.move_history.len();
-        for (i, (state, action)) in self.move_history.clone().iter().enumerate().rev() {
-            // Scale reward based on position in history
-            let position_factor = (i + 1) as f64 / history_len as f64;
-            let move_reward = reward * position_factor;
+        // History was already taken above, reset turn counter
+        self.turn = 0;
+    }
+}
 
-            // For losses, make sure mistakes are still penalized
-            let adjusted_reward = if reward < 0.0 && move_reward > -0.5 {
-                -0.5 // Minimum penalty for loss-leading moves
-            } else {
-                move_reward
-            };
+#[cfg(test)]
+mod tests {
+    use rand::{SeedableRng, rngs::StdRng, seq::IndexedRandom};
 
-            // Update Q-value for this state-action pair
-            self.update_q_value(state, *action, adjusted_reward);
+    use super::*;
+
+    // Learning agent with an empty table, as Yellow on a board no other test uses
+    fn learning_agent(save_interval: usize) -> RLAgent {
+        learning_agent_on(GameConfig::custom(5, 6, 4).unwrap(), save_interval)
+    }
+
+    fn learning_agent_on(config: GameConfig, save_interval: usize) -> RLAgent {
+        // Keep tests off the disk, only the session cache is written
+        keep_in_memory(config, 0);
+        let mut agent = RLAgent::untrained(config);
+        agent.save_dir = None;
+        agent.learning = true;
+        agent.gamma = RLAgent::DEFAULT_GAMMA;
+        agent.max_history = RLAgent::full_history(&config);
+        agent.agent_color = Player::Yellow;
+        agent.save_interval = save_interval;
+        agent
+    }
+
+    // Mark the table of a config as kept in memory, agents taking it up later don't write it
+    fn keep_in_memory(config: GameConfig, obstacles: usize) {
+        let mut agent = RLAgent::untrained(config);
+        agent.save_dir = None;
+        agent.obstacles = obstacles;
+        agent.attach_table();
+        agent.update_cache();
+    }
+
+    // Random games from the seed, with the agent learning from Yellow's moves in each
+    fn play_seeded_games(agent: &mut RLAgent, seed: u64, games: usize) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        for _ in 0..games {
+            let mut board = Game::with_config(agent.board_config);
+            while *board.state() == GameState::InProgress {
+                let col = *board.valid_moves().choose(&mut rng).unwrap();
+                if board.current_player() == Player::Yellow {
+                    agent.record_move(&board, col);
+                    agent.turn += 1;
+                }
+                board.place(col);
+            }
+            agent.learn(&board, Player::Yellow);
         }
+    }
 
-        // Save updated Q-table
-        if let Err(e) = self.save_q_table() {
-            eprintln!(
-                "Error saving Q-table at {:?}: {}",
-                Self::save_path(&self.board_config),
-                e
+    #[test]
+    fn throttled_saves_learn_the_same_table_as_saving_every_game() {
+        let mut every_game = learning_agent(1);
+        let mut throttled = learning_agent(RLAgent::SAVE_INTERVAL);
+        play_seeded_games(&mut every_game, 7, 50);
+        play_seeded_games(&mut throttled, 7, 50);
+
+        assert!(!every_game.table().is_empty());
+        assert_eq!(*every_game.table(), *throttled.table());
+        assert_eq!(throttled.unsaved_games, 50 % RLAgent::SAVE_INTERVAL);
+    }
+
+    #[test]
+    fn a_failed_write_keeps_the_table_in_memory_from_then_on() {
+        let config = GameConfig::custom(6, 7, 4).unwrap();
+        // A file where the data directory goes, so it can't be created
+        let blocker = temp_table("blocker");
+        fs::write(&blocker, b"").unwrap();
+        let mut agent = learning_agent_on(config, 1);
+        agent.save_dir = Some(blocker.join("connect4_learn"));
+        learn_game(&mut agent, &[0, 1, 0, 1, 0, 1, 0]);
+
+        let warning = agent.take_warning().unwrap();
+        assert!(
+            warning.starts_with("Q-table saving disabled"),
+            "{}",
+            warning
+        );
+        assert_eq!(agent.save_dir, None);
+        let states = agent.table().len();
+        assert!(states > 0);
+
+        // Neither the agent nor a later one on the table tries again, even once it could
+        fs::remove_file(&blocker).unwrap();
+        let mut later = RLAgent::new(
+            0.0,
+            true,
+            RLAgent::DEFAULT_GAMMA,
+            30,
+            Player::Yellow,
+            config,
+        );
+        later.save_interval = 1;
+        assert_eq!(later.save_dir, None);
+        learn_game(&mut agent, &[2, 1, 2, 1, 2, 1, 2]);
+        learn_game(&mut later, &[3, 1, 3, 1, 3, 1, 3]);
+        assert_eq!(agent.take_warning(), None);
+        assert_eq!(later.take_warning(), None);
+        assert!(!blocker.exists());
+        assert!(later.table().len() > states);
+    }
+
+    #[test]
+    fn softmax_picks_follow_the_q_values_and_cool_down_to_the_best_move() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let q_values = [0.5, -1.0, 2.0, 1.0, 0.0];
+        let valid_moves = [0, 1, 2, 3, 4];
+        let picks = |temperature, rng: &mut StdRng| {
+            let mut counts = [0; 5];
+            for _ in 0..20_000 {
+                counts[RLAgent::softmax_choice(&q_values, &valid_moves, temperature, rng)] += 1;
+            }
+            counts
+        };
+
+        // Columns are picked more often the higher their value
+        let counts = picks(1.0, &mut rng);
+        for pair in [2, 3, 0, 4, 1].windows(2) {
+            assert!(counts[pair[0]] > counts[pair[1]], "{:?}", counts);
+        }
+        // Hot enough, the values hardly matter
+        let counts = picks(1000.0, &mut rng);
+        assert!(counts.iter().all(|&count| (3_600..4_400).contains(&count)));
+
+        // Cooled down to nothing it only plays the best move
+        let best = RLAgent::best_move(&q_values, &valid_moves, 5);
+        for temperature in [1e-3, 0.0] {
+            assert_eq!(picks(temperature, &mut rng)[best], 20_000);
+        }
+        let mut agent = learning_agent(0);
+        agent.exploration = Exploration::Softmax { temperature: 0.0 };
+        let board = Game::from_notation(agent.board_config, "12").unwrap();
+        let (state, _) = agent.board_to_state(&board);
+        agent.table_mut().insert(state, q_values.to_vec());
+        let values = agent.board_values(&board).unwrap();
+        let best = RLAgent::best_move(&values, &board.valid_moves(), 5);
+        for _ in 0..100 {
+            assert_eq!(agent.get_action(&board, None), Some(best));
+        }
+    }
+
+    #[test]
+    fn learning_without_cloning_matches_a_cloned_history_walk() {
+        let mut agent = learning_agent(0);
+        let mut reference = learning_agent(0);
+        let mut rng = StdRng::seed_from_u64(11);
+        for _ in 0..50 {
+            let mut board = Game::with_config(agent.board_config);
+            while *board.state() == GameState::InProgress {
+                let col = *board.valid_moves().choose(&mut rng).unwrap();
+                if board.current_player() == Player::Yellow {
+                    agent.record_move(&board, col);
+                    reference.record_move(&board, col);
+                    agent.turn += 1;
+                    reference.turn += 1;
+                }
+                board.place(col);
+            }
+            agent.learn(&board, Player::Yellow);
+
+            // The same updates, indexing into a copy of the history
+            let reward = reference.final_reward(&board, Player::Yellow).unwrap();
+            let history = reference.move_history.clone();
+            let (rows, cols) = (reference.board_config.rows, reference.board_config.cols);
+            let mut table = reference.table_mut();
+            for i in (0..history.len()).rev() {
+                let target = match history.get(i + 1) {
+                    Some((next_state, _)) => {
+                        reference.gamma * RLAgent::best_value(&table, next_state, rows)
+                    }
+                    None => reward,
+                };
+                RLAgent::update_q_value(&mut table, &history[i].0, history[i].1, target, cols);
+            }
+            drop(table);
+            reference.move_history.clear();
+            reference.turn = 0;
+        }
+
+        assert_eq!(*agent.table(), *reference.table());
+    }
+
+    // Play the columns as a game, the agent learning from Yellow's moves
+    fn learn_game(agent: &mut RLAgent, moves: &[usize]) -> Game {
+        let mut board = Game::with_config(agent.board_config);
+        for &col in moves {
+            if board.current_player() == Player::Yellow {
+                agent.record_move(&board, col);
+                agent.turn += 1;
+            }
+            board.place(col);
+        }
+        agent.learn(&board, Player::Yellow);
+        board
+    }
+
+    #[test]
+    fn mirrored_openings_train_the_same_states() {
+        let config = GameConfig::custom(4, 5, 3).unwrap();
+        let cols = config.cols;
+        // Yellow wins along the bottom row, once from the left edge and once from the right
+        let moves = [0, 0, 1, 1, 2];
+        let mirrored: Vec<usize> = moves.iter().map(|&col| cols - 1 - col).collect();
+
+        let mut left = learning_agent_on(config, 0);
+        let mut right = learning_agent_on(config, 0);
+        let board = learn_game(&mut left, &moves);
+        let mirrored_board = learn_game(&mut right, &mirrored);
+        assert_eq!(*board.state(), GameState::Won(Player::Yellow));
+        assert_eq!(*mirrored_board.state(), GameState::Won(Player::Yellow));
+
+        assert!(!left.table().is_empty());
+        assert_eq!(*left.table(), *right.table());
+
+        // Mirror positions share one key, the frame tells their columns apart
+        let (left_state, left_frame) =
+            left.board_to_state(&Game::from_notation(config, "11").unwrap());
+        let (right_state, right_frame) =
+            right.board_to_state(&Game::from_notation(config, "55").unwrap());
+        assert_eq!(left_state, right_state);
+        assert!(left.table().contains_key(&left_state));
+        assert_eq!(
+            (left_frame, right_frame),
+            (StateFrame::Mirrored, StateFrame::AsIs)
+        );
+        let (_, frame) = left.board_to_state(&Game::with_config(config));
+        assert_eq!(frame, StateFrame::Symmetric);
+    }
+
+    #[test]
+    fn a_position_and_its_mirror_share_a_key() {
+        let config = GameConfig::default();
+        for notation in ["", "1", "12", "4453", "7716254"] {
+            let board = Game::from_notation(config, notation).unwrap();
+            let mirrored = board.mirrored();
+            for player in [Player::Yellow, Player::Red] {
+                let (state, frame) = RLAgent::canonical_state(&board, player);
+                let (mirrored_state, mirrored_frame) = RLAgent::canonical_state(&mirrored, player);
+                assert_eq!(state, mirrored_state, "{}", notation);
+                // Each column's values are stored at its mirror column's index
+                for col in 0..config.cols {
+                    assert_eq!(
+                        frame.stored_column(col, config.cols),
+                        mirrored_frame.stored_column(config.cols - 1 - col, config.cols)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn symmetric_states_only_list_the_left_half() {
+        assert_eq!(RLAgent::open_columns("00000", 4), vec![0, 1, 2]);
+        assert_eq!(RLAgent::open_columns("1m0000", 4), vec![0, 1, 2, 3, 4]);
+        assert_eq!(RLAgent::open_columns("4mmmm0000", 4), vec![1, 2, 3, 4]);
+        assert_eq!(RLAgent::mirror_state("1m02mo0", 4), "02mo01m");
+        // Lengths on tall boards take two digits each
+        assert_eq!(RLAgent::mirror_state("0001m", 10), "01m00");
+        assert_eq!(RLAgent::open_columns("0001m", 10), vec![0, 1]);
+    }
+
+    #[test]
+    fn agents_on_a_config_share_one_table() {
+        let config = GameConfig::custom(6, 5, 4).unwrap();
+        keep_in_memory(config, 0);
+        let mut first = RLAgent::new(
+            0.0,
+            true,
+            RLAgent::DEFAULT_GAMMA,
+            30,
+            Player::Yellow,
+            config,
+        );
+        learn_game(&mut first, &[0, 1, 0, 1, 0, 1, 0]);
+        assert!(!first.table().is_empty());
+
+        // A later agent sees the values without a copy, and its updates reach the first one
+        let mut second = RLAgent::new(
+            0.0,
+            true,
+            RLAgent::DEFAULT_GAMMA,
+            30,
+            Player::Yellow,
+            config,
+        );
+        assert!(Arc::ptr_eq(&first.q_table, &second.q_table));
+        let states = first.table().len();
+        learn_game(&mut second, &[2, 0, 2, 0, 2, 0, 2]);
+        assert!(first.table().len() > states);
+
+        let other_config = GameConfig::custom(6, 5, 3).unwrap();
+        keep_in_memory(other_config, 0);
+        let other = RLAgent::new(
+            0.0,
+            false,
+            RLAgent::DEFAULT_GAMMA,
+            30,
+            Player::Yellow,
+            other_config,
+        );
+        assert!(!Arc::ptr_eq(&first.q_table, &other.q_table));
+    }
+
+    #[test]
+    fn parallel_learners_keep_every_update() {
+        const THREADS: usize = 3;
+        const GAMES: i32 = 20;
+        let config = GameConfig::custom(5, 5, 4).unwrap();
+        let table = SharedTable::default();
+
+        // Each thread wins down its own column against Red in column 4, so the position
+        // before its winning move is seen by no other thread
+        let won_from = |col: usize| Game::from_notation(config, &format!("{0}4{0}4{0}4", col + 1));
+        thread::scope(|scope| {
+            for col in 0..THREADS {
+                let table = &table;
+                scope.spawn(move || {
+                    let mut agent = learning_agent_on(config, 0);
+                    agent.q_table = Arc::clone(table);
+                    for _ in 0..GAMES {
+                        learn_game(&mut agent, &[col, 3, col, 3, col, 3, col]);
+                    }
+                });
+            }
+        });
+
+        let mut checker = learning_agent_on(config, 0);
+        checker.q_table = table;
+        for col in 0..THREADS {
+            let board = won_from(col).unwrap();
+            assert!(
+                checker.contains_state(&board),
+                "no states from thread {}",
+                col
             );
+
+            // Every game moved the winning value the same step toward the reward
+            let mut finished = board.clone();
+            finished.place(col);
+            checker.turn = 4;
+            let reward = checker.final_reward(&finished, Player::Yellow).unwrap();
+            let expected = reward * (1.0 - (1.0 - RLAgent::LEARNING_RATE).powi(GAMES));
+            let value = checker.board_values(&board).unwrap()[col];
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
         }
 
-        // Clear history and reset turn counter
-        self.move_history.clear();
-        self.turn = 0;
+        // The trainer plays exactly the games asked for, whatever the split
+        let mut played = 0;
+        let mut last = TrainingReport::default();
+        let plan = TrainingPlan {
+            games: 50,
+            threads: 4,
+            report_every: 7,
+            baseline: None,
+            evaluation_games: 0,
+            league: Vec::new(),
+        };
+        let trained = GameConfig::custom(4, 6, 3).unwrap();
+        keep_in_memory(trained, 0);
+        RLAgent::train_self_play(trained, &plan, |report| {
+            played += report.window();
+            last.games = report.games;
+            last.states = report.states;
+        })
+        .unwrap();
+        assert_eq!((played, last.games), (50, 50));
+        assert!(last.states > 0);
+    }
+
+    #[test]
+    fn every_variant_of_the_rules_gets_its_own_table() {
+        let standard = GameConfig::default();
+        assert_eq!(RLAgent::table_name(&standard, 0), "7x6_connect4");
+        let handicap = Some(crate::game::Handicap {
+            player: Player::Red,
+            connect_length: 5,
+        });
+        let names: HashSet<String> = [
+            (standard, 0),
+            (standard, 3),
+            (
+                GameConfig {
+                    connect_length: 5,
+                    ..standard
+                },
+                0,
+            ),
+            (
+                GameConfig {
+                    pop_out: true,
+                    ..standard
+                },
+                0,
+            ),
+            (
+                GameConfig {
+                    handicap,
+                    ..standard
+                },
+                0,
+            ),
+        ]
+        .iter()
+        .map(|(config, obstacles)| RLAgent::table_name(config, *obstacles))
+        .collect();
+        assert_eq!(names.len(), 5);
+
+        // Obstacle games on a config learn apart from the plain ones
+        let config = GameConfig::custom(6, 6, 4).unwrap();
+        keep_in_memory(config, 0);
+        keep_in_memory(config, 3);
+        let new_agent = || {
+            RLAgent::new(
+                0.0,
+                true,
+                RLAgent::DEFAULT_GAMMA,
+                30,
+                Player::Yellow,
+                config,
+            )
+        };
+        let plain = new_agent();
+        let mut blocked = new_agent();
+        blocked.set_obstacles(3);
+        assert!(!Arc::ptr_eq(&plain.q_table, &blocked.q_table));
+        let mut also_blocked = new_agent();
+        also_blocked.set_obstacles(3);
+        assert!(Arc::ptr_eq(&blocked.q_table, &also_blocked.q_table));
+    }
+
+    #[test]
+    fn the_best_table_is_the_one_from_the_peak_win_rate() {
+        let config = GameConfig::custom(4, 5, 3).unwrap();
+        let mut best = BestTable::new(None);
+
+        // Evaluations that improve and then fall off again
+        let win_rates = [20.0, 45.0, 70.0, 55.0, 30.0];
+        let kept: Vec<bool> = win_rates
+            .iter()
+            .enumerate()
+            .map(|(evaluation, &win_rate)| {
+                let snapshot = RLAgent::untrained(config);
+                snapshot
+                    .table_mut()
+                    .insert(format!("evaluation {}", evaluation), vec![win_rate]);
+                best.offer(win_rate, &snapshot).unwrap()
+            })
+            .collect();
+
+        assert_eq!(kept, [true, true, true, false, false]);
+        let (win_rate, table) = best.best.unwrap();
+        assert_eq!(win_rate, 70.0);
+        let table = table.read().unwrap();
+        assert_eq!(table.keys().collect::<Vec<_>>(), ["evaluation 2"]);
+    }
+
+    #[test]
+    fn league_training_faces_every_opponent_and_grows_the_table() {
+        let config = GameConfig::custom(5, 4, 3).unwrap();
+        keep_in_memory(config, 0);
+        let league = vec![
+            (LeagueOpponent::Agent(Agents::Random), 1),
+            (LeagueOpponent::Agent(Agents::Greedy), 1),
+            (LeagueOpponent::Agent(Agents::Minimax(1)), 2),
+            (LeagueOpponent::PastSelf, 1),
+        ];
+        let plan = TrainingPlan {
+            games: 200,
+            threads: 2,
+            report_every: 50,
+            baseline: None,
+            evaluation_games: 0,
+            league: league.clone(),
+        };
+        let mut faced = vec![0; league.len()];
+        let mut states = Vec::new();
+        RLAgent::train_self_play(config, &plan, |report| {
+            for (total, games) in faced.iter_mut().zip(&report.league_games) {
+                *total += games;
+            }
+            states.push(report.states);
+        })
+        .unwrap();
+
+        assert_eq!(faced.iter().sum::<usize>(), 200);
+        for ((opponent, _), games) in league.iter().zip(&faced) {
+            assert!(*games > 0, "never played {}", opponent);
+        }
+        assert!(states.first() < states.last(), "{:?}", states);
+    }
+
+    // File in the temp directory for a table test, unique to the test and process
+    fn temp_table(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("q_table_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn tables_of_another_encoding_version_are_refused() {
+        let table: QTable = [("0000".to_string(), vec![0.5, 0.0, -0.5, 0.0])].into();
+        let json = temp_table("old.json");
+        let unversioned = temp_table("unversioned.json");
+        let binary = temp_table("old.bin");
+        fs::write(
+            &json,
+            serde_json::to_vec(&TableFile {
+                encoding_version: RLAgent::ENCODING_VERSION - 1,
+                q_table: &table,
+            })
+            .unwrap(),
+        )
+        .unwrap();
+        // Written before versioning, so in the first encoding
+        fs::write(&unversioned, serde_json::to_vec(&table).unwrap()).unwrap();
+        fs::write(
+            &binary,
+            bincode::DefaultOptions::new()
+                .serialize(&(RLAgent::ENCODING_VERSION + 1, &table))
+                .unwrap(),
+        )
+        .unwrap();
+
+        for path in [&json, &unversioned, &binary] {
+            let err = RLAgent::read_table(path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData, "{}", path.display());
+            assert!(err.to_string().contains("retrain"), "{}", err);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn tables_reload_unchanged_in_either_format() {
+        let mut agent = learning_agent(0);
+        play_seeded_games(&mut agent, 3, 30);
+        let saved = agent.table().clone();
+        assert!(!saved.is_empty());
+
+        let binary = temp_table("round_trip.bin");
+        agent.write_table(&binary).unwrap();
+        assert_eq!(RLAgent::read_table(&binary).unwrap(), saved);
+
+        // JSON to binary and back again loses nothing
+        let json = temp_table("round_trip.json");
+        let converted = temp_table("converted.bin");
+        agent.write_table(&json).unwrap();
+        assert_eq!(
+            RLAgent::convert_q_table(&json, &converted).unwrap(),
+            saved.len()
+        );
+        RLAgent::convert_q_table(&converted, &json).unwrap();
+        assert_eq!(RLAgent::read_table(&json).unwrap(), saved);
+
+        for path in [binary, json, converted] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn a_bootstrapped_agent_beats_random_moves_every_game() {
+        // Small is won by the first player from every opening, so the agent plays Yellow and
+        // has to win each game however the random side answers
+        let config = crate::game::GameConfigPreset::Small.into_config();
+        let mut agent = RLAgent::solved(config);
+        agent.agent_color = Player::Yellow;
+        let mut rng = StdRng::seed_from_u64(5);
+        for game in 0..20 {
+            let mut board = Game::with_config(config);
+            while *board.state() == GameState::InProgress {
+                let col = match board.current_player() {
+                    Player::Yellow => agent.get_action(&board, None).unwrap(),
+                    Player::Red => *board.valid_moves().choose(&mut rng).unwrap(),
+                };
+                board.place(col);
+            }
+            assert_eq!(
+                *board.state(),
+                GameState::Won(Player::Yellow),
+                "game {}",
+                game
+            );
+        }
+    }
+
+    #[test]
+    fn a_cached_table_matches_the_same_table_loaded_from_disk() {
+        let config = GameConfig::custom(5, 7, 4).unwrap();
+        let mut learner = learning_agent_on(config, 0);
+        play_seeded_games(&mut learner, 13, 30);
+        let path = temp_table("cached.bin");
+        learner.write_table(&path).unwrap();
+
+        // A later agent on the config takes up the cached table instead of reading a file
+        let cached = RLAgent::new(0.0, false, RLAgent::DEFAULT_GAMMA, 30, Player::Red, config);
+        assert!(Arc::ptr_eq(&cached.q_table, &learner.q_table));
+        assert!(!cached.table().is_empty());
+        assert_eq!(*cached.table(), RLAgent::read_table(&path).unwrap());
+        fs::remove_file(path).unwrap();
     }
 }
@@ -0,0 +1,285 @@
+use crossterm::event::Event;
+
+use crate::{
+    agent::{Agent, center_opening},
+    game::{Game, GameConfig, GameState, Player},
+    minimax_agent::MinimaxAgent,
+};
+
+/// AI agent encoding classic Connect 4 strategy rules on top of a shallow search.
+/// Threats on odd rows (counted from the bottom) favor the first player and threats on even rows
+/// favor the second player, who can claim even squares by answering on top of the opponent
+/// (claimeven). The rules only hold on the standard board, other configs fall back to minimax.
+pub struct RulesAgent {
+    fallback: MinimaxAgent,
+    // Root score of the last chosen move
+    last_score: Option<i32>,
+}
+
+impl RulesAgent {
+    const SEARCH_DEPTH: usize = 4;
+    const WIN_SCORE: i32 = 1000;
+    const INFINITY: i32 = Self::WIN_SCORE * 2;
+    // Threat on a row of its owner's parity, which zugzwang will eventually force
+    const GOOD_THREAT: i32 = 40;
+    // Threat on the wrong parity, the opponent can usually refute it
+    const BAD_THREAT: i32 = 10;
+    const CENTER_PIECE: i32 = 3;
+    // Second player landing on an even row keeps claimeven intact
+    const CLAIMEVEN_BONUS: i32 = 2;
+
+    pub fn new() -> Self {
+        RulesAgent {
+            fallback: MinimaxAgent::new(5),
+            last_score: None,
+        }
+    }
+
+    /// The parity rules only hold on standard connect 4: 7x6, four in a row for both players,
+    /// no Pop Out and no blocked cells
+    fn rules_apply(board: &Game) -> bool {
+        *board.config() == GameConfig::default() && !board.has_obstacles()
+    }
+
+    /// Valid moves ordered center first, which improves pruning and breaks ties toward the center
+    fn ordered_moves(board: &Game) -> Vec<usize> {
+        let center = board.config().cols as i32 / 2;
+        let mut moves = board.valid_moves();
+        moves.sort_by_key(|&col| (col as i32 - center).abs());
+        moves
+    }
+
+    /// Negamax with alpha-beta pruning, scores are from the side to move's perspective
+    fn negamax(&self, board: &Game, depth: usize, alpha: i32, beta: i32) -> i32 {
+        if depth == 0 {
+            return self.evaluate(board, board.current_player());
+        }
+
+        let mut best = -Self::INFINITY;
+        let mut alpha = alpha;
+        for col in Self::ordered_moves(board) {
+            let Some(board_copy) = board.with_move(col) else {
+                continue;
+            };
+            let value = match board_copy.state() {
+                // Prefer faster wins
+                GameState::Won(_) => Self::WIN_SCORE + depth as i32,
+                GameState::Draw => 0,
+                GameState::InProgress => -self.negamax(&board_copy, depth - 1, -beta, -alpha),
+            };
+            best = best.max(value);
+            alpha = alpha.max(value);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        // No valid moves, treat as a draw
+        if best == -Self::INFINITY { 0 } else { best }
+    }
+
+    /// Static evaluation based on threat parity for the given player
+    fn evaluate(&self, board: &Game, player: Player) -> i32 {
+        let first_player = board.first_player();
+        let rows = board.config().rows;
+        let mut score = 0;
+
+        for (owner, sign) in [(player, 1), (player.opponent(), -1)] {
+            for (row, _) in board.threats(owner) {
+                let odd_row = (rows - row) % 2 == 1;
+                let good_parity = odd_row == (owner == first_player);
+                score += sign
+                    * if good_parity {
+                        Self::GOOD_THREAT
+                    } else {
+                        Self::BAD_THREAT
+                    };
+            }
+        }
+
+        // Center pieces take part in the most lines
+        let center_col = board.config().cols / 2;
+        for row in 0..rows {
+            match board.get_cell(row, center_col) {
+                Some(p) if p == player => score += Self::CENTER_PIECE,
+                Some(_) => score -= Self::CENTER_PIECE,
+                None => {}
+            }
+        }
+
+        score
+    }
+}
+
+impl Agent for RulesAgent {
+    fn get_action(&mut self, board: &Game, event: Option<Event>) -> Option<usize> {
+        if !Self::rules_apply(board) {
+            let action = self.fallback.get_action(board, event);
+            self.last_score = self.fallback.last_evaluation();
+            return action;
+        }
+
+        if let Some(col) = center_opening(board) {
+            self.last_score = None;
+            return Some(col);
+        }
+
+        let current_player = board.current_player();
+        let second_player = board.first_player() != current_player;
+        let rows = board.config().rows;
+
+        let mut best_col = None;
+        let mut best_value = i32::MIN;
+        for col in Self::ordered_moves(board) {
+            let Some(board_copy) = board.with_move(col) else {
+                continue;
+            };
+            let mut value = match board_copy.state() {
+                GameState::Won(_) => {
+                    self.last_score = Some(Self::WIN_SCORE);
+                    return Some(col);
+                }
+                GameState::Draw => 0,
+                GameState::InProgress => -self.negamax(
+                    &board_copy,
+                    Self::SEARCH_DEPTH - 1,
+                    -Self::INFINITY,
+                    Self::INFINITY,
+                ),
+            };
+
+            if second_player
+                && board
+                    .landing_row(col)
+                    .is_some_and(|row| (rows - row).is_multiple_of(2))
+            {
+                value += Self::CLAIMEVEN_BONUS;
+            }
+
+            if value > best_value {
+                best_value = value;
+                best_col = Some(col);
+            }
+        }
+
+        self.last_score = best_col.map(|_| best_value);
+        best_col
+    }
+
+    fn get_type(&self) -> String {
+        "Rules-based".to_string()
+    }
+
+    fn last_evaluation(&self) -> Option<i32> {
+        self.last_score
+    }
+
+    fn set_branch_cap(&mut self, cap: Option<usize>) {
+        // Only the minimax fallback for non-standard boards searches wide enough to need it
+        self.fallback.set_branch_cap(cap);
+    }
+
+    fn is_human(&self) -> bool {
+        false
+    }
+
+    fn learn(&mut self, _board: &Game, _player: Player) {
+        // No learning for rules-based agent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        evaluator::Evaluator,
+        game::{GameConfigPreset, Handicap},
+        solver::Solver,
+    };
+
+    // Scores every position at the horizon the same, leaving a search that only sees wins
+    struct Flat;
+
+    impl Evaluator for Flat {
+        fn evaluate(&self, _board: &Game, _root_player: Player) -> i32 {
+            0
+        }
+    }
+
+    // The move a plain search of the same depth plays, with the scores it gives every column
+    fn plain_search(board: &Game) -> (usize, Vec<(usize, i32)>) {
+        let plain = MinimaxAgent::with_evaluator(RulesAgent::SEARCH_DEPTH, Box::new(Flat));
+        let scores = plain.evaluate_columns(board);
+        let col = RulesAgent::ordered_moves(board)[0];
+        (col, scores)
+    }
+
+    #[test]
+    fn parity_picks_the_only_winning_column_a_plain_search_cannot_tell_apart() {
+        // The first player to move, then the second, so both the odd threats of the first
+        // player and claimeven of the second are covered
+        let positions = [
+            ("45576561711774261557667146", Player::Yellow, 1),
+            ("22576572622342366677745", Player::Red, 4),
+        ];
+        for (notation, to_move, winning) in positions {
+            let board = Game::from_notation(GameConfig::default(), notation).unwrap();
+            assert_eq!(board.current_player(), to_move);
+
+            // The plain search sees nothing within its depth and falls back on the center
+            let (plain_col, scores) = plain_search(&board);
+            assert!(scores.iter().all(|&(_, score)| score == 0), "{:?}", scores);
+            assert_ne!(plain_col, winning);
+
+            // Only one column wins with perfect play, and the rules find it
+            let values = Solver::new().move_values(&board);
+            let winners: Vec<usize> = values
+                .iter()
+                .filter(|&&(_, value)| value == 1)
+                .map(|&(col, _)| col)
+                .collect();
+            assert_eq!(winners, [winning], "{}", notation);
+            assert_eq!(
+                RulesAgent::new().get_action(&board, None),
+                Some(winning),
+                "{}",
+                notation
+            );
+        }
+    }
+
+    #[test]
+    fn other_boards_are_left_to_the_fallback() {
+        let standard = GameConfig::default();
+        let boards = [
+            Game::from_notation(GameConfigPreset::Large.into_config(), "4455").unwrap(),
+            Game::from_notation(GameConfigPreset::PopOut.into_config(), "4455").unwrap(),
+            Game::from_notation(
+                GameConfig {
+                    handicap: Some(Handicap {
+                        player: Player::Red,
+                        connect_length: 3,
+                    }),
+                    ..standard
+                },
+                "4455",
+            )
+            .unwrap(),
+            Game::with_obstacles(standard, &[(5, 0)])
+                .play_notation("4455")
+                .unwrap(),
+        ];
+        for board in boards {
+            let mut rules = RulesAgent::new();
+            let mut fallback = MinimaxAgent::new(5);
+            assert_eq!(
+                rules.get_action(&board, None),
+                fallback.get_action(&board, None),
+                "{}",
+                board.config()
+            );
+            assert_eq!(rules.last_evaluation(), fallback.last_evaluation());
+        }
+        assert!(RulesAgent::rules_apply(&Game::new()));
+    }
+}
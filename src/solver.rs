@@ -0,0 +1,61 @@
+use std::collections::HashMap;
+
+use crate::game::{Game, GameState};
+
+/// Perfect-play solver using exhaustive memoized search.
+/// Only practical for small boards, the state space explodes on the larger presets.
+pub struct Solver {
+    // Position key -> value for the side to move
+    cache: HashMap<String, i32>,
+}
+
+impl Solver {
+    pub fn new() -> Self {
+        Solver {
+            cache: HashMap::new(),
+        }
+    }
+
+    /// Value of the position for the side to move: 1 win, 0 draw, -1 loss
+    pub fn solve(&mut self, board: &Game) -> i32 {
+        match board.state() {
+            GameState::Won(p) if *p == board.current_player() => return 1,
+            GameState::Won(_) => return -1,
+            GameState::Draw => return 0,
+            GameState::InProgress => {}
+        }
+
+        let key = board.to_key();
+        if let Some(&value) = self.cache.get(&key) {
+            return value;
+        }
+
+        let value = self
+            .move_values(board)
+            .into_iter()
+            .map(|(_, value)| value)
+            .max()
+            .unwrap_or(0);
+
+        self.cache.insert(key, value);
+        value
+    }
+
+    /// Value of each valid move for the side to move
+    pub fn move_values(&mut self, board: &Game) -> Vec<(usize, i32)> {
+        board
+            .valid_moves()
+            .into_iter()
+            .filter_map(|col| {
+                let board_copy = board.with_move(col)?;
+                let value = match board_copy.state() {
+                    GameState::Won(_) => 1,
+                    GameState::Draw => 0,
+                    // Opponent is to move in the resulting position
+                    GameState::InProgress => -self.solve(&board_copy),
+                };
+                Some((col, value))
+            })
+            .collect()
+    }
+}